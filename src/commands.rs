@@ -0,0 +1,98 @@
+use std::io;
+
+use crate::services::{
+    env_settings::EnvSettings,
+    post_db_service::{PostDbService, PostFilter, Visibility},
+    search_db_service::SearchDbService,
+    social_db_service::{SocialDbService, TimelinePost},
+    user_db_service::UserDbService,
+};
+
+/// Runs a maintenance command given on the command line, e.g. `cargo run -- reindex`.
+pub fn run(command: &str, env_settings: &EnvSettings) -> io::Result<()> {
+    match command {
+        "reindex" => reindex(env_settings),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown command: {}, available commands: reindex", command),
+        )),
+    }
+}
+
+/// Rebuilds the full-text search index of every user database, and the site-wide
+/// search index and the home timelines from the public posts found in them.
+fn reindex(env_settings: &EnvSettings) -> io::Result<()> {
+    let user_db_service = UserDbService::connect(&env_settings.db_collection_path)
+        .map_err(|err| io::Error::other(format!("{:?}", err)))?;
+    let mut search_db_service = SearchDbService::connect(&env_settings.db_collection_path)
+        .map_err(|err| io::Error::other(format!("{:?}", err)))?;
+    search_db_service
+        .clear()
+        .map_err(|err| io::Error::other(format!("{:?}", err)))?;
+    let social_db_service = SocialDbService::connect(&env_settings.db_collection_path)
+        .map_err(|err| io::Error::other(format!("{:?}", err)))?;
+    social_db_service
+        .clear_timelines()
+        .map_err(|err| io::Error::other(format!("{:?}", err)))?;
+
+    let public_posts = PostFilter {
+        visibilities: vec![Visibility::Public],
+        exclude_hidden: true,
+        ..PostFilter::default()
+    };
+    let mut failed = 0;
+    let user_uuids = PostDbService::user_uuids(&env_settings.db_collection_path)?;
+
+    for user_uuid in &user_uuids {
+        let result = PostDbService::connect(&env_settings.db_collection_path, user_uuid).and_then(
+            |post_db_service| {
+                post_db_service.reindex()?;
+                post_db_service.get_posts(&public_posts, u32::MAX, 0)
+            },
+        );
+        let posts = match result {
+            Ok(posts) => posts,
+            Err(err) => {
+                log::error!("reindex failed for user {}: {:?}", user_uuid, err);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let author = match user_db_service.get_user_from_uuid(user_uuid) {
+            Ok(author) => author,
+            Err(err) => {
+                log::error!("reindex, no user found for {}: {:?}", user_uuid, err);
+                failed += 1;
+                continue;
+            }
+        };
+        for post in &posts {
+            if let Err(err) = search_db_service.index_post(user_uuid, &author.display_name, post) {
+                log::error!("reindex failed for post {}: {:?}", post.uuid, err);
+            }
+            let timeline_post = TimelinePost {
+                post_uuid: post.uuid.clone(),
+                author_uuid: user_uuid.clone(),
+                created_at: post.created_at,
+            };
+            if let Err(err) = social_db_service.fan_out_post(&timeline_post) {
+                log::error!(
+                    "reindex, timeline fan-out failed for post {}: {:?}",
+                    post.uuid,
+                    err
+                );
+            }
+        }
+    }
+
+    println!(
+        "reindexed {} of {} user databases",
+        user_uuids.len() - failed,
+        user_uuids.len()
+    );
+    if failed > 0 {
+        return Err(io::Error::other("reindex failed"));
+    }
+    return Ok(());
+}
@@ -0,0 +1,289 @@
+use std::sync::Mutex;
+
+use actix_web::{
+    get, http::header, http::StatusCode, web, HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use chrono::DateTime;
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    analytics_db_service::{today, AnalyticsDbError, AnalyticsDbService, NameViews, PostView},
+    env_settings::EnvSettings,
+    post_db_service::{PostDbError, PostDbService},
+};
+
+use super::{error_response::AppErrorResponse, user_auth_token_extractor::UserAuthentication};
+
+const DEFAULT_ANALYTICS_DAYS: u32 = 30;
+const MAX_ANALYTICS_DAYS: u32 = 365;
+const TOP_POSTS_LIMIT: u32 = 10;
+const TOP_REFERRERS_LIMIT: u32 = 10;
+
+#[derive(Serialize, Debug, Display)]
+pub enum AnalyticsError {
+    GenericError = 21301,
+    PostNotFound,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AnalyticsQuery {
+    /// length of the period ending today, 30 days unless given, at most 365
+    days: Option<u32>,
+    /// limits the numbers to one own post
+    post_uuid: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AnalyticsResponse {
+    /// first day of the period, `YYYY-MM-DD` in UTC
+    from: String,
+    to: String,
+    total_views: i64,
+    /// every day of the period, oldest first
+    daily: Vec<DailyViewsResponse>,
+    top_posts: Vec<TopPostResponse>,
+    /// hosts of the sites readers came from, direct visits left out
+    referrers: Vec<ReferrerResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DailyViewsResponse {
+    date: String,
+    views: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TopPostResponse {
+    post_uuid: String,
+    title: String,
+    views: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReferrerResponse {
+    referrer: String,
+    views: i64,
+}
+
+impl ResponseError for AnalyticsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AnalyticsError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            AnalyticsError::PostNotFound => StatusCode::NOT_FOUND,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            AnalyticsError::GenericError => HttpResponse::build(status)
+                .json(AppErrorResponse::from(AnalyticsError::GenericError)),
+            AnalyticsError::PostNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(AnalyticsError::PostNotFound)),
+        }
+    }
+}
+
+impl From<AnalyticsDbError> for AnalyticsError {
+    fn from(_value: AnalyticsDbError) -> Self {
+        AnalyticsError::GenericError
+    }
+}
+
+impl From<PostDbError> for AnalyticsError {
+    fn from(value: PostDbError) -> Self {
+        match value {
+            PostDbError::PostNotFound => AnalyticsError::PostNotFound,
+            _ => AnalyticsError::GenericError,
+        }
+    }
+}
+
+impl From<NameViews> for ReferrerResponse {
+    fn from(value: NameViews) -> Self {
+        Self {
+            referrer: value.name,
+            views: value.views,
+        }
+    }
+}
+
+/// `YYYY-MM-DD` of a day counted from the unix epoch.
+fn day_date(day: i64) -> String {
+    DateTime::from_timestamp(day * 24 * 60 * 60, 0)
+        .map(|date| date.date_naive().to_string())
+        .unwrap_or_default()
+}
+
+/// The lowercase host of an absolute `http` or `https` url, without user info and port.
+fn url_host(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return None;
+    }
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = match host_port.strip_prefix('[') {
+        // IPv6 literal
+        Some(literal) => literal.split(']').next()?,
+        None => host_port.split(':').next()?,
+    };
+    if host.is_empty() {
+        return None;
+    }
+    return Some(host.to_ascii_lowercase());
+}
+
+/// Counts a read of a post by anyone but its author. Only the host of the referring site
+/// is kept, links within the blog don't count as referrers. The post was already served,
+/// failures are only logged.
+pub fn record_view(
+    analytics_db_state: &Mutex<AnalyticsDbService>,
+    env_settings: &EnvSettings,
+    req: &HttpRequest,
+    user_auth: Option<&UserAuthentication>,
+    author_uuid: &str,
+    post_uuid: &str,
+) {
+    if user_auth.is_some_and(|user_auth| user_auth.uuid == author_uuid) {
+        return;
+    }
+
+    let visitor = match user_auth {
+        Some(user_auth) => format!("user:{}", user_auth.uuid),
+        None => format!(
+            "{}|{}",
+            req.connection_info().realip_remote_addr().unwrap_or("-"),
+            req.headers()
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("-")
+        ),
+    };
+    let own_hosts = [
+        url_host(&env_settings.public_base_url),
+        url_host(&format!("http://{}", req.connection_info().host())),
+    ];
+    let referrer = req
+        .headers()
+        .get(header::REFERER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(url_host)
+        .filter(|host| !own_hosts.iter().flatten().any(|own_host| own_host == host));
+
+    if let Err(err) = analytics_db_state.lock().unwrap().record_view(&PostView {
+        author_uuid,
+        post_uuid,
+        visitor: &visitor,
+        referrer: referrer.as_deref(),
+    }) {
+        log::error!("analytics, recording view failed {:?}", err);
+    }
+}
+
+/// Forgets the views of a deleted post, failures are only logged.
+pub fn remove_views(analytics_db_state: &Mutex<AnalyticsDbService>, post_uuid: &str) {
+    if let Err(err) = analytics_db_state.lock().unwrap().remove_post(post_uuid) {
+        log::error!("analytics, removing post failed {:?}", err);
+    }
+}
+
+/// Views of the user's posts over a period ending today: per day, the most read posts and
+/// the sites readers came from. Views are counted once per reader and day, the author's
+/// own reads are left out.
+#[get("/analytics")]
+async fn user_get_analytics(
+    user_auth: UserAuthentication,
+    query: web::Query<AnalyticsQuery>,
+    env_settings: web::Data<EnvSettings>,
+    analytics_db_state: web::Data<Mutex<AnalyticsDbService>>,
+) -> Result<impl Responder, AnalyticsError> {
+    let query = query.into_inner();
+    log::info!("/analytics {:?}", query);
+
+    let days = query
+        .days
+        .unwrap_or(DEFAULT_ANALYTICS_DAYS)
+        .clamp(1, MAX_ANALYTICS_DAYS);
+    let to_day = today();
+    let from_day = to_day - i64::from(days) + 1;
+
+    let post_db_service =
+        if PostDbService::exists(&env_settings.db_collection_path, &user_auth.uuid) {
+            Some(PostDbService::connect(
+                &env_settings.db_collection_path,
+                &user_auth.uuid,
+            )?)
+        } else {
+            None
+        };
+    if let Some(post_uuid) = &query.post_uuid {
+        post_db_service
+            .as_ref()
+            .ok_or(AnalyticsError::PostNotFound)?
+            .get_post(post_uuid)?;
+    }
+
+    let (daily_views, top_posts, referrers) = {
+        let analytics_db_service = analytics_db_state.lock().unwrap();
+        let daily_views = analytics_db_service.daily_views(
+            &user_auth.uuid,
+            query.post_uuid.as_deref(),
+            from_day,
+        )?;
+        let top_posts = match &query.post_uuid {
+            Some(_) => Vec::new(),
+            None => analytics_db_service.top_posts(&user_auth.uuid, from_day, TOP_POSTS_LIMIT)?,
+        };
+        let referrers = analytics_db_service.top_referrers(
+            &user_auth.uuid,
+            query.post_uuid.as_deref(),
+            from_day,
+            TOP_REFERRERS_LIMIT,
+        )?;
+        (daily_views, top_posts, referrers)
+    };
+
+    let daily: Vec<DailyViewsResponse> = (from_day..=to_day)
+        .map(|day| DailyViewsResponse {
+            date: day_date(day),
+            views: daily_views
+                .iter()
+                .find(|count| count.day == day)
+                .map_or(0, |count| count.views),
+        })
+        .collect();
+    let top_posts = match &post_db_service {
+        Some(post_db_service) => top_posts
+            .into_iter()
+            // posts deleted in the meantime are left out
+            .filter_map(|top_post| {
+                let post = post_db_service.get_post(&top_post.name).ok()?;
+                Some(TopPostResponse {
+                    post_uuid: post.uuid,
+                    title: post.title,
+                    views: top_post.views,
+                })
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    return Ok(web::Json(AnalyticsResponse {
+        from: day_date(from_day),
+        to: day_date(to_day),
+        total_views: daily.iter().map(|day| day.views).sum(),
+        daily,
+        top_posts,
+        referrers: referrers.into_iter().map(ReferrerResponse::from).collect(),
+    }));
+}
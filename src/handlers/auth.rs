@@ -30,8 +30,13 @@ pub enum RegisterError {
     GenericError = 10021,
     EmailAlreadyExist,
     DisplayNameAlreadyExist,
+    DisplayNameNotAllowed,
 }
 
+/// First path segments owned by api scopes, a permalink `/{displayName}/{slug}`
+/// under one of these never reaches the blog handler.
+const RESERVED_DISPLAY_NAMES: [&str; 5] = ["auth", "media", "moderation", "user", "users"];
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct LoginRequestData {
@@ -72,7 +77,7 @@ impl UserClaims {
             (Utc::now() + Duration::minutes(user_jwt_expiration_minutes)).timestamp() as usize;
         Self {
             exp: token_expiry_date,
-            uuid: uuid,
+            uuid,
         }
     }
 }
@@ -99,6 +104,7 @@ impl ResponseError for RegisterError {
         match self {
             RegisterError::DisplayNameAlreadyExist => StatusCode::BAD_REQUEST,
             RegisterError::EmailAlreadyExist => StatusCode::BAD_REQUEST,
+            RegisterError::DisplayNameNotAllowed => StatusCode::BAD_REQUEST,
             RegisterError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -112,6 +118,8 @@ impl ResponseError for RegisterError {
             ),
             RegisterError::EmailAlreadyExist => HttpResponse::build(status)
                 .json(AppErrorResponse::from(RegisterError::EmailAlreadyExist)),
+            RegisterError::DisplayNameNotAllowed => HttpResponse::build(status)
+                .json(AppErrorResponse::from(RegisterError::DisplayNameNotAllowed)),
             RegisterError::GenericError => HttpResponse::build(status)
                 .json(AppErrorResponse::from(RegisterError::GenericError)),
         }
@@ -179,6 +187,13 @@ async fn auth_register(
     let payload = param_obj.into_inner();
     log::trace!("/register {:?}", payload);
 
+    if RESERVED_DISPLAY_NAMES
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(payload.display_name.trim()))
+    {
+        return Err(RegisterError::DisplayNameNotAllowed);
+    }
+
     let user_db_service = user_db_state.lock().unwrap();
     let uuid = Uuid::new_v4();
     let uuid_str = uuid.to_string();
@@ -0,0 +1,276 @@
+use std::sync::Mutex;
+
+use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    social_db_service::{FollowEntry, SocialDbError, SocialDbService},
+    user_db_service::UserDbService,
+};
+
+use super::{
+    blog::{find_author, AuthorResponse, BlogError},
+    error_response::AppErrorResponse,
+    search::{page_params, PageQuery},
+    user_auth_token_extractor::UserAuthentication,
+};
+
+#[derive(Serialize, Debug, Display)]
+pub enum BlockError {
+    GenericError = 20801,
+    UserNotFound,
+    CannotBlockSelf,
+    CannotMuteSelf,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BlockRequest {
+    /// uuid or display name of the user to block or mute
+    user: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BlockResponse {
+    user: AuthorResponse,
+    blocked: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MuteResponse {
+    user: AuthorResponse,
+    muted: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BlockedUserResponse {
+    uuid: String,
+    display_name: String,
+    since: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BlockListResponse {
+    page: u32,
+    page_size: u32,
+    total: i64,
+    users: Vec<BlockedUserResponse>,
+}
+
+impl ResponseError for BlockError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            BlockError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            BlockError::UserNotFound => StatusCode::NOT_FOUND,
+            BlockError::CannotBlockSelf => StatusCode::BAD_REQUEST,
+            BlockError::CannotMuteSelf => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            BlockError::GenericError => {
+                HttpResponse::build(status).json(AppErrorResponse::from(BlockError::GenericError))
+            }
+            BlockError::UserNotFound => {
+                HttpResponse::build(status).json(AppErrorResponse::from(BlockError::UserNotFound))
+            }
+            BlockError::CannotBlockSelf => HttpResponse::build(status)
+                .json(AppErrorResponse::from(BlockError::CannotBlockSelf)),
+            BlockError::CannotMuteSelf => {
+                HttpResponse::build(status).json(AppErrorResponse::from(BlockError::CannotMuteSelf))
+            }
+        }
+    }
+}
+
+impl From<BlogError> for BlockError {
+    fn from(value: BlogError) -> Self {
+        match value {
+            BlogError::UserNotFound => BlockError::UserNotFound,
+            _ => BlockError::GenericError,
+        }
+    }
+}
+
+impl From<SocialDbError> for BlockError {
+    fn from(_value: SocialDbError) -> Self {
+        BlockError::GenericError
+    }
+}
+
+/// Adds the display names to a page of blocked or muted users, users which no longer exist
+/// are left out.
+fn blocked_users(
+    user_db_service: &UserDbService,
+    entries: Vec<FollowEntry>,
+) -> Vec<BlockedUserResponse> {
+    entries
+        .into_iter()
+        .filter_map(|entry| {
+            let user = user_db_service.get_user_from_uuid(&entry.user_uuid).ok()?;
+            Some(BlockedUserResponse {
+                uuid: user.uuid,
+                display_name: user.display_name,
+                since: entry.created_at,
+            })
+        })
+        .collect()
+}
+
+/// Blocks a user. Blocked users and the blocking user are invisible to each other: follows
+/// between them end, and they can't follow, reply to, mention, like, repost or message each
+/// other.
+#[post("/block")]
+async fn user_block(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<BlockRequest>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, BlockError> {
+    let payload = param_obj.into_inner();
+    log::info!("/block {:?}", payload);
+
+    let blocked = find_author(&user_db_state.lock().unwrap(), &payload.user)?;
+    if blocked.uuid == user_auth.uuid {
+        return Err(BlockError::CannotBlockSelf);
+    }
+    social_db_state
+        .lock()
+        .unwrap()
+        .block(&user_auth.uuid, &blocked.uuid)?;
+
+    return Ok(web::Json(BlockResponse {
+        user: AuthorResponse::from(blocked),
+        blocked: true,
+    }));
+}
+
+#[post("/unblock")]
+async fn user_unblock(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<BlockRequest>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, BlockError> {
+    let payload = param_obj.into_inner();
+    log::info!("/unblock {:?}", payload);
+
+    let blocked = find_author(&user_db_state.lock().unwrap(), &payload.user)?;
+    social_db_state
+        .lock()
+        .unwrap()
+        .unblock(&user_auth.uuid, &blocked.uuid)?;
+
+    return Ok(web::Json(BlockResponse {
+        user: AuthorResponse::from(blocked),
+        blocked: false,
+    }));
+}
+
+/// Mutes a user, their posts and the notifications they cause are hidden from the home
+/// timeline and the notifications of the muting user. Unlike a block the muted user notices
+/// nothing.
+#[post("/mute")]
+async fn user_mute(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<BlockRequest>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, BlockError> {
+    let payload = param_obj.into_inner();
+    log::info!("/mute {:?}", payload);
+
+    let muted = find_author(&user_db_state.lock().unwrap(), &payload.user)?;
+    if muted.uuid == user_auth.uuid {
+        return Err(BlockError::CannotMuteSelf);
+    }
+    social_db_state
+        .lock()
+        .unwrap()
+        .mute(&user_auth.uuid, &muted.uuid)?;
+
+    return Ok(web::Json(MuteResponse {
+        user: AuthorResponse::from(muted),
+        muted: true,
+    }));
+}
+
+#[post("/unmute")]
+async fn user_unmute(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<BlockRequest>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, BlockError> {
+    let payload = param_obj.into_inner();
+    log::info!("/unmute {:?}", payload);
+
+    let muted = find_author(&user_db_state.lock().unwrap(), &payload.user)?;
+    social_db_state
+        .lock()
+        .unwrap()
+        .unmute(&user_auth.uuid, &muted.uuid)?;
+
+    return Ok(web::Json(MuteResponse {
+        user: AuthorResponse::from(muted),
+        muted: false,
+    }));
+}
+
+/// Users blocked by the user, the most recently blocked first.
+#[get("/blocks")]
+async fn user_get_blocks(
+    user_auth: UserAuthentication,
+    page_query: web::Query<PageQuery>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, BlockError> {
+    log::info!("/blocks {:?}", page_query);
+
+    let (page, page_size) = page_params(page_query.page, page_query.page_size);
+    let (entries, total) = social_db_state.lock().unwrap().get_blocks(
+        &user_auth.uuid,
+        page_size,
+        (page - 1).saturating_mul(page_size),
+    )?;
+
+    return Ok(web::Json(BlockListResponse {
+        page,
+        page_size,
+        total,
+        users: blocked_users(&user_db_state.lock().unwrap(), entries),
+    }));
+}
+
+/// Users muted by the user, the most recently muted first.
+#[get("/mutes")]
+async fn user_get_mutes(
+    user_auth: UserAuthentication,
+    page_query: web::Query<PageQuery>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, BlockError> {
+    log::info!("/mutes {:?}", page_query);
+
+    let (page, page_size) = page_params(page_query.page, page_query.page_size);
+    let (entries, total) = social_db_state.lock().unwrap().get_mutes(
+        &user_auth.uuid,
+        page_size,
+        (page - 1).saturating_mul(page_size),
+    )?;
+
+    return Ok(web::Json(BlockListResponse {
+        page,
+        page_size,
+        total,
+        users: blocked_users(&user_db_state.lock().unwrap(), entries),
+    }));
+}
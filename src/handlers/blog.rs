@@ -0,0 +1,342 @@
+use std::sync::Mutex;
+
+use actix_web::{
+    get, http::header, http::StatusCode, web, HttpRequest, HttpResponse, ResponseError,
+};
+use derive_more::Display;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::services::{
+    analytics_db_service::AnalyticsDbService,
+    env_settings::EnvSettings,
+    link_preview_db_service::LinkPreviewDbService,
+    post_db_service::{PostDbError, PostDbService, PostFilter, SlugLookup, Visibility},
+    social_db_service::{SocialDbError, SocialDbService},
+    user_db_service::{User, UserDbError, UserDbService},
+};
+
+use super::{
+    analytics::record_view,
+    error_response::AppErrorResponse,
+    search::{page_params, PageQuery},
+    user::{load_link_previews, load_poll_votes, PostDataResponse, PostListQuery},
+    user_auth_token_extractor::UserAuthentication,
+};
+
+#[derive(Serialize, Debug, Display)]
+pub enum BlogError {
+    GenericError = 30001,
+    UserNotFound,
+    PostNotFound,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorResponse {
+    uuid: String,
+    display_name: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BlogPostListResponse {
+    author: AuthorResponse,
+    page: u32,
+    page_size: u32,
+    total: i64,
+    /// pinned posts in the order the author chose, only on the first page; they are left out
+    /// of `posts` and `total`
+    pinned: Vec<PostDataResponse>,
+    posts: Vec<PostDataResponse>,
+}
+
+impl ResponseError for BlogError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            BlogError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            BlogError::UserNotFound => StatusCode::NOT_FOUND,
+            BlogError::PostNotFound => StatusCode::NOT_FOUND,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            BlogError::GenericError => {
+                HttpResponse::build(status).json(AppErrorResponse::from(BlogError::GenericError))
+            }
+            BlogError::UserNotFound => {
+                HttpResponse::build(status).json(AppErrorResponse::from(BlogError::UserNotFound))
+            }
+            BlogError::PostNotFound => {
+                HttpResponse::build(status).json(AppErrorResponse::from(BlogError::PostNotFound))
+            }
+        }
+    }
+}
+
+impl From<User> for AuthorResponse {
+    fn from(value: User) -> Self {
+        Self {
+            uuid: value.uuid,
+            display_name: value.display_name,
+        }
+    }
+}
+
+impl From<PostDbError> for BlogError {
+    fn from(value: PostDbError) -> Self {
+        match value {
+            PostDbError::PostNotFound => BlogError::PostNotFound,
+            _ => BlogError::GenericError,
+        }
+    }
+}
+
+impl From<SocialDbError> for BlogError {
+    fn from(_value: SocialDbError) -> Self {
+        BlogError::GenericError
+    }
+}
+
+impl From<UserDbError> for BlogError {
+    fn from(value: UserDbError) -> Self {
+        match value {
+            UserDbError::UserNotFound => BlogError::UserNotFound,
+            _ => BlogError::GenericError,
+        }
+    }
+}
+
+/// Finds the author of a blog, `user` is either the uuid or the display name.
+pub fn find_author(user_db_service: &UserDbService, user: &str) -> Result<User, BlogError> {
+    if Uuid::parse_str(user).is_ok() {
+        if let Ok(author) = user_db_service.get_user_from_uuid(user) {
+            return Ok(author);
+        }
+    }
+    return Ok(user_db_service.get_user_from_display_name(user)?);
+}
+
+/// Whether the signed in reader blocked the author or is blocked by them, the blog is hidden
+/// from them then. Signed out readers are never blocked.
+pub fn is_blocked_reader(
+    social_db_state: &Mutex<SocialDbService>,
+    user_auth: Option<&UserAuthentication>,
+    author_uuid: &str,
+) -> Result<bool, BlogError> {
+    match user_auth {
+        Some(user_auth) => Ok(social_db_state
+            .lock()
+            .unwrap()
+            .is_blocked(&user_auth.uuid, author_uuid)?),
+        None => Ok(false),
+    }
+}
+
+/// Adds the poll votes the reader may see and the link previews to a single post.
+fn readable_post(
+    social_db_state: &Mutex<SocialDbService>,
+    link_preview_db_state: &Mutex<LinkPreviewDbService>,
+    user_auth: Option<&UserAuthentication>,
+    mut post: PostDataResponse,
+) -> PostDataResponse {
+    load_poll_votes(
+        social_db_state,
+        std::slice::from_mut(&mut post),
+        user_auth.map(|user_auth| user_auth.uuid.as_str()),
+    );
+    load_link_previews(link_preview_db_state, std::slice::from_mut(&mut post));
+    return post;
+}
+
+/// Public listing of a blog, newest posts first, with the pinned posts matching the filter
+/// listed separately on the first page. Unlisted and private posts are left out. Signed in
+/// readers see the poll votes once they voted, blogs of users blocking them or blocked by
+/// them aren't found.
+#[get("/{user}/posts")]
+#[allow(clippy::too_many_arguments)]
+async fn blog_get_posts(
+    user_auth: Option<UserAuthentication>,
+    path: web::Path<String>,
+    list_query: web::Query<PostListQuery>,
+    page_query: web::Query<PageQuery>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
+) -> Result<HttpResponse, BlogError> {
+    let user = path.into_inner();
+    log::info!("/users/{}/posts {:?} {:?}", user, list_query, page_query);
+
+    let author = find_author(&user_db_state.lock().unwrap(), &user)?;
+    if is_blocked_reader(&social_db_state, user_auth.as_ref(), &author.uuid)? {
+        return Err(BlogError::UserNotFound);
+    }
+    let (page, page_size) = page_params(page_query.page, page_query.page_size);
+    let mut response = BlogPostListResponse {
+        author: AuthorResponse::from(author.clone()),
+        page,
+        page_size,
+        total: 0,
+        pinned: Vec::new(),
+        posts: Vec::new(),
+    };
+
+    if !PostDbService::exists(&env_settings.db_collection_path, &author.uuid) {
+        return Ok(HttpResponse::Ok().json(response));
+    }
+
+    let filter = PostFilter {
+        visibilities: vec![Visibility::Public],
+        exclude_hidden: true,
+        ..PostFilter::from(list_query.into_inner())
+    };
+    let unpinned_filter = PostFilter {
+        exclude_pinned: true,
+        ..filter.clone()
+    };
+    let post_db_service = PostDbService::connect(&env_settings.db_collection_path, &author.uuid)?;
+    response.total = post_db_service.count_posts(&unpinned_filter)?;
+    response.posts = post_db_service
+        .get_posts(
+            &unpinned_filter,
+            page_size,
+            (page - 1).saturating_mul(page_size),
+        )?
+        .into_iter()
+        .map(PostDataResponse::from)
+        .collect();
+    if page == 1 {
+        response.pinned = post_db_service
+            .get_pinned_posts(&filter)?
+            .into_iter()
+            .map(PostDataResponse::from)
+            .collect();
+    }
+    let reader_uuid = user_auth.as_ref().map(|user_auth| user_auth.uuid.as_str());
+    load_poll_votes(&social_db_state, &mut response.pinned, reader_uuid);
+    load_poll_votes(&social_db_state, &mut response.posts, reader_uuid);
+    load_link_previews(&link_preview_db_state, &mut response.pinned);
+    load_link_previews(&link_preview_db_state, &mut response.posts);
+
+    return Ok(HttpResponse::Ok().json(response));
+}
+
+/// Public and unlisted posts can be read by everyone who knows the post uuid.
+#[get("/{user}/posts/{post_uuid}")]
+#[allow(clippy::too_many_arguments)]
+async fn blog_get_post(
+    req: HttpRequest,
+    user_auth: Option<UserAuthentication>,
+    path: web::Path<(String, String)>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
+    analytics_db_state: web::Data<Mutex<AnalyticsDbService>>,
+) -> Result<HttpResponse, BlogError> {
+    let (user, post_uuid) = path.into_inner();
+    log::info!("/users/{}/posts/{}", user, post_uuid);
+
+    let author = find_author(&user_db_state.lock().unwrap(), &user)?;
+    if is_blocked_reader(&social_db_state, user_auth.as_ref(), &author.uuid)? {
+        return Err(BlogError::PostNotFound);
+    }
+    if !PostDbService::exists(&env_settings.db_collection_path, &author.uuid) {
+        return Err(BlogError::PostNotFound);
+    }
+
+    let post_db_service = PostDbService::connect(&env_settings.db_collection_path, &author.uuid)?;
+    let post = post_db_service.get_post(&post_uuid)?;
+    if !post.is_readable_by_others() {
+        return Err(BlogError::PostNotFound);
+    }
+    record_view(
+        &analytics_db_state,
+        &env_settings,
+        &req,
+        user_auth.as_ref(),
+        &author.uuid,
+        &post.uuid,
+    );
+
+    return Ok(HttpResponse::Ok().json(readable_post(
+        &social_db_state,
+        &link_preview_db_state,
+        user_auth.as_ref(),
+        PostDataResponse::from(post),
+    )));
+}
+
+/// Permalink of a post. Slugs a post had before its title changed answer with a
+/// permanent redirect to the current one.
+#[get("/{display_name}/{slug}")]
+#[allow(clippy::too_many_arguments)]
+async fn blog_get_post_by_slug(
+    req: HttpRequest,
+    user_auth: Option<UserAuthentication>,
+    path: web::Path<(String, String)>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
+    analytics_db_state: web::Data<Mutex<AnalyticsDbService>>,
+) -> Result<HttpResponse, BlogError> {
+    let (display_name, slug) = path.into_inner();
+    log::info!("/{}/{}", display_name, slug);
+
+    let author = user_db_state
+        .lock()
+        .unwrap()
+        .get_user_from_display_name(&display_name)?;
+    if is_blocked_reader(&social_db_state, user_auth.as_ref(), &author.uuid)? {
+        return Err(BlogError::PostNotFound);
+    }
+
+    if !PostDbService::exists(&env_settings.db_collection_path, &author.uuid) {
+        return Err(BlogError::PostNotFound);
+    }
+
+    let post_db_service = PostDbService::connect(&env_settings.db_collection_path, &author.uuid)?;
+    match post_db_service.find_post_by_slug(&slug)? {
+        SlugLookup::Post(post) if post.is_readable_by_others() => {
+            record_view(
+                &analytics_db_state,
+                &env_settings,
+                &req,
+                user_auth.as_ref(),
+                &author.uuid,
+                &post.uuid,
+            );
+            return Ok(HttpResponse::Ok().json(readable_post(
+                &social_db_state,
+                &link_preview_db_state,
+                user_auth.as_ref(),
+                PostDataResponse::from(*post),
+            )));
+        }
+        SlugLookup::Post(_) => {
+            return Err(BlogError::PostNotFound);
+        }
+        SlugLookup::Redirect(current_slug) => {
+            // the current slug of a private post would give away its title
+            if !matches!(
+                post_db_service.find_post_by_slug(&current_slug)?,
+                SlugLookup::Post(post) if post.is_readable_by_others()
+            ) {
+                return Err(BlogError::PostNotFound);
+            }
+            // keep the display name segment exactly as it was requested
+            let author_path = req.path().rsplit_once('/').map_or("", |(prefix, _)| prefix);
+            return Ok(HttpResponse::MovedPermanently()
+                .insert_header((
+                    header::LOCATION,
+                    format!("{}/{}", author_path, current_slug),
+                ))
+                .finish());
+        }
+    }
+}
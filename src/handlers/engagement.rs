@@ -0,0 +1,634 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    env_settings::EnvSettings,
+    link_preview_db_service::LinkPreviewDbService,
+    post_db_service::{PostDbError, PostDbService},
+    post_entities::text_length,
+    search_db_service::{PublicPost, SearchDbError, SearchDbService},
+    social_db_service::{EngagementState, NotificationKind, SocialDbError, SocialDbService},
+    user_db_service::UserDbService,
+};
+
+use super::{
+    error_response::AppErrorResponse,
+    notification::notify,
+    search::{load_public_link_previews, page_params, PageQuery, PublicPostResponse},
+    user_auth_token_extractor::UserAuthentication,
+};
+
+#[derive(Serialize, Debug, Display)]
+pub enum EngagementError {
+    GenericError = 20401,
+    PostNotFound,
+    QuoteTooLong,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct EngagementRequest {
+    post_uuid: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RepostRequest {
+    post_uuid: String,
+    /// text shown above the reposted post, reposting again replaces it
+    quote: Option<String>,
+}
+
+/// Where the user stands with a post after an engagement request, and the public counts.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct EngagementResponse {
+    post_uuid: String,
+    liked: bool,
+    reposted: bool,
+    bookmarked: bool,
+    like_count: i64,
+    repost_count: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LikeUserResponse {
+    uuid: String,
+    display_name: String,
+    liked_at: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LikeListResponse {
+    post_uuid: String,
+    page: u32,
+    page_size: u32,
+    total: i64,
+    users: Vec<LikeUserResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RepostUserResponse {
+    uuid: String,
+    display_name: String,
+    quote: Option<String>,
+    reposted_at: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RepostListResponse {
+    post_uuid: String,
+    page: u32,
+    page_size: u32,
+    total: i64,
+    users: Vec<RepostUserResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BookmarkListResponse {
+    page: u32,
+    page_size: u32,
+    total: i64,
+    posts: Vec<PublicPostResponse>,
+}
+
+impl ResponseError for EngagementError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            EngagementError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            EngagementError::PostNotFound => StatusCode::NOT_FOUND,
+            EngagementError::QuoteTooLong => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            EngagementError::GenericError => HttpResponse::build(status)
+                .json(AppErrorResponse::from(EngagementError::GenericError)),
+            EngagementError::PostNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(EngagementError::PostNotFound)),
+            EngagementError::QuoteTooLong => HttpResponse::build(status)
+                .json(AppErrorResponse::from(EngagementError::QuoteTooLong)),
+        }
+    }
+}
+
+impl From<SearchDbError> for EngagementError {
+    fn from(_value: SearchDbError) -> Self {
+        EngagementError::GenericError
+    }
+}
+
+impl From<SocialDbError> for EngagementError {
+    fn from(_value: SocialDbError) -> Self {
+        EngagementError::GenericError
+    }
+}
+
+/// Only public posts can be liked, reposted or bookmarked, the search index lists exactly those.
+fn find_public_post(
+    search_db_state: &Mutex<SearchDbService>,
+    post_uuid: &str,
+) -> Result<PublicPost, EngagementError> {
+    return search_db_state
+        .lock()
+        .unwrap()
+        .get_posts(&[post_uuid.to_owned()])?
+        .pop()
+        .ok_or(EngagementError::PostNotFound);
+}
+
+/// Like `find_public_post`, posts of users blocking `user_uuid` or blocked by them are
+/// treated as not found.
+fn find_visible_post(
+    search_db_state: &Mutex<SearchDbService>,
+    social_db_state: &Mutex<SocialDbService>,
+    user_uuid: &str,
+    post_uuid: &str,
+) -> Result<PublicPost, EngagementError> {
+    let post = find_public_post(search_db_state, post_uuid)?;
+    if social_db_state
+        .lock()
+        .unwrap()
+        .is_blocked(user_uuid, &post.author_uuid)?
+    {
+        return Err(EngagementError::PostNotFound);
+    }
+    return Ok(post);
+}
+
+/// Public post for a possibly signed in reader, along with the users they block or are blocked
+/// by, whose likes and reposts are left out of the listings.
+fn find_readable_post(
+    search_db_state: &Mutex<SearchDbService>,
+    social_db_state: &Mutex<SocialDbService>,
+    user_auth: Option<&UserAuthentication>,
+    post_uuid: &str,
+) -> Result<(PublicPost, Vec<String>), EngagementError> {
+    let Some(user_auth) = user_auth else {
+        return Ok((find_public_post(search_db_state, post_uuid)?, Vec::new()));
+    };
+    let post = find_visible_post(search_db_state, social_db_state, &user_auth.uuid, post_uuid)?;
+    let blocked_uuids = social_db_state
+        .lock()
+        .unwrap()
+        .get_blocked_uuids(&user_auth.uuid)?;
+    return Ok((post, blocked_uuids));
+}
+
+/// Counts the likes and reposts of a post and stores them with the post in the database of
+/// its author and in the search index. Storing is best effort like the other derived data,
+/// the counts themselves are returned.
+fn sync_engagement_counts(
+    env_settings: &EnvSettings,
+    search_db_state: &Mutex<SearchDbService>,
+    social_db_state: &Mutex<SocialDbService>,
+    post_uuid: &str,
+    author_uuid: &str,
+) -> Result<(i64, i64), EngagementError> {
+    let (like_count, repost_count) = social_db_state
+        .lock()
+        .unwrap()
+        .engagement_counts(post_uuid)?;
+
+    if PostDbService::exists(&env_settings.db_collection_path, author_uuid) {
+        let result = PostDbService::connect(&env_settings.db_collection_path, author_uuid)
+            .and_then(|post_db_service| {
+                post_db_service.set_engagement_counts(post_uuid, like_count, repost_count)
+            });
+        match result {
+            Ok(()) | Err(PostDbError::PostNotFound) => {}
+            Err(err) => log::error!("engagement, storing counts failed {:?}", err),
+        }
+    }
+    if let Err(err) =
+        search_db_state
+            .lock()
+            .unwrap()
+            .set_engagement_counts(post_uuid, like_count, repost_count)
+    {
+        log::error!("engagement, indexing counts failed {:?}", err);
+    }
+
+    return Ok((like_count, repost_count));
+}
+
+fn engagement_response(
+    social_db_state: &Mutex<SocialDbService>,
+    user_uuid: &str,
+    post_uuid: String,
+    (like_count, repost_count): (i64, i64),
+) -> Result<EngagementResponse, EngagementError> {
+    let EngagementState {
+        liked,
+        reposted,
+        bookmarked,
+    } = social_db_state
+        .lock()
+        .unwrap()
+        .engagement_state(user_uuid, &post_uuid)?;
+
+    return Ok(EngagementResponse {
+        post_uuid,
+        liked,
+        reposted,
+        bookmarked,
+        like_count,
+        repost_count,
+    });
+}
+
+/// Drops the likes, reposts and bookmarks of a deleted post, failures are only logged.
+pub fn remove_engagement(social_db_state: &Mutex<SocialDbService>, post_uuid: &str) {
+    if let Err(err) = social_db_state.lock().unwrap().remove_engagement(post_uuid) {
+        log::error!("engagement, removing post failed {:?}", err);
+    }
+}
+
+#[post("/like")]
+async fn user_like(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<EngagementRequest>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, EngagementError> {
+    let payload = param_obj.into_inner();
+    log::info!("/like {:?}", payload);
+
+    let post = find_visible_post(
+        &search_db_state,
+        &social_db_state,
+        &user_auth.uuid,
+        &payload.post_uuid,
+    )?;
+    let liked = social_db_state.lock().unwrap().like(
+        &user_auth.uuid,
+        &post.post_uuid,
+        &post.author_uuid,
+    )?;
+    if liked {
+        notify(
+            &user_db_state,
+            &social_db_state,
+            &post.author_uuid,
+            NotificationKind::Like,
+            &user_auth.uuid,
+            Some(&post.post_uuid),
+        );
+    }
+    let counts = sync_engagement_counts(
+        &env_settings,
+        &search_db_state,
+        &social_db_state,
+        &post.post_uuid,
+        &post.author_uuid,
+    )?;
+
+    return Ok(web::Json(engagement_response(
+        &social_db_state,
+        &user_auth.uuid,
+        post.post_uuid,
+        counts,
+    )?));
+}
+
+/// Works for posts which were deleted or aren't public anymore, too.
+#[post("/unlike")]
+async fn user_unlike(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<EngagementRequest>,
+    env_settings: web::Data<EnvSettings>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, EngagementError> {
+    let payload = param_obj.into_inner();
+    log::info!("/unlike {:?}", payload);
+
+    let removed_from = social_db_state
+        .lock()
+        .unwrap()
+        .unlike(&user_auth.uuid, &payload.post_uuid)?;
+    let counts = match removed_from {
+        Some(author_uuid) => sync_engagement_counts(
+            &env_settings,
+            &search_db_state,
+            &social_db_state,
+            &payload.post_uuid,
+            &author_uuid,
+        )?,
+        None => social_db_state
+            .lock()
+            .unwrap()
+            .engagement_counts(&payload.post_uuid)?,
+    };
+
+    return Ok(web::Json(engagement_response(
+        &social_db_state,
+        &user_auth.uuid,
+        payload.post_uuid,
+        counts,
+    )?));
+}
+
+#[post("/repost")]
+async fn user_repost(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<RepostRequest>,
+    env_settings: web::Data<EnvSettings>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, EngagementError> {
+    let payload = param_obj.into_inner();
+    log::info!("/repost {:?}", payload);
+
+    let quote = payload
+        .quote
+        .as_deref()
+        .map(str::trim)
+        .filter(|quote| !quote.is_empty());
+    let post_limits = &env_settings.post_limits;
+    if quote.is_some_and(|quote| {
+        text_length(quote, post_limits.url_length) > post_limits.max_quote_length
+    }) {
+        return Err(EngagementError::QuoteTooLong);
+    }
+
+    let post = find_visible_post(
+        &search_db_state,
+        &social_db_state,
+        &user_auth.uuid,
+        &payload.post_uuid,
+    )?;
+    social_db_state.lock().unwrap().repost(
+        &user_auth.uuid,
+        &post.post_uuid,
+        &post.author_uuid,
+        quote,
+    )?;
+    let counts = sync_engagement_counts(
+        &env_settings,
+        &search_db_state,
+        &social_db_state,
+        &post.post_uuid,
+        &post.author_uuid,
+    )?;
+
+    return Ok(web::Json(engagement_response(
+        &social_db_state,
+        &user_auth.uuid,
+        post.post_uuid,
+        counts,
+    )?));
+}
+
+/// Works for posts which were deleted or aren't public anymore, too.
+#[post("/unrepost")]
+async fn user_unrepost(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<EngagementRequest>,
+    env_settings: web::Data<EnvSettings>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, EngagementError> {
+    let payload = param_obj.into_inner();
+    log::info!("/unrepost {:?}", payload);
+
+    let removed_from = social_db_state
+        .lock()
+        .unwrap()
+        .unrepost(&user_auth.uuid, &payload.post_uuid)?;
+    let counts = match removed_from {
+        Some(author_uuid) => sync_engagement_counts(
+            &env_settings,
+            &search_db_state,
+            &social_db_state,
+            &payload.post_uuid,
+            &author_uuid,
+        )?,
+        None => social_db_state
+            .lock()
+            .unwrap()
+            .engagement_counts(&payload.post_uuid)?,
+    };
+
+    return Ok(web::Json(engagement_response(
+        &social_db_state,
+        &user_auth.uuid,
+        payload.post_uuid,
+        counts,
+    )?));
+}
+
+/// Bookmarks are private, they don't show up in any count.
+#[post("/bookmark")]
+async fn user_bookmark(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<EngagementRequest>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, EngagementError> {
+    let payload = param_obj.into_inner();
+    log::info!("/bookmark {:?}", payload);
+
+    let post = find_visible_post(
+        &search_db_state,
+        &social_db_state,
+        &user_auth.uuid,
+        &payload.post_uuid,
+    )?;
+    let counts = {
+        let social_db_service = social_db_state.lock().unwrap();
+        social_db_service.bookmark(&user_auth.uuid, &post.post_uuid)?;
+        social_db_service.engagement_counts(&post.post_uuid)?
+    };
+
+    return Ok(web::Json(engagement_response(
+        &social_db_state,
+        &user_auth.uuid,
+        post.post_uuid,
+        counts,
+    )?));
+}
+
+#[post("/unbookmark")]
+async fn user_unbookmark(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<EngagementRequest>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, EngagementError> {
+    let payload = param_obj.into_inner();
+    log::info!("/unbookmark {:?}", payload);
+
+    let counts = {
+        let social_db_service = social_db_state.lock().unwrap();
+        social_db_service.unbookmark(&user_auth.uuid, &payload.post_uuid)?;
+        social_db_service.engagement_counts(&payload.post_uuid)?
+    };
+
+    return Ok(web::Json(engagement_response(
+        &social_db_state,
+        &user_auth.uuid,
+        payload.post_uuid,
+        counts,
+    )?));
+}
+
+/// Posts bookmarked by the user, the latest bookmark first. Posts which were deleted, aren't
+/// public anymore or were written by blocked users are left out of the page.
+#[get("/bookmarks")]
+async fn user_get_bookmarks(
+    user_auth: UserAuthentication,
+    page_query: web::Query<PageQuery>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
+) -> Result<impl Responder, EngagementError> {
+    let page_query = page_query.into_inner();
+    log::info!("/bookmarks {:?}", page_query);
+
+    let (page, page_size) = page_params(page_query.page, page_query.page_size);
+    let (post_uuids, total, blocked_uuids) = {
+        let social_db_service = social_db_state.lock().unwrap();
+        let (post_uuids, total) = social_db_service.get_bookmarks(
+            &user_auth.uuid,
+            page_size,
+            (page - 1).saturating_mul(page_size),
+        )?;
+        let blocked_uuids = social_db_service.get_blocked_uuids(&user_auth.uuid)?;
+        (post_uuids, total, blocked_uuids)
+    };
+
+    let mut posts_by_uuid: HashMap<String, PublicPostResponse> = search_db_state
+        .lock()
+        .unwrap()
+        .get_posts(&post_uuids)?
+        .into_iter()
+        .filter(|post| !blocked_uuids.contains(&post.author_uuid))
+        .map(|post| (post.post_uuid.clone(), PublicPostResponse::from(post)))
+        .collect();
+    let mut posts: Vec<PublicPostResponse> = post_uuids
+        .iter()
+        .filter_map(|post_uuid| posts_by_uuid.remove(post_uuid))
+        .collect();
+    load_public_link_previews(&link_preview_db_state, &mut posts);
+
+    return Ok(web::Json(BookmarkListResponse {
+        page,
+        page_size,
+        total,
+        posts,
+    }));
+}
+
+/// Users who liked a public post, the most recent likes first. Signed in readers don't see
+/// users blocking them or blocked by them.
+#[get("/posts/{post_uuid}/likes")]
+async fn get_post_likes(
+    user_auth: Option<UserAuthentication>,
+    path: web::Path<String>,
+    page_query: web::Query<PageQuery>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, EngagementError> {
+    let post_uuid = path.into_inner();
+    log::info!("/posts/{}/likes {:?}", post_uuid, page_query);
+
+    let (post, blocked_uuids) = find_readable_post(
+        &search_db_state,
+        &social_db_state,
+        user_auth.as_ref(),
+        &post_uuid,
+    )?;
+    let (page, page_size) = page_params(page_query.page, page_query.page_size);
+    let entries = social_db_state.lock().unwrap().get_likes(
+        &post.post_uuid,
+        page_size,
+        (page - 1).saturating_mul(page_size),
+    )?;
+
+    let user_db_service = user_db_state.lock().unwrap();
+    let users = entries
+        .into_iter()
+        .filter(|entry| !blocked_uuids.contains(&entry.user_uuid))
+        .filter_map(|entry| {
+            let user = user_db_service.get_user_from_uuid(&entry.user_uuid).ok()?;
+            Some(LikeUserResponse {
+                uuid: user.uuid,
+                display_name: user.display_name,
+                liked_at: entry.created_at,
+            })
+        })
+        .collect();
+
+    return Ok(web::Json(LikeListResponse {
+        post_uuid: post.post_uuid,
+        page,
+        page_size,
+        total: post.like_count,
+        users,
+    }));
+}
+
+/// Users who reposted a public post with their quotes, the most recent reposts first. Signed
+/// in readers don't see users blocking them or blocked by them.
+#[get("/posts/{post_uuid}/reposts")]
+async fn get_post_reposts(
+    user_auth: Option<UserAuthentication>,
+    path: web::Path<String>,
+    page_query: web::Query<PageQuery>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, EngagementError> {
+    let post_uuid = path.into_inner();
+    log::info!("/posts/{}/reposts {:?}", post_uuid, page_query);
+
+    let (post, blocked_uuids) = find_readable_post(
+        &search_db_state,
+        &social_db_state,
+        user_auth.as_ref(),
+        &post_uuid,
+    )?;
+    let (page, page_size) = page_params(page_query.page, page_query.page_size);
+    let entries = social_db_state.lock().unwrap().get_reposts(
+        &post.post_uuid,
+        page_size,
+        (page - 1).saturating_mul(page_size),
+    )?;
+
+    let user_db_service = user_db_state.lock().unwrap();
+    let users = entries
+        .into_iter()
+        .filter(|entry| !blocked_uuids.contains(&entry.user_uuid))
+        .filter_map(|entry| {
+            let user = user_db_service.get_user_from_uuid(&entry.user_uuid).ok()?;
+            Some(RepostUserResponse {
+                uuid: user.uuid,
+                display_name: user.display_name,
+                quote: entry.quote,
+                reposted_at: entry.created_at,
+            })
+        })
+        .collect();
+
+    return Ok(web::Json(RepostListResponse {
+        post_uuid: post.post_uuid,
+        page,
+        page_size,
+        total: post.repost_count,
+        users,
+    }));
+}
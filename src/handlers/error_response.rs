@@ -1,7 +1,23 @@
 use serde::Serialize;
 
 use super::{
+    analytics::AnalyticsError,
     auth::{AppError, LoginError, RegisterError},
+    block::BlockError,
+    blog::BlogError,
+    engagement::EngagementError,
+    events::EventError,
+    feed::FeedError,
+    media::MediaError,
+    message::MessageError,
+    moderation::ModerationError,
+    notification::NotificationError,
+    poll::PollError,
+    post_revision::PostRevisionError,
+    profile::ProfileError,
+    search::SearchError,
+    social::SocialError,
+    thread::ThreadError,
     user::UserPostError,
 };
 
@@ -59,6 +75,12 @@ impl From<RegisterError> for AppErrorResponse {
                     error_message: "An account with email already exist".to_string(),
                 };
             }
+            RegisterError::DisplayNameNotAllowed => {
+                return AppErrorResponse {
+                    error_code: RegisterError::DisplayNameNotAllowed as u16,
+                    error_message: "Display name is reserved".to_string(),
+                };
+            }
         }
     }
 }
@@ -78,6 +100,569 @@ impl From<UserPostError> for AppErrorResponse {
                     error_message: "Post not found".to_string(),
                 };
             }
+            UserPostError::ReplyToNotFound => {
+                return AppErrorResponse {
+                    error_code: UserPostError::ReplyToNotFound as u16,
+                    error_message: "The post to reply to was not found".to_string(),
+                };
+            }
+            UserPostError::AttachmentNotFound => {
+                return AppErrorResponse {
+                    error_code: UserPostError::AttachmentNotFound as u16,
+                    error_message: "Attachment not found".to_string(),
+                };
+            }
+            UserPostError::TooManyAttachments => {
+                return AppErrorResponse {
+                    error_code: UserPostError::TooManyAttachments as u16,
+                    error_message: "Too many attachments".to_string(),
+                };
+            }
+            UserPostError::InvalidPoll => {
+                return AppErrorResponse {
+                    error_code: UserPostError::InvalidPoll as u16,
+                    error_message: "Poll needs 2 to 6 options and a closing time within 30 days"
+                        .to_string(),
+                };
+            }
+            UserPostError::TooManyPinnedPosts => {
+                return AppErrorResponse {
+                    error_code: UserPostError::TooManyPinnedPosts as u16,
+                    error_message: "At most 5 posts can be pinned".to_string(),
+                };
+            }
+            UserPostError::PinnedPostNotPublic => {
+                return AppErrorResponse {
+                    error_code: UserPostError::PinnedPostNotPublic as u16,
+                    error_message: "Only public posts can be pinned".to_string(),
+                };
+            }
+            UserPostError::TitleTooLong => {
+                return AppErrorResponse {
+                    error_code: UserPostError::TitleTooLong as u16,
+                    error_message: "Title is longer than allowed".to_string(),
+                };
+            }
+            UserPostError::PostTooLong => {
+                return AppErrorResponse {
+                    error_code: UserPostError::PostTooLong as u16,
+                    error_message: "Post is longer than allowed".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<PostRevisionError> for AppErrorResponse {
+    fn from(value: PostRevisionError) -> AppErrorResponse {
+        match value {
+            PostRevisionError::GenericError => {
+                return AppErrorResponse {
+                    error_code: PostRevisionError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            PostRevisionError::PostNotFound => {
+                return AppErrorResponse {
+                    error_code: PostRevisionError::PostNotFound as u16,
+                    error_message: "Post not found".to_string(),
+                };
+            }
+            PostRevisionError::RevisionNotFound => {
+                return AppErrorResponse {
+                    error_code: PostRevisionError::RevisionNotFound as u16,
+                    error_message: "Post revision not found".to_string(),
+                };
+            }
+            PostRevisionError::TitleTooLong => {
+                return AppErrorResponse {
+                    error_code: PostRevisionError::TitleTooLong as u16,
+                    error_message: "Title is longer than allowed".to_string(),
+                };
+            }
+            PostRevisionError::PostTooLong => {
+                return AppErrorResponse {
+                    error_code: PostRevisionError::PostTooLong as u16,
+                    error_message: "Post is longer than allowed".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<SearchError> for AppErrorResponse {
+    fn from(value: SearchError) -> AppErrorResponse {
+        match value {
+            SearchError::GenericError => {
+                return AppErrorResponse {
+                    error_code: SearchError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            SearchError::InvalidSearchQuery => {
+                return AppErrorResponse {
+                    error_code: SearchError::InvalidSearchQuery as u16,
+                    error_message: "Search query has no searchable terms".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<BlogError> for AppErrorResponse {
+    fn from(value: BlogError) -> AppErrorResponse {
+        match value {
+            BlogError::GenericError => {
+                return AppErrorResponse {
+                    error_code: BlogError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            BlogError::UserNotFound => {
+                return AppErrorResponse {
+                    error_code: BlogError::UserNotFound as u16,
+                    error_message: "User not found".to_string(),
+                };
+            }
+            BlogError::PostNotFound => {
+                return AppErrorResponse {
+                    error_code: BlogError::PostNotFound as u16,
+                    error_message: "Post not found".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<FeedError> for AppErrorResponse {
+    fn from(value: FeedError) -> AppErrorResponse {
+        match value {
+            FeedError::GenericError => {
+                return AppErrorResponse {
+                    error_code: FeedError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            FeedError::UserNotFound => {
+                return AppErrorResponse {
+                    error_code: FeedError::UserNotFound as u16,
+                    error_message: "User not found".to_string(),
+                };
+            }
+            FeedError::FeedNotFound => {
+                return AppErrorResponse {
+                    error_code: FeedError::FeedNotFound as u16,
+                    error_message: "Feed not found".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<SocialError> for AppErrorResponse {
+    fn from(value: SocialError) -> AppErrorResponse {
+        match value {
+            SocialError::GenericError => {
+                return AppErrorResponse {
+                    error_code: SocialError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            SocialError::UserNotFound => {
+                return AppErrorResponse {
+                    error_code: SocialError::UserNotFound as u16,
+                    error_message: "User not found".to_string(),
+                };
+            }
+            SocialError::CannotFollowSelf => {
+                return AppErrorResponse {
+                    error_code: SocialError::CannotFollowSelf as u16,
+                    error_message: "Users can't follow themselves".to_string(),
+                };
+            }
+            SocialError::UserBlocked => {
+                return AppErrorResponse {
+                    error_code: SocialError::UserBlocked as u16,
+                    error_message: "Users blocking each other can't follow each other".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<ThreadError> for AppErrorResponse {
+    fn from(value: ThreadError) -> AppErrorResponse {
+        match value {
+            ThreadError::GenericError => {
+                return AppErrorResponse {
+                    error_code: ThreadError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            ThreadError::PostNotFound => {
+                return AppErrorResponse {
+                    error_code: ThreadError::PostNotFound as u16,
+                    error_message: "Post not found".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<EngagementError> for AppErrorResponse {
+    fn from(value: EngagementError) -> AppErrorResponse {
+        match value {
+            EngagementError::GenericError => {
+                return AppErrorResponse {
+                    error_code: EngagementError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            EngagementError::PostNotFound => {
+                return AppErrorResponse {
+                    error_code: EngagementError::PostNotFound as u16,
+                    error_message: "Post not found".to_string(),
+                };
+            }
+            EngagementError::QuoteTooLong => {
+                return AppErrorResponse {
+                    error_code: EngagementError::QuoteTooLong as u16,
+                    error_message: "Repost quote is too long".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<NotificationError> for AppErrorResponse {
+    fn from(value: NotificationError) -> AppErrorResponse {
+        match value {
+            NotificationError::GenericError => {
+                return AppErrorResponse {
+                    error_code: NotificationError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<EventError> for AppErrorResponse {
+    fn from(value: EventError) -> AppErrorResponse {
+        match value {
+            EventError::GenericError => {
+                return AppErrorResponse {
+                    error_code: EventError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<MessageError> for AppErrorResponse {
+    fn from(value: MessageError) -> AppErrorResponse {
+        match value {
+            MessageError::GenericError => {
+                return AppErrorResponse {
+                    error_code: MessageError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            MessageError::ConversationNotFound => {
+                return AppErrorResponse {
+                    error_code: MessageError::ConversationNotFound as u16,
+                    error_message: "Conversation not found".to_string(),
+                };
+            }
+            MessageError::UserNotFound => {
+                return AppErrorResponse {
+                    error_code: MessageError::UserNotFound as u16,
+                    error_message: "User not found".to_string(),
+                };
+            }
+            MessageError::InvalidMembers => {
+                return AppErrorResponse {
+                    error_code: MessageError::InvalidMembers as u16,
+                    error_message: "A conversation needs between 1 and 9 other members".to_string(),
+                };
+            }
+            MessageError::EmptyMessage => {
+                return AppErrorResponse {
+                    error_code: MessageError::EmptyMessage as u16,
+                    error_message: "Message is empty".to_string(),
+                };
+            }
+            MessageError::MessageTooLong => {
+                return AppErrorResponse {
+                    error_code: MessageError::MessageTooLong as u16,
+                    error_message: "Message is too long".to_string(),
+                };
+            }
+            MessageError::UserBlocked => {
+                return AppErrorResponse {
+                    error_code: MessageError::UserBlocked as u16,
+                    error_message: "Users blocking each other can't message each other".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<BlockError> for AppErrorResponse {
+    fn from(value: BlockError) -> AppErrorResponse {
+        match value {
+            BlockError::GenericError => {
+                return AppErrorResponse {
+                    error_code: BlockError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            BlockError::UserNotFound => {
+                return AppErrorResponse {
+                    error_code: BlockError::UserNotFound as u16,
+                    error_message: "User not found".to_string(),
+                };
+            }
+            BlockError::CannotBlockSelf => {
+                return AppErrorResponse {
+                    error_code: BlockError::CannotBlockSelf as u16,
+                    error_message: "Users can't block themselves".to_string(),
+                };
+            }
+            BlockError::CannotMuteSelf => {
+                return AppErrorResponse {
+                    error_code: BlockError::CannotMuteSelf as u16,
+                    error_message: "Users can't mute themselves".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<ModerationError> for AppErrorResponse {
+    fn from(value: ModerationError) -> AppErrorResponse {
+        match value {
+            ModerationError::GenericError => {
+                return AppErrorResponse {
+                    error_code: ModerationError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            ModerationError::NotModerator => {
+                return AppErrorResponse {
+                    error_code: ModerationError::NotModerator as u16,
+                    error_message: "Only moderators can do this".to_string(),
+                };
+            }
+            ModerationError::PostNotFound => {
+                return AppErrorResponse {
+                    error_code: ModerationError::PostNotFound as u16,
+                    error_message: "Post not found".to_string(),
+                };
+            }
+            ModerationError::UserNotFound => {
+                return AppErrorResponse {
+                    error_code: ModerationError::UserNotFound as u16,
+                    error_message: "User not found".to_string(),
+                };
+            }
+            ModerationError::ReportNotFound => {
+                return AppErrorResponse {
+                    error_code: ModerationError::ReportNotFound as u16,
+                    error_message: "Report not found".to_string(),
+                };
+            }
+            ModerationError::InvalidReportTarget => {
+                return AppErrorResponse {
+                    error_code: ModerationError::InvalidReportTarget as u16,
+                    error_message: "Report either a post or a user".to_string(),
+                };
+            }
+            ModerationError::CannotReportSelf => {
+                return AppErrorResponse {
+                    error_code: ModerationError::CannotReportSelf as u16,
+                    error_message: "Users can't report themselves".to_string(),
+                };
+            }
+            ModerationError::DetailsTooLong => {
+                return AppErrorResponse {
+                    error_code: ModerationError::DetailsTooLong as u16,
+                    error_message: "Details are too long".to_string(),
+                };
+            }
+            ModerationError::ReasonMissing => {
+                return AppErrorResponse {
+                    error_code: ModerationError::ReasonMissing as u16,
+                    error_message: "A reason is required".to_string(),
+                };
+            }
+            ModerationError::ActionNotApplicable => {
+                return AppErrorResponse {
+                    error_code: ModerationError::ActionNotApplicable as u16,
+                    error_message: "Action doesn't apply to this report".to_string(),
+                };
+            }
+            ModerationError::ReportAlreadyResolved => {
+                return AppErrorResponse {
+                    error_code: ModerationError::ReportAlreadyResolved as u16,
+                    error_message: "Report was already resolved".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<MediaError> for AppErrorResponse {
+    fn from(value: MediaError) -> AppErrorResponse {
+        match value {
+            MediaError::GenericError => {
+                return AppErrorResponse {
+                    error_code: MediaError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            MediaError::AttachmentNotFound => {
+                return AppErrorResponse {
+                    error_code: MediaError::AttachmentNotFound as u16,
+                    error_message: "Attachment not found".to_string(),
+                };
+            }
+            MediaError::FileMissing => {
+                return AppErrorResponse {
+                    error_code: MediaError::FileMissing as u16,
+                    error_message: "No file was uploaded".to_string(),
+                };
+            }
+            MediaError::FileTooLarge => {
+                return AppErrorResponse {
+                    error_code: MediaError::FileTooLarge as u16,
+                    error_message: "File is too large".to_string(),
+                };
+            }
+            MediaError::UnsupportedMediaType => {
+                return AppErrorResponse {
+                    error_code: MediaError::UnsupportedMediaType as u16,
+                    error_message: "File type is not supported".to_string(),
+                };
+            }
+            MediaError::QuotaExceeded => {
+                return AppErrorResponse {
+                    error_code: MediaError::QuotaExceeded as u16,
+                    error_message: "Upload quota exceeded".to_string(),
+                };
+            }
+            MediaError::AttachmentInUse => {
+                return AppErrorResponse {
+                    error_code: MediaError::AttachmentInUse as u16,
+                    error_message: "Attachment is still used by a post or profile".to_string(),
+                };
+            }
+            MediaError::AttachmentProcessing => {
+                return AppErrorResponse {
+                    error_code: MediaError::AttachmentProcessing as u16,
+                    error_message: "Attachment is still being processed".to_string(),
+                };
+            }
+            MediaError::AttachmentUnprocessable => {
+                return AppErrorResponse {
+                    error_code: MediaError::AttachmentUnprocessable as u16,
+                    error_message: "Attachment could not be processed".to_string(),
+                };
+            }
+            MediaError::VariantNotFound => {
+                return AppErrorResponse {
+                    error_code: MediaError::VariantNotFound as u16,
+                    error_message: "Variant not found".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<PollError> for AppErrorResponse {
+    fn from(value: PollError) -> Self {
+        match value {
+            PollError::GenericError => {
+                return AppErrorResponse {
+                    error_code: PollError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            PollError::PollNotFound => {
+                return AppErrorResponse {
+                    error_code: PollError::PollNotFound as u16,
+                    error_message: "Poll not found".to_string(),
+                };
+            }
+            PollError::PollClosed => {
+                return AppErrorResponse {
+                    error_code: PollError::PollClosed as u16,
+                    error_message: "Poll is closed".to_string(),
+                };
+            }
+            PollError::AlreadyVoted => {
+                return AppErrorResponse {
+                    error_code: PollError::AlreadyVoted as u16,
+                    error_message: "Already voted in this poll".to_string(),
+                };
+            }
+            PollError::InvalidChoice => {
+                return AppErrorResponse {
+                    error_code: PollError::InvalidChoice as u16,
+                    error_message: "Invalid poll choice".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<ProfileError> for AppErrorResponse {
+    fn from(value: ProfileError) -> AppErrorResponse {
+        match value {
+            ProfileError::GenericError => {
+                return AppErrorResponse {
+                    error_code: ProfileError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            ProfileError::UserNotFound => {
+                return AppErrorResponse {
+                    error_code: ProfileError::UserNotFound as u16,
+                    error_message: "User not found".to_string(),
+                };
+            }
+            ProfileError::InvalidProfile => {
+                return AppErrorResponse {
+                    error_code: ProfileError::InvalidProfile as u16,
+                    error_message: "Profile field too long or invalid link".to_string(),
+                };
+            }
+            ProfileError::AvatarNotFound => {
+                return AppErrorResponse {
+                    error_code: ProfileError::AvatarNotFound as u16,
+                    error_message: "Avatar must be an own uploaded image".to_string(),
+                };
+            }
+        }
+    }
+}
+
+impl From<AnalyticsError> for AppErrorResponse {
+    fn from(value: AnalyticsError) -> AppErrorResponse {
+        match value {
+            AnalyticsError::GenericError => {
+                return AppErrorResponse {
+                    error_code: AnalyticsError::GenericError as u16,
+                    error_message: "Unknown generic error".to_string(),
+                };
+            }
+            AnalyticsError::PostNotFound => {
+                return AppErrorResponse {
+                    error_code: AnalyticsError::PostNotFound as u16,
+                    error_message: "Post not found".to_string(),
+                };
+            }
         }
     }
 }
@@ -0,0 +1,250 @@
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use actix_web::{
+    get,
+    http::{header, StatusCode},
+    rt::time::{interval, Interval},
+    web::{self, Bytes},
+    HttpRequest, HttpResponse, ResponseError,
+};
+use derive_more::Display;
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    post_db_service::Post,
+    search_db_service::SearchDbService,
+    social_db_service::{SocialDbError, SocialDbService, StreamEvent, StreamEventKind},
+};
+
+use super::{
+    error_response::AppErrorResponse, search::PublicPostResponse,
+    user_auth_token_extractor::UserAuthentication,
+};
+
+/// How often an open stream looks for new events in the log.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Polls without events after which a comment is sent, so proxies keep the connection open.
+const KEEP_ALIVE_POLLS: u32 = 15;
+const EVENT_BATCH_SIZE: u32 = 100;
+/// Delay in milliseconds clients wait before reconnecting after the stream ended.
+const RECONNECT_DELAY: u32 = 3000;
+
+#[derive(Serialize, Debug, Display)]
+pub enum EventError {
+    GenericError = 20601,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct EventStreamQuery {
+    /// resume after this event, for clients which can't send the `Last-Event-ID` header
+    last_event_id: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PostDeletedEvent {
+    pub post_uuid: String,
+}
+
+struct EventStreamState {
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    user_uuid: String,
+    last_event_id: i64,
+    pending: VecDeque<Bytes>,
+    poll: Interval,
+    idle_polls: u32,
+}
+
+impl ResponseError for EventError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            EventError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            EventError::GenericError => {
+                HttpResponse::build(status).json(AppErrorResponse::from(EventError::GenericError))
+            }
+        }
+    }
+}
+
+impl From<SocialDbError> for EventError {
+    fn from(_value: SocialDbError) -> Self {
+        EventError::GenericError
+    }
+}
+
+/// Appends an event to the logs of the recipients, open streams pick it up on their next
+/// poll. Like the other derived data failures are only logged.
+pub fn publish_event<T: Serialize>(
+    social_db_state: &Mutex<SocialDbService>,
+    recipient_uuids: &[String],
+    kind: StreamEventKind,
+    data: &T,
+) {
+    if recipient_uuids.is_empty() {
+        return;
+    }
+
+    let data = match serde_json::to_string(data) {
+        Ok(data) => data,
+        Err(err) => {
+            log::error!(
+                "events, serializing {} event failed {:?}",
+                kind.as_str(),
+                err
+            );
+            return;
+        }
+    };
+    if let Err(err) =
+        social_db_state
+            .lock()
+            .unwrap()
+            .add_stream_events(recipient_uuids, kind, &data)
+    {
+        log::error!("events, adding {} event failed {:?}", kind.as_str(), err);
+    }
+}
+
+/// Tells the followers of `user_uuid` about a new public post. Has to run after the post
+/// was put into the search index, the event carries the indexed post.
+pub fn publish_new_post(
+    search_db_state: &Mutex<SearchDbService>,
+    social_db_state: &Mutex<SocialDbService>,
+    user_uuid: &str,
+    post: &Post,
+) {
+    if !post.is_public() {
+        return;
+    }
+
+    let public_post = match search_db_state
+        .lock()
+        .unwrap()
+        .get_posts(std::slice::from_ref(&post.uuid))
+    {
+        Ok(mut posts) => posts.pop(),
+        Err(err) => {
+            log::error!("events, post lookup failed {:?}", err);
+            return;
+        }
+    };
+    let Some(public_post) = public_post else {
+        return;
+    };
+    let follower_uuids = match social_db_state
+        .lock()
+        .unwrap()
+        .get_follower_uuids(user_uuid)
+    {
+        Ok(follower_uuids) => follower_uuids,
+        Err(err) => {
+            log::error!("events, follower lookup failed {:?}", err);
+            return;
+        }
+    };
+
+    publish_event(
+        social_db_state,
+        &follower_uuids,
+        StreamEventKind::Post,
+        &PublicPostResponse::from(public_post),
+    );
+}
+
+/// `data` is a single line, serde_json escapes the line breaks inside strings.
+fn format_event(event: &StreamEvent) -> Bytes {
+    Bytes::from(format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        event.id,
+        event.kind.as_str(),
+        event.data
+    ))
+}
+
+/// Server-Sent Events stream of the user: new posts of followed users, notifications, direct
+/// messages, and edits and deletes of the own posts. Every event has an id, a client
+/// reconnecting with the `Last-Event-ID` header or `lastEventId` query receives the events
+/// it missed, as long as they are in the log. A fresh connection only receives new events.
+#[get("/events")]
+async fn user_events(
+    user_auth: UserAuthentication,
+    req: HttpRequest,
+    query: web::Query<EventStreamQuery>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<HttpResponse, EventError> {
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<i64>().ok())
+        .or(query.last_event_id);
+    log::info!("/events {:?}", last_event_id);
+
+    let last_event_id = match last_event_id {
+        Some(last_event_id) => last_event_id,
+        None => social_db_state
+            .lock()
+            .unwrap()
+            .last_stream_event_id(&user_auth.uuid)?,
+    };
+
+    let state = EventStreamState {
+        social_db_state,
+        user_uuid: user_auth.uuid,
+        last_event_id,
+        pending: VecDeque::from([Bytes::from(format!("retry: {}\n\n", RECONNECT_DELAY))]),
+        poll: interval(POLL_INTERVAL),
+        idle_polls: 0,
+    };
+    let events = stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(chunk) = state.pending.pop_front() {
+                return Some((Ok::<_, actix_web::Error>(chunk), state));
+            }
+
+            state.poll.tick().await;
+            let events = state.social_db_state.lock().unwrap().get_stream_events(
+                &state.user_uuid,
+                state.last_event_id,
+                EVENT_BATCH_SIZE,
+            );
+            match events {
+                Ok(events) if events.is_empty() => {
+                    state.idle_polls += 1;
+                    if state.idle_polls >= KEEP_ALIVE_POLLS {
+                        state.idle_polls = 0;
+                        state
+                            .pending
+                            .push_back(Bytes::from_static(b": keep-alive\n\n"));
+                    }
+                }
+                Ok(events) => {
+                    state.idle_polls = 0;
+                    for event in events {
+                        state.last_event_id = event.id;
+                        state.pending.push_back(format_event(&event));
+                    }
+                }
+                Err(err) => {
+                    // ends the stream, the client reconnects with the last event id it got
+                    log::error!("events, reading events failed {:?}", err);
+                    return None;
+                }
+            }
+        }
+    });
+
+    return Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "text/event-stream"))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events));
+}
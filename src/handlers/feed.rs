@@ -0,0 +1,646 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use actix_web::{
+    get,
+    http::header::{self, CacheControl, CacheDirective, EntityTag, Header, HttpDate, IfNoneMatch},
+    http::StatusCode,
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use chrono::{DateTime, Utc};
+use derive_more::Display;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Serialize;
+
+use crate::services::{
+    env_settings::EnvSettings,
+    post_db_service::{normalize_tag, Post, PostDbError, PostDbService, PostFilter, Visibility},
+    post_renderer::{render_html, ContentFormat},
+    search_db_service::{PublicPost, PublicPostFilter, SearchDbError, SearchDbService},
+    user_db_service::{User, UserDbService},
+};
+
+use super::{
+    blog::{find_author, BlogError},
+    error_response::AppErrorResponse,
+};
+
+/// Number of newest posts a feed contains.
+const FEED_SIZE: u32 = 20;
+const FEED_MAX_AGE_SECONDS: u32 = 300;
+
+/// Characters left as they are in a url path segment.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+#[derive(Serialize, Debug, Display)]
+pub enum FeedError {
+    GenericError = 30101,
+    UserNotFound,
+    FeedNotFound,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    /// `format` is the extension of the requested feed file, `rss`, `atom` or `json`.
+    fn from_extension(format: &str) -> Result<Self, FeedError> {
+        match format {
+            "rss" | "xml" => Ok(FeedFormat::Rss),
+            "atom" => Ok(FeedFormat::Atom),
+            "json" => Ok(FeedFormat::Json),
+            _ => Err(FeedError::FeedNotFound),
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FeedEntry {
+    post_uuid: String,
+    title: String,
+    link: String,
+    html: String,
+    author: String,
+    tags: Vec<String>,
+    created_at: i64,
+    updated_at: i64,
+}
+
+#[derive(Debug)]
+struct Feed {
+    title: String,
+    description: String,
+    home_page_url: String,
+    feed_url: String,
+    entries: Vec<FeedEntry>,
+}
+
+/// JSON Feed 1.1, the spec names its fields in snake_case.
+#[derive(Serialize, Debug)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    home_page_url: &'a str,
+    feed_url: &'a str,
+    description: &'a str,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+struct JsonFeedItem<'a> {
+    id: &'a str,
+    url: &'a str,
+    title: &'a str,
+    content_html: &'a str,
+    date_published: String,
+    date_modified: String,
+    authors: Vec<JsonFeedAuthor<'a>>,
+    tags: &'a [String],
+}
+
+#[derive(Serialize, Debug)]
+struct JsonFeedAuthor<'a> {
+    name: &'a str,
+}
+
+impl ResponseError for FeedError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            FeedError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            FeedError::UserNotFound => StatusCode::NOT_FOUND,
+            FeedError::FeedNotFound => StatusCode::NOT_FOUND,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            FeedError::GenericError => {
+                HttpResponse::build(status).json(AppErrorResponse::from(FeedError::GenericError))
+            }
+            FeedError::UserNotFound => {
+                HttpResponse::build(status).json(AppErrorResponse::from(FeedError::UserNotFound))
+            }
+            FeedError::FeedNotFound => {
+                HttpResponse::build(status).json(AppErrorResponse::from(FeedError::FeedNotFound))
+            }
+        }
+    }
+}
+
+impl From<BlogError> for FeedError {
+    fn from(value: BlogError) -> Self {
+        match value {
+            BlogError::UserNotFound => FeedError::UserNotFound,
+            _ => FeedError::GenericError,
+        }
+    }
+}
+
+impl From<PostDbError> for FeedError {
+    fn from(_value: PostDbError) -> Self {
+        FeedError::GenericError
+    }
+}
+
+impl From<SearchDbError> for FeedError {
+    fn from(_value: SearchDbError) -> Self {
+        FeedError::GenericError
+    }
+}
+
+fn path_segment(value: &str) -> String {
+    return utf8_percent_encode(value, PATH_SEGMENT).to_string();
+}
+
+/// Link of a post, posts without a slug only have their uuid url.
+fn permalink(
+    base_url: &str,
+    display_name: &str,
+    author_uuid: &str,
+    slug: &str,
+    post_uuid: &str,
+) -> String {
+    if slug.is_empty() {
+        return format!(
+            "{}/users/{}/posts/{}",
+            base_url,
+            path_segment(author_uuid),
+            path_segment(post_uuid)
+        );
+    }
+
+    return format!(
+        "{}/{}/{}",
+        base_url,
+        path_segment(display_name),
+        path_segment(slug)
+    );
+}
+
+/// Html of a post indexed before the search index kept it, taken from the author's copy of the
+/// post so it is rendered in the format it was written in.
+fn stored_post_html(db_collection_path: &str, post: &PublicPost) -> String {
+    if PostDbService::exists(db_collection_path, &post.author_uuid) {
+        let stored = PostDbService::connect(db_collection_path, &post.author_uuid)
+            .and_then(|post_db_service| post_db_service.get_post(&post.post_uuid));
+        match stored {
+            Ok(stored) if !stored.post_html.is_empty() => return stored.post_html,
+            Ok(stored) => return render_html(stored.format, &stored.post),
+            Err(err) => log::error!("{:?}", err),
+        }
+    }
+
+    return render_html(ContentFormat::Plain, &post.post);
+}
+
+fn timestamp_to_datetime(timestamp: i64) -> DateTime<Utc> {
+    return DateTime::from_timestamp(timestamp, 0).unwrap_or_default();
+}
+
+impl FeedEntry {
+    fn from_post(base_url: &str, author: &User, post: Post) -> Self {
+        Self {
+            link: permalink(
+                base_url,
+                &author.display_name,
+                &author.uuid,
+                &post.slug,
+                &post.uuid,
+            ),
+            post_uuid: post.uuid,
+            title: post.title,
+            html: post.post_html,
+            author: author.display_name.clone(),
+            tags: post.tags,
+            created_at: post.created_at,
+            updated_at: post.updated_at,
+        }
+    }
+
+    fn from_public_post(env_settings: &EnvSettings, post: PublicPost) -> Self {
+        // posts indexed before the html was kept in the search index only have their source
+        let html = if post.post_html.is_empty() {
+            stored_post_html(&env_settings.db_collection_path, &post)
+        } else {
+            post.post_html
+        };
+
+        Self {
+            link: permalink(
+                &env_settings.public_base_url,
+                &post.author_display_name,
+                &post.author_uuid,
+                &post.slug,
+                &post.post_uuid,
+            ),
+            post_uuid: post.post_uuid,
+            title: post.title,
+            html,
+            author: post.author_display_name,
+            tags: post.tags,
+            created_at: post.created_at,
+            updated_at: post.updated_at,
+        }
+    }
+}
+
+impl Feed {
+    fn last_modified(&self) -> i64 {
+        return self
+            .entries
+            .iter()
+            .map(|entry| entry.updated_at)
+            .max()
+            .unwrap_or(0);
+    }
+
+    fn to_rss(&self) -> String {
+        let items: Vec<rss::Item> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut guid = rss::Guid::default();
+                guid.set_value(format!("urn:uuid:{}", entry.post_uuid));
+                guid.set_permalink(false);
+
+                let mut item = rss::Item::default();
+                item.set_title(entry.title.clone());
+                item.set_link(entry.link.clone());
+                item.set_guid(guid);
+                item.set_pub_date(timestamp_to_datetime(entry.created_at).to_rfc2822());
+                item.set_content(entry.html.clone());
+                item.set_categories(
+                    entry
+                        .tags
+                        .iter()
+                        .map(|tag| rss::Category {
+                            name: tag.clone(),
+                            domain: None,
+                        })
+                        .collect::<Vec<_>>(),
+                );
+                return item;
+            })
+            .collect();
+
+        let mut channel = rss::Channel::default();
+        channel.set_title(self.title.clone());
+        channel.set_link(self.home_page_url.clone());
+        channel.set_description(self.description.clone());
+        channel.set_last_build_date(timestamp_to_datetime(self.last_modified()).to_rfc2822());
+        channel.set_namespaces(BTreeMap::from([(
+            "content".to_string(),
+            "http://purl.org/rss/1.0/modules/content/".to_string(),
+        )]));
+        channel.set_items(items);
+
+        return channel.to_string();
+    }
+
+    fn to_atom(&self) -> String {
+        let entries: Vec<atom_syndication::Entry> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut link = atom_syndication::Link::default();
+                link.set_href(entry.link.clone());
+                link.set_rel("alternate");
+
+                let mut author = atom_syndication::Person::default();
+                author.set_name(entry.author.clone());
+
+                let mut content = atom_syndication::Content::default();
+                content.set_value(entry.html.clone());
+                content.set_content_type("html".to_string());
+
+                let mut atom_entry = atom_syndication::Entry::default();
+                atom_entry.set_id(format!("urn:uuid:{}", entry.post_uuid));
+                atom_entry.set_title(entry.title.clone());
+                atom_entry.set_links(vec![link]);
+                atom_entry.set_authors(vec![author]);
+                atom_entry.set_published(timestamp_to_datetime(entry.created_at).fixed_offset());
+                atom_entry.set_updated(timestamp_to_datetime(entry.updated_at).fixed_offset());
+                atom_entry.set_content(content);
+                atom_entry.set_categories(
+                    entry
+                        .tags
+                        .iter()
+                        .map(|tag| {
+                            let mut category = atom_syndication::Category::default();
+                            category.set_term(tag.clone());
+                            return category;
+                        })
+                        .collect::<Vec<_>>(),
+                );
+                return atom_entry;
+            })
+            .collect();
+
+        let mut alternate = atom_syndication::Link::default();
+        alternate.set_href(self.home_page_url.clone());
+        alternate.set_rel("alternate");
+        let mut this = atom_syndication::Link::default();
+        this.set_href(self.feed_url.clone());
+        this.set_rel("self");
+
+        let mut feed = atom_syndication::Feed::default();
+        feed.set_id(self.feed_url.clone());
+        feed.set_title(self.title.clone());
+        feed.set_subtitle(atom_syndication::Text::from(self.description.clone()));
+        feed.set_links(vec![alternate, this]);
+        feed.set_updated(timestamp_to_datetime(self.last_modified()).fixed_offset());
+        feed.set_entries(entries);
+
+        return feed.to_string();
+    }
+
+    fn to_json(&self) -> Result<String, FeedError> {
+        let json_feed = JsonFeed {
+            version: "https://jsonfeed.org/version/1.1",
+            title: &self.title,
+            home_page_url: &self.home_page_url,
+            feed_url: &self.feed_url,
+            description: &self.description,
+            items: self
+                .entries
+                .iter()
+                .map(|entry| JsonFeedItem {
+                    id: &entry.post_uuid,
+                    url: &entry.link,
+                    title: &entry.title,
+                    content_html: &entry.html,
+                    date_published: timestamp_to_datetime(entry.created_at).to_rfc3339(),
+                    date_modified: timestamp_to_datetime(entry.updated_at).to_rfc3339(),
+                    authors: vec![JsonFeedAuthor {
+                        name: &entry.author,
+                    }],
+                    tags: &entry.tags,
+                })
+                .collect(),
+        };
+
+        return serde_json::to_string(&json_feed).map_err(|err| {
+            log::error!("{:?}", err);
+            FeedError::GenericError
+        });
+    }
+}
+
+/// Only `If-None-Match` is answered: the newest `updated_at` doesn't move when a post is deleted,
+/// hidden or made private, so `If-Modified-Since` could keep a removed post in a reader's feed.
+fn is_not_modified(req: &HttpRequest, etag: &EntityTag) -> bool {
+    return match IfNoneMatch::parse(req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(etags)) => etags.iter().any(|other| other.weak_eq(etag)),
+        Err(_) => false,
+    };
+}
+
+/// Renders the feed, or answers `304 Not Modified` when the client already has it.
+fn feed_response(
+    req: &HttpRequest,
+    feed: &Feed,
+    format: FeedFormat,
+) -> Result<HttpResponse, FeedError> {
+    let body = match format {
+        FeedFormat::Rss => feed.to_rss(),
+        FeedFormat::Atom => feed.to_atom(),
+        FeedFormat::Json => feed.to_json()?,
+    };
+
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let etag = EntityTag::new_strong(format!("{:016x}", hasher.finish()));
+    let last_modified =
+        SystemTime::UNIX_EPOCH + Duration::from_secs(feed.last_modified().max(0) as u64);
+
+    let not_modified = is_not_modified(req, &etag);
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(header::ETag(etag))
+        .insert_header(header::LastModified(HttpDate::from(last_modified)))
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(FEED_MAX_AGE_SECONDS),
+        ]));
+
+    if not_modified {
+        return Ok(response.finish());
+    }
+    return Ok(response.content_type(format.content_type()).body(body));
+}
+
+fn user_feed(
+    env_settings: &EnvSettings,
+    user_db_service: &UserDbService,
+    user: &str,
+    tag: Option<String>,
+    format: &str,
+) -> Result<Feed, FeedError> {
+    let author = find_author(user_db_service, user)?;
+    let base_url = &env_settings.public_base_url;
+    let author_segment = path_segment(&author.display_name);
+
+    let mut feed = match &tag {
+        Some(tag) => Feed {
+            title: format!("{} #{}", author.display_name, tag),
+            description: format!("Posts of {} tagged #{}", author.display_name, tag),
+            home_page_url: format!(
+                "{}/users/{}/posts?tags={}",
+                base_url,
+                author_segment,
+                path_segment(tag)
+            ),
+            feed_url: format!(
+                "{}/users/{}/tags/{}/feed.{}",
+                base_url,
+                author_segment,
+                path_segment(tag),
+                format
+            ),
+            entries: Vec::new(),
+        },
+        None => Feed {
+            title: author.display_name.clone(),
+            description: format!("Posts of {}", author.display_name),
+            home_page_url: format!("{}/users/{}/posts", base_url, author_segment),
+            feed_url: format!("{}/users/{}/feed.{}", base_url, author_segment, format),
+            entries: Vec::new(),
+        },
+    };
+
+    if !PostDbService::exists(&env_settings.db_collection_path, &author.uuid) {
+        return Ok(feed);
+    }
+
+    let filter = PostFilter {
+        tags: tag.into_iter().collect(),
+        visibilities: vec![Visibility::Public],
+        exclude_hidden: true,
+        ..PostFilter::default()
+    };
+    let post_db_service = PostDbService::connect(&env_settings.db_collection_path, &author.uuid)?;
+    feed.entries = post_db_service
+        .get_posts(&filter, FEED_SIZE, 0)?
+        .into_iter()
+        .map(|post| FeedEntry::from_post(base_url, &author, post))
+        .collect();
+
+    return Ok(feed);
+}
+
+fn site_feed(
+    env_settings: &EnvSettings,
+    search_db_service: &SearchDbService,
+    tag: Option<String>,
+    format: &str,
+) -> Result<Feed, FeedError> {
+    let base_url = &env_settings.public_base_url;
+
+    let mut feed = match &tag {
+        Some(tag) => Feed {
+            title: format!("#{}", tag),
+            description: format!("Public posts tagged #{}", tag),
+            home_page_url: format!("{}/search?tag={}", base_url, path_segment(tag)),
+            feed_url: format!("{}/tags/{}/feed.{}", base_url, path_segment(tag), format),
+            entries: Vec::new(),
+        },
+        None => Feed {
+            title: "All posts".to_string(),
+            description: "Public posts of every blog".to_string(),
+            home_page_url: format!("{}/search", base_url),
+            feed_url: format!("{}/feed.{}", base_url, format),
+            entries: Vec::new(),
+        },
+    };
+
+    let filter = PublicPostFilter {
+        tag,
+        ..PublicPostFilter::default()
+    };
+    let (hits, _total) = search_db_service.search(&filter, FEED_SIZE, 0)?;
+    feed.entries = hits
+        .into_iter()
+        .map(|hit| FeedEntry::from_public_post(env_settings, hit.post))
+        .collect();
+
+    return Ok(feed);
+}
+
+/// Feed of the newest public posts of a blog, `format` is `rss`, `atom` or `json`.
+#[get("/{user}/feed.{format}")]
+async fn blog_feed(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+) -> Result<HttpResponse, FeedError> {
+    let (user, format) = path.into_inner();
+    log::info!("/users/{}/feed.{}", user, format);
+
+    let feed_format = FeedFormat::from_extension(&format)?;
+    let feed = user_feed(
+        &env_settings,
+        &user_db_state.lock().unwrap(),
+        &user,
+        None,
+        &format,
+    )?;
+
+    return feed_response(&req, &feed, feed_format);
+}
+
+/// Feed of the newest public posts of a blog with the given tag.
+#[get("/{user}/tags/{tag}/feed.{format}")]
+async fn blog_tag_feed(
+    req: HttpRequest,
+    path: web::Path<(String, String, String)>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+) -> Result<HttpResponse, FeedError> {
+    let (user, tag, format) = path.into_inner();
+    log::info!("/users/{}/tags/{}/feed.{}", user, tag, format);
+
+    let feed_format = FeedFormat::from_extension(&format)?;
+    let tag = normalize_tag(&tag).ok_or(FeedError::FeedNotFound)?;
+    let feed = user_feed(
+        &env_settings,
+        &user_db_state.lock().unwrap(),
+        &user,
+        Some(tag),
+        &format,
+    )?;
+
+    return feed_response(&req, &feed, feed_format);
+}
+
+/// Feed of the newest public posts of all blogs.
+#[get("/feed.{format}")]
+async fn site_feed_all(
+    req: HttpRequest,
+    path: web::Path<String>,
+    env_settings: web::Data<EnvSettings>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+) -> Result<HttpResponse, FeedError> {
+    let format = path.into_inner();
+    log::info!("/feed.{}", format);
+
+    let feed_format = FeedFormat::from_extension(&format)?;
+    let feed = site_feed(
+        &env_settings,
+        &search_db_state.lock().unwrap(),
+        None,
+        &format,
+    )?;
+
+    return feed_response(&req, &feed, feed_format);
+}
+
+/// Feed of the newest public posts of all blogs with the given tag.
+#[get("/tags/{tag}/feed.{format}")]
+async fn site_tag_feed(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    env_settings: web::Data<EnvSettings>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+) -> Result<HttpResponse, FeedError> {
+    let (tag, format) = path.into_inner();
+    log::info!("/tags/{}/feed.{}", tag, format);
+
+    let feed_format = FeedFormat::from_extension(&format)?;
+    let tag = normalize_tag(&tag).ok_or(FeedError::FeedNotFound)?;
+    let feed = site_feed(
+        &env_settings,
+        &search_db_state.lock().unwrap(),
+        Some(tag),
+        &format,
+    )?;
+
+    return feed_response(&req, &feed, feed_format);
+}
@@ -0,0 +1,89 @@
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::services::{
+    link_preview_db_service::{LinkPreview, LinkPreviewDbService},
+    post_db_service::Post,
+};
+
+/// Only the first links of a post get a preview.
+pub const MAX_PREVIEWED_LINKS: usize = 4;
+/// Longer urls are left without a preview.
+const MAX_PREVIEWED_URL_LENGTH: usize = 2000;
+
+/// The card a client shows for a link in a post.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkPreviewResponse {
+    url: String,
+    title: String,
+    description: Option<String>,
+    /// absolute url of an image chosen by the linked page, not checked by the api
+    image_url: Option<String>,
+    site_name: Option<String>,
+}
+
+impl From<LinkPreview> for LinkPreviewResponse {
+    fn from(value: LinkPreview) -> Self {
+        Self {
+            url: value.url,
+            title: value.title,
+            description: value.description,
+            image_url: value.image_url,
+            site_name: value.site_name,
+        }
+    }
+}
+
+/// The links of a post which get a preview, in the order they appear.
+pub fn previewed_links(links: &[String]) -> Vec<String> {
+    links
+        .iter()
+        .filter(|url| url.len() <= MAX_PREVIEWED_URL_LENGTH)
+        .take(MAX_PREVIEWED_LINKS)
+        .cloned()
+        .collect()
+}
+
+/// The previews fetched for `links`, in the order of the links. Links without a preview yet
+/// are left out, failing to load them only leaves them all out.
+pub fn find_link_previews(
+    link_preview_db_service: &LinkPreviewDbService,
+    links: &[String],
+) -> Vec<LinkPreviewResponse> {
+    let previews = match link_preview_db_service.get_previews(links) {
+        Ok(previews) => previews,
+        Err(err) => {
+            log::error!("link preview, loading previews failed {:?}", err);
+            return Vec::new();
+        }
+    };
+    return links
+        .iter()
+        .filter_map(|url| previews.iter().find(|preview| &preview.url == url))
+        .cloned()
+        .map(LinkPreviewResponse::from)
+        .collect();
+}
+
+/// Queues the links of a post for the preview worker. Links in private posts aren't
+/// fetched, the linked sites would learn about them. The post is already saved at this
+/// point, failures are only logged.
+pub fn request_link_previews(link_preview_db_state: &Mutex<LinkPreviewDbService>, post: &Post) {
+    if !post.is_readable_by_others() {
+        return;
+    }
+
+    let links = previewed_links(&post.links);
+    if links.is_empty() {
+        return;
+    }
+    if let Err(err) = link_preview_db_state
+        .lock()
+        .unwrap()
+        .request_previews(&links)
+    {
+        log::error!("link preview, queueing links failed {:?}", err);
+    }
+}
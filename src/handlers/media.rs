@@ -0,0 +1,563 @@
+use std::sync::Mutex;
+
+use actix_multipart::Multipart;
+use actix_web::{
+    error::BlockingError,
+    get,
+    http::{header, StatusCode},
+    post, web, HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use derive_more::Display;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    env_settings::EnvSettings,
+    media_db_service::{
+        Attachment, AttachmentVariant, MediaDbError, MediaDbService, ProcessingState,
+    },
+    media_processor::remove_unused,
+    media_storage::MediaStorage,
+    post_db_service::{PostDbError, PostDbService},
+    user_db_service::{UserDbError, UserDbService},
+};
+
+use super::{
+    error_response::AppErrorResponse,
+    search::{page_params, PageQuery},
+    user_auth_token_extractor::UserAuthentication,
+};
+
+const MAX_IMAGE_SIZE: u64 = 10 * 1024 * 1024;
+const MAX_FILE_SIZE: u64 = 25 * 1024 * 1024;
+/// Bytes of uploads a user can keep, deleting uploads frees their share again.
+const USER_QUOTA: i64 = 200 * 1024 * 1024;
+const MAX_FILE_NAME_LENGTH: usize = 200;
+
+/// Images are shown inline, everything else is served as a download.
+pub const IMAGE_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
+const FILE_TYPES: &[&str] = &[
+    "application/pdf",
+    "video/mp4",
+    "video/webm",
+    "audio/mpeg",
+    "audio/ogg",
+];
+
+/// Stored content never changes, clients and proxies can keep it for a year.
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+#[derive(Serialize, Debug, Display)]
+pub enum MediaError {
+    GenericError = 21001,
+    AttachmentNotFound,
+    FileMissing,
+    FileTooLarge,
+    UnsupportedMediaType,
+    QuotaExceeded,
+    AttachmentInUse,
+    AttachmentProcessing,
+    AttachmentUnprocessable,
+    VariantNotFound,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DeleteMediaRequest {
+    attachment_uuid: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentResponse {
+    uuid: String,
+    mime_type: String,
+    file_name: String,
+    size: i64,
+    /// where the content can be downloaded
+    url: String,
+    created_at: i64,
+    /// `none` for files, images go from `pending` to `ready` or `failed`
+    processing: &'static str,
+    width: Option<u32>,
+    height: Option<u32>,
+    blurhash: Option<String>,
+    variants: Vec<AttachmentVariantResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AttachmentVariantResponse {
+    name: String,
+    mime_type: String,
+    width: u32,
+    height: u32,
+    size: i64,
+    url: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MediaListResponse {
+    page: u32,
+    page_size: u32,
+    total: i64,
+    used_bytes: i64,
+    quota_bytes: i64,
+    attachments: Vec<AttachmentResponse>,
+}
+
+impl ResponseError for MediaError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            MediaError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            MediaError::AttachmentNotFound => StatusCode::NOT_FOUND,
+            MediaError::FileMissing => StatusCode::BAD_REQUEST,
+            MediaError::FileTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            MediaError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            MediaError::QuotaExceeded => StatusCode::INSUFFICIENT_STORAGE,
+            MediaError::AttachmentInUse => StatusCode::CONFLICT,
+            MediaError::AttachmentProcessing => StatusCode::CONFLICT,
+            MediaError::AttachmentUnprocessable => StatusCode::UNPROCESSABLE_ENTITY,
+            MediaError::VariantNotFound => StatusCode::NOT_FOUND,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            MediaError::GenericError => {
+                HttpResponse::build(status).json(AppErrorResponse::from(MediaError::GenericError))
+            }
+            MediaError::AttachmentNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(MediaError::AttachmentNotFound)),
+            MediaError::FileMissing => {
+                HttpResponse::build(status).json(AppErrorResponse::from(MediaError::FileMissing))
+            }
+            MediaError::FileTooLarge => {
+                HttpResponse::build(status).json(AppErrorResponse::from(MediaError::FileTooLarge))
+            }
+            MediaError::UnsupportedMediaType => HttpResponse::build(status)
+                .json(AppErrorResponse::from(MediaError::UnsupportedMediaType)),
+            MediaError::QuotaExceeded => {
+                HttpResponse::build(status).json(AppErrorResponse::from(MediaError::QuotaExceeded))
+            }
+            MediaError::AttachmentInUse => HttpResponse::build(status)
+                .json(AppErrorResponse::from(MediaError::AttachmentInUse)),
+            MediaError::AttachmentProcessing => HttpResponse::build(status)
+                .json(AppErrorResponse::from(MediaError::AttachmentProcessing)),
+            MediaError::AttachmentUnprocessable => HttpResponse::build(status)
+                .json(AppErrorResponse::from(MediaError::AttachmentUnprocessable)),
+            MediaError::VariantNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(MediaError::VariantNotFound)),
+        }
+    }
+}
+
+impl From<MediaDbError> for MediaError {
+    fn from(value: MediaDbError) -> Self {
+        match value {
+            MediaDbError::AttachmentNotFound => MediaError::AttachmentNotFound,
+            MediaDbError::QuotaExceeded => MediaError::QuotaExceeded,
+            MediaDbError::GenericError => MediaError::GenericError,
+        }
+    }
+}
+
+impl From<PostDbError> for MediaError {
+    fn from(_value: PostDbError) -> Self {
+        MediaError::GenericError
+    }
+}
+
+impl From<UserDbError> for MediaError {
+    fn from(_value: UserDbError) -> Self {
+        MediaError::GenericError
+    }
+}
+
+impl From<std::io::Error> for MediaError {
+    fn from(value: std::io::Error) -> Self {
+        log::error!("media, storage failed {:?}", value);
+        MediaError::GenericError
+    }
+}
+
+impl From<BlockingError> for MediaError {
+    fn from(value: BlockingError) -> Self {
+        log::error!("media, blocking task failed {:?}", value);
+        MediaError::GenericError
+    }
+}
+
+impl From<Attachment> for AttachmentResponse {
+    fn from(value: Attachment) -> Self {
+        Self {
+            url: format!("/media/{}/file", value.uuid),
+            mime_type: value.mime_type,
+            file_name: value.file_name,
+            size: value.size,
+            created_at: value.created_at,
+            processing: value.processing.as_str(),
+            width: value.width,
+            height: value.height,
+            variants: value
+                .variants
+                .into_iter()
+                .map(|variant| AttachmentVariantResponse {
+                    url: format!("/media/{}/variants/{}", value.uuid, variant.name),
+                    name: variant.name,
+                    mime_type: variant.mime_type,
+                    width: variant.width,
+                    height: variant.height,
+                    size: variant.size,
+                })
+                .collect(),
+            uuid: value.uuid,
+            blurhash: value.blurhash,
+        }
+    }
+}
+
+/// Only content which is done processing is served, a pending image still carries its
+/// metadata.
+fn check_processed(attachment: &Attachment) -> Result<(), MediaError> {
+    match attachment.processing {
+        ProcessingState::None | ProcessingState::Ready => Ok(()),
+        ProcessingState::Pending => Err(MediaError::AttachmentProcessing),
+        ProcessingState::Failed => Err(MediaError::AttachmentUnprocessable),
+    }
+}
+
+/// Answers a matching `If-None-Match` with `304 Not Modified`, the `ETag` is the content hash.
+fn not_modified(req: &HttpRequest, hash: &str) -> Option<HttpResponse> {
+    let etag = format!("\"{}\"", hash);
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+    if !not_modified {
+        return None;
+    }
+    return Some(
+        HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .insert_header((header::CACHE_CONTROL, IMMUTABLE_CACHE_CONTROL))
+            .finish(),
+    );
+}
+
+/// Keeps the last path segment of a client supplied file name and drops characters which
+/// could break out of the `Content-Disposition` header.
+fn clean_file_name(file_name: Option<&str>) -> String {
+    let file_name = file_name
+        .and_then(|name| name.rsplit(['/', '\\']).next())
+        .unwrap_or("")
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_FILE_NAME_LENGTH)
+        .collect::<String>();
+    let file_name = file_name.trim();
+
+    if file_name.is_empty() {
+        return "upload".to_owned();
+    }
+    return file_name.to_owned();
+}
+
+/// Uploads an image or file as multipart form data in the `file` field. The type is sniffed
+/// from the content, the `Content-Type` sent by the client is ignored. Uploading the same
+/// content again stores it only once, but counts against the quota each time.
+#[post("/media")]
+async fn user_upload_media(
+    user_auth: UserAuthentication,
+    mut payload: Multipart,
+    media_storage: web::Data<MediaStorage>,
+    media_db_state: web::Data<Mutex<MediaDbService>>,
+) -> Result<impl Responder, MediaError> {
+    log::info!("/media");
+
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|_| MediaError::FileMissing)?;
+        if field.name() != Some("file") {
+            continue;
+        }
+        let file_name = clean_file_name(
+            field
+                .content_disposition()
+                .and_then(|content_disposition| content_disposition.get_filename()),
+        );
+
+        let mut upload = media_storage.begin_upload()?;
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|_| MediaError::FileMissing)?;
+            if upload.size() + chunk.len() as u64 > MAX_FILE_SIZE {
+                return Err(MediaError::FileTooLarge);
+            }
+            upload.write(&chunk)?;
+        }
+        if upload.size() == 0 {
+            return Err(MediaError::FileMissing);
+        }
+
+        let mime_type = infer::get(upload.head())
+            .map(|kind| kind.mime_type())
+            .filter(|mime_type| IMAGE_TYPES.contains(mime_type) || FILE_TYPES.contains(mime_type))
+            .ok_or(MediaError::UnsupportedMediaType)?;
+        if IMAGE_TYPES.contains(&mime_type) && upload.size() > MAX_IMAGE_SIZE {
+            return Err(MediaError::FileTooLarge);
+        }
+        let size = upload.size() as i64;
+        // rejects uploads over the quota before their content is stored, adding the
+        // attachment checks the quota again together with the insert
+        let used_bytes = media_db_state.lock().unwrap().used_bytes(&user_auth.uuid)?;
+        if used_bytes + size > USER_QUOTA {
+            return Err(MediaError::QuotaExceeded);
+        }
+
+        let processing = if IMAGE_TYPES.contains(&mime_type) {
+            ProcessingState::Pending
+        } else {
+            ProcessingState::None
+        };
+        let storage = media_storage.clone();
+        let hash = web::block(move || upload.finish(&storage)).await??;
+        let added = media_db_state.lock().unwrap().add_attachment(
+            &user_auth.uuid,
+            &hash,
+            mime_type,
+            &file_name,
+            size,
+            processing,
+            USER_QUOTA,
+        );
+        let attachment = match added {
+            Ok(attachment) => attachment,
+            Err(err) => {
+                // the content may be left without an attachment referring to it
+                remove_unused_blocking(media_db_state, media_storage, vec![hash]).await;
+                return Err(MediaError::from(err));
+            }
+        };
+        return Ok(web::Json(AttachmentResponse::from(attachment)));
+    }
+
+    return Err(MediaError::FileMissing);
+}
+
+/// The uploads of the user, newest first, with the share of the quota they use.
+#[get("/media")]
+async fn user_get_media(
+    user_auth: UserAuthentication,
+    page_query: web::Query<PageQuery>,
+    media_db_state: web::Data<Mutex<MediaDbService>>,
+) -> Result<impl Responder, MediaError> {
+    log::info!("/media {:?}", page_query);
+
+    let (page, page_size) = page_params(page_query.page, page_query.page_size);
+    let media_db_service = media_db_state.lock().unwrap();
+    let (attachments, total) = media_db_service.get_attachments(
+        &user_auth.uuid,
+        page_size,
+        (page - 1).saturating_mul(page_size),
+    )?;
+
+    return Ok(web::Json(MediaListResponse {
+        page,
+        page_size,
+        total,
+        used_bytes: media_db_service.used_bytes(&user_auth.uuid)?,
+        quota_bytes: USER_QUOTA,
+        attachments: attachments
+            .into_iter()
+            .map(AttachmentResponse::from)
+            .collect(),
+    }));
+}
+
+/// Deletes an upload which none of the posts of the user has attached anymore and which
+/// isn't their avatar. The stored content goes once no other upload shares it.
+#[post("/delete-media")]
+async fn user_delete_media(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<DeleteMediaRequest>,
+    env_settings: web::Data<EnvSettings>,
+    media_storage: web::Data<MediaStorage>,
+    media_db_state: web::Data<Mutex<MediaDbService>>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+) -> Result<impl Responder, MediaError> {
+    let payload = param_obj.into_inner();
+    log::info!("/delete-media {:?}", payload);
+
+    let attachment = {
+        let media_db_service = media_db_state.lock().unwrap();
+        let attachment = media_db_service.get_attachment(&payload.attachment_uuid)?;
+        if attachment.owner_uuid != user_auth.uuid {
+            return Err(MediaError::AttachmentNotFound);
+        }
+        if PostDbService::exists(&env_settings.db_collection_path, &user_auth.uuid)
+            && PostDbService::connect(&env_settings.db_collection_path, &user_auth.uuid)?
+                .is_attachment_used(&attachment.uuid)?
+        {
+            return Err(MediaError::AttachmentInUse);
+        }
+        if user_db_state.lock().unwrap().is_avatar(&attachment.uuid)? {
+            return Err(MediaError::AttachmentInUse);
+        }
+
+        media_db_service.delete_attachment(&attachment.uuid)?;
+        attachment
+    };
+    let mut hashes = vec![attachment.hash.clone()];
+    hashes.extend(
+        attachment
+            .variants
+            .iter()
+            .map(|variant| variant.hash.clone()),
+    );
+    remove_unused_blocking(media_db_state, media_storage, hashes).await;
+
+    return Ok(web::Json(AttachmentResponse::from(attachment)));
+}
+
+/// The attachment resource, public like the content itself: anyone who knows the uuid can
+/// read it.
+#[get("/{attachment_uuid}")]
+async fn get_media(
+    path: web::Path<String>,
+    media_db_state: web::Data<Mutex<MediaDbService>>,
+) -> Result<impl Responder, MediaError> {
+    let attachment_uuid = path.into_inner();
+    log::info!("/media/{}", attachment_uuid);
+
+    let attachment = media_db_state
+        .lock()
+        .unwrap()
+        .get_attachment(&attachment_uuid)?;
+    return Ok(web::Json(AttachmentResponse::from(attachment)));
+}
+
+/// `remove_unused` on the blocking thread pool, the storage may have to reach a remote server.
+async fn remove_unused_blocking(
+    media_db_state: web::Data<Mutex<MediaDbService>>,
+    media_storage: web::Data<MediaStorage>,
+    hashes: Vec<String>,
+) {
+    let result = web::block(move || remove_unused(&media_db_state, &media_storage, &hashes)).await;
+    if let Err(err) = result {
+        log::error!("media, removing stored content failed {:?}", err);
+    }
+}
+
+/// Sends a client to a presigned url of the storage when it hands them out, otherwise the
+/// content is served from here. The redirect changes with every request, the content never.
+async fn blob_response(
+    media_storage: web::Data<MediaStorage>,
+    hash: String,
+    mime_type: String,
+    content_disposition: header::ContentDisposition,
+) -> Result<HttpResponse, MediaError> {
+    if let Some(url) =
+        media_storage.presigned_url(&hash, &mime_type, &content_disposition.to_string())
+    {
+        return Ok(HttpResponse::Found()
+            .insert_header((header::LOCATION, url))
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .finish());
+    }
+
+    let content = {
+        let hash = hash.clone();
+        web::block(move || media_storage.read(&hash)).await??
+    };
+    return Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, mime_type))
+        .insert_header(content_disposition)
+        .insert_header((header::ETAG, format!("\"{}\"", hash)))
+        .insert_header((header::CACHE_CONTROL, IMMUTABLE_CACHE_CONTROL))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .body(content));
+}
+
+/// Downloads the content of an attachment, images once they are processed. The `ETag` is
+/// the content hash, so a matching `If-None-Match` is answered with `304 Not Modified`.
+/// With an S3 storage the download is a redirect to a presigned url.
+#[get("/{attachment_uuid}/file")]
+async fn get_media_file(
+    req: HttpRequest,
+    path: web::Path<String>,
+    media_storage: web::Data<MediaStorage>,
+    media_db_state: web::Data<Mutex<MediaDbService>>,
+) -> Result<HttpResponse, MediaError> {
+    let attachment_uuid = path.into_inner();
+    log::info!("/media/{}/file", attachment_uuid);
+
+    let attachment = media_db_state
+        .lock()
+        .unwrap()
+        .get_attachment(&attachment_uuid)?;
+    check_processed(&attachment)?;
+    if let Some(response) = not_modified(&req, &attachment.hash) {
+        return Ok(response);
+    }
+
+    let disposition = if IMAGE_TYPES.contains(&attachment.mime_type.as_str()) {
+        header::DispositionType::Inline
+    } else {
+        header::DispositionType::Attachment
+    };
+    return blob_response(
+        media_storage,
+        attachment.hash,
+        attachment.mime_type,
+        header::ContentDisposition {
+            disposition,
+            parameters: vec![header::DispositionParam::Filename(attachment.file_name)],
+        },
+    )
+    .await;
+}
+
+/// Downloads a resized variant or the WebP version of a processed image, served like the
+/// original.
+#[get("/{attachment_uuid}/variants/{name}")]
+async fn get_media_variant(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    media_storage: web::Data<MediaStorage>,
+    media_db_state: web::Data<Mutex<MediaDbService>>,
+) -> Result<HttpResponse, MediaError> {
+    let (attachment_uuid, name) = path.into_inner();
+    log::info!("/media/{}/variants/{}", attachment_uuid, name);
+
+    let attachment = media_db_state
+        .lock()
+        .unwrap()
+        .get_attachment(&attachment_uuid)?;
+    check_processed(&attachment)?;
+    let variant: AttachmentVariant = attachment
+        .variants
+        .into_iter()
+        .find(|variant| variant.name == name)
+        .ok_or(MediaError::VariantNotFound)?;
+    if let Some(response) = not_modified(&req, &variant.hash) {
+        return Ok(response);
+    }
+
+    return blob_response(
+        media_storage,
+        variant.hash,
+        variant.mime_type,
+        header::ContentDisposition {
+            disposition: header::DispositionType::Inline,
+            parameters: vec![],
+        },
+    )
+    .await;
+}
@@ -0,0 +1,467 @@
+use std::sync::Mutex;
+
+use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    message_db_service::{Conversation, Message, MessageDbError, MessageDbService},
+    social_db_service::{SocialDbError, SocialDbService, StreamEventKind},
+    user_db_service::UserDbService,
+};
+
+use super::{
+    blog::{find_author, AuthorResponse, BlogError},
+    error_response::AppErrorResponse,
+    events::publish_event,
+    search::{page_params, PageQuery},
+    user_auth_token_extractor::UserAuthentication,
+};
+
+const MAX_MESSAGE_LENGTH: usize = 500;
+/// Members of a group conversation, including the user starting it.
+const MAX_GROUP_MEMBERS: usize = 10;
+
+#[derive(Serialize, Debug, Display)]
+pub enum MessageError {
+    GenericError = 20701,
+    ConversationNotFound,
+    UserNotFound,
+    InvalidMembers,
+    EmptyMessage,
+    MessageTooLong,
+    UserBlocked,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ConversationRequest {
+    /// uuids or display names of the other members, one member starts a one-to-one
+    /// conversation, which is reused if the two users already have one
+    members: Vec<String>,
+    /// first message, optional
+    body: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MessageRequest {
+    body: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MessageResponse {
+    uuid: String,
+    conversation_uuid: String,
+    sender_uuid: String,
+    body: String,
+    created_at: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ConversationResponse {
+    uuid: String,
+    is_group: bool,
+    /// every member including the user, members which no longer exist are left out
+    members: Vec<AuthorResponse>,
+    created_at: i64,
+    updated_at: i64,
+    unread_count: i64,
+    last_message: Option<MessageResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ConversationListResponse {
+    page: u32,
+    page_size: u32,
+    total: i64,
+    /// conversations with unread messages, across all pages
+    unread: i64,
+    conversations: Vec<ConversationResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MessageListResponse {
+    conversation: ConversationResponse,
+    page: u32,
+    page_size: u32,
+    total: i64,
+    messages: Vec<MessageResponse>,
+}
+
+impl ResponseError for MessageError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            MessageError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            MessageError::ConversationNotFound => StatusCode::NOT_FOUND,
+            MessageError::UserNotFound => StatusCode::NOT_FOUND,
+            MessageError::InvalidMembers => StatusCode::BAD_REQUEST,
+            MessageError::EmptyMessage => StatusCode::BAD_REQUEST,
+            MessageError::MessageTooLong => StatusCode::BAD_REQUEST,
+            MessageError::UserBlocked => StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            MessageError::GenericError => {
+                HttpResponse::build(status).json(AppErrorResponse::from(MessageError::GenericError))
+            }
+            MessageError::ConversationNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(MessageError::ConversationNotFound)),
+            MessageError::UserNotFound => {
+                HttpResponse::build(status).json(AppErrorResponse::from(MessageError::UserNotFound))
+            }
+            MessageError::InvalidMembers => HttpResponse::build(status)
+                .json(AppErrorResponse::from(MessageError::InvalidMembers)),
+            MessageError::EmptyMessage => {
+                HttpResponse::build(status).json(AppErrorResponse::from(MessageError::EmptyMessage))
+            }
+            MessageError::MessageTooLong => HttpResponse::build(status)
+                .json(AppErrorResponse::from(MessageError::MessageTooLong)),
+            MessageError::UserBlocked => {
+                HttpResponse::build(status).json(AppErrorResponse::from(MessageError::UserBlocked))
+            }
+        }
+    }
+}
+
+impl From<MessageDbError> for MessageError {
+    fn from(value: MessageDbError) -> Self {
+        match value {
+            MessageDbError::ConversationNotFound => MessageError::ConversationNotFound,
+            MessageDbError::GenericError => MessageError::GenericError,
+        }
+    }
+}
+
+impl From<SocialDbError> for MessageError {
+    fn from(_value: SocialDbError) -> Self {
+        MessageError::GenericError
+    }
+}
+
+impl From<BlogError> for MessageError {
+    fn from(value: BlogError) -> Self {
+        match value {
+            BlogError::UserNotFound => MessageError::UserNotFound,
+            _ => MessageError::GenericError,
+        }
+    }
+}
+
+impl MessageResponse {
+    fn new(conversation_uuid: &str, message: Message) -> Self {
+        Self {
+            uuid: message.uuid,
+            conversation_uuid: conversation_uuid.to_owned(),
+            sender_uuid: message.sender_uuid,
+            body: message.body,
+            created_at: message.created_at,
+        }
+    }
+}
+
+fn conversation_response(
+    user_db_service: &UserDbService,
+    conversation: Conversation,
+) -> ConversationResponse {
+    let members = conversation
+        .member_uuids
+        .iter()
+        .filter_map(|member_uuid| user_db_service.get_user_from_uuid(member_uuid).ok())
+        .map(AuthorResponse::from)
+        .collect();
+    let conversation_uuid = conversation.uuid;
+    let last_message = conversation
+        .last_message
+        .map(|message| MessageResponse::new(&conversation_uuid, message));
+
+    ConversationResponse {
+        uuid: conversation_uuid,
+        is_group: conversation.is_group,
+        members,
+        created_at: conversation.created_at,
+        updated_at: conversation.updated_at,
+        unread_count: conversation.unread_count,
+        last_message,
+    }
+}
+
+/// Returns the trimmed message body, if it can be sent.
+fn validate_body(body: &str) -> Result<&str, MessageError> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err(MessageError::EmptyMessage);
+    }
+    if body.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(MessageError::MessageTooLong);
+    }
+    return Ok(body);
+}
+
+/// Users blocked by `user_uuid` or blocking them, hidden from each other in conversations.
+fn blocked_uuids(
+    social_db_state: &Mutex<SocialDbService>,
+    user_uuid: &str,
+) -> Result<Vec<String>, MessageError> {
+    return Ok(social_db_state
+        .lock()
+        .unwrap()
+        .get_blocked_uuids(user_uuid)?);
+}
+
+/// Stores the message and streams it to the other members of the conversation. One-to-one
+/// conversations with a blocked user are gone for both users, in group conversations the
+/// message isn't streamed to members blocking the sender or blocked by them.
+fn send_message(
+    message_db_state: &Mutex<MessageDbService>,
+    social_db_state: &Mutex<SocialDbService>,
+    user_uuid: &str,
+    conversation_uuid: &str,
+    body: &str,
+) -> Result<Conversation, MessageError> {
+    let blocked_uuids = blocked_uuids(social_db_state, user_uuid)?;
+    let (message, conversation) = {
+        let mut message_db_service = message_db_state.lock().unwrap();
+        message_db_service.get_conversation(conversation_uuid, user_uuid, &blocked_uuids)?;
+        let message = message_db_service.add_message(conversation_uuid, user_uuid, body)?;
+        let conversation =
+            message_db_service.get_conversation(conversation_uuid, user_uuid, &blocked_uuids)?;
+        (message, conversation)
+    };
+
+    let recipient_uuids: Vec<String> = conversation
+        .member_uuids
+        .iter()
+        .filter(|member_uuid| *member_uuid != user_uuid && !blocked_uuids.contains(member_uuid))
+        .cloned()
+        .collect();
+    publish_event(
+        social_db_state,
+        &recipient_uuids,
+        StreamEventKind::Message,
+        &MessageResponse::new(conversation_uuid, message),
+    );
+
+    return Ok(conversation);
+}
+
+/// Starts a conversation with the given members. A single member gives the one-to-one
+/// conversation of the two users, the existing one when they already wrote each other.
+/// Users blocking each other can't start conversations with each other.
+#[post("/conversations")]
+async fn user_start_conversation(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<ConversationRequest>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    message_db_state: web::Data<Mutex<MessageDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, MessageError> {
+    let payload = param_obj.into_inner();
+    log::info!("/conversations {:?}", payload);
+
+    let body = payload.body.as_deref().map(validate_body).transpose()?;
+    let mut member_uuids: Vec<String> = Vec::new();
+    {
+        let user_db_service = user_db_state.lock().unwrap();
+        for member in &payload.members {
+            let member = find_author(&user_db_service, member.trim())?;
+            if member.uuid != user_auth.uuid && !member_uuids.contains(&member.uuid) {
+                member_uuids.push(member.uuid);
+            }
+        }
+    }
+    if member_uuids.is_empty() || member_uuids.len() >= MAX_GROUP_MEMBERS {
+        return Err(MessageError::InvalidMembers);
+    }
+    let blocked_uuids = blocked_uuids(&social_db_state, &user_auth.uuid)?;
+    if member_uuids
+        .iter()
+        .any(|member_uuid| blocked_uuids.contains(member_uuid))
+    {
+        return Err(MessageError::UserBlocked);
+    }
+
+    let conversation_uuid = {
+        let mut message_db_service = message_db_state.lock().unwrap();
+        if member_uuids.len() == 1 {
+            message_db_service.direct_conversation(&user_auth.uuid, &member_uuids[0])?
+        } else {
+            let mut group_uuids: Vec<&str> = vec![&user_auth.uuid];
+            group_uuids.extend(member_uuids.iter().map(String::as_str));
+            message_db_service.create_group(&group_uuids)?
+        }
+    };
+
+    let conversation = match body {
+        Some(body) => send_message(
+            &message_db_state,
+            &social_db_state,
+            &user_auth.uuid,
+            &conversation_uuid,
+            body,
+        )?,
+        None => message_db_state.lock().unwrap().get_conversation(
+            &conversation_uuid,
+            &user_auth.uuid,
+            &blocked_uuids,
+        )?,
+    };
+
+    return Ok(web::Json(conversation_response(
+        &user_db_state.lock().unwrap(),
+        conversation,
+    )));
+}
+
+#[post("/conversations/{conversation_uuid}/messages")]
+async fn user_send_message(
+    user_auth: UserAuthentication,
+    path: web::Path<String>,
+    param_obj: web::Json<MessageRequest>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    message_db_state: web::Data<Mutex<MessageDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, MessageError> {
+    let conversation_uuid = path.into_inner();
+    let payload = param_obj.into_inner();
+    log::info!(
+        "/conversations/{}/messages {:?}",
+        conversation_uuid,
+        payload
+    );
+
+    let body = validate_body(&payload.body)?;
+    let conversation = send_message(
+        &message_db_state,
+        &social_db_state,
+        &user_auth.uuid,
+        &conversation_uuid,
+        body,
+    )?;
+
+    return Ok(web::Json(conversation_response(
+        &user_db_state.lock().unwrap(),
+        conversation,
+    )));
+}
+
+/// Conversations of the user, the most recently active first. One-to-one conversations with
+/// blocked users are left out.
+#[get("/conversations")]
+async fn user_get_conversations(
+    user_auth: UserAuthentication,
+    query: web::Query<PageQuery>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    message_db_state: web::Data<Mutex<MessageDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, MessageError> {
+    log::info!("/conversations {:?}", query);
+
+    let (page, page_size) = page_params(query.page, query.page_size);
+    let blocked_uuids = blocked_uuids(&social_db_state, &user_auth.uuid)?;
+    let (conversations, total, unread) = {
+        let message_db_service = message_db_state.lock().unwrap();
+        let (conversations, total) = message_db_service.get_conversations(
+            &user_auth.uuid,
+            &blocked_uuids,
+            page_size,
+            (page - 1).saturating_mul(page_size),
+        )?;
+        let unread =
+            message_db_service.count_unread_conversations(&user_auth.uuid, &blocked_uuids)?;
+        (conversations, total, unread)
+    };
+
+    let user_db_service = user_db_state.lock().unwrap();
+    let conversations = conversations
+        .into_iter()
+        .map(|conversation| conversation_response(&user_db_service, conversation))
+        .collect();
+
+    return Ok(web::Json(ConversationListResponse {
+        page,
+        page_size,
+        total,
+        unread,
+        conversations,
+    }));
+}
+
+/// Messages of a conversation, newest first. Reading them doesn't mark them as read.
+/// Messages of blocked users are left out.
+#[get("/conversations/{conversation_uuid}/messages")]
+async fn user_get_messages(
+    user_auth: UserAuthentication,
+    path: web::Path<String>,
+    query: web::Query<PageQuery>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    message_db_state: web::Data<Mutex<MessageDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, MessageError> {
+    let conversation_uuid = path.into_inner();
+    log::info!("/conversations/{}/messages {:?}", conversation_uuid, query);
+
+    let (page, page_size) = page_params(query.page, query.page_size);
+    let blocked_uuids = blocked_uuids(&social_db_state, &user_auth.uuid)?;
+    let (conversation, messages, total) = {
+        let message_db_service = message_db_state.lock().unwrap();
+        let conversation = message_db_service.get_conversation(
+            &conversation_uuid,
+            &user_auth.uuid,
+            &blocked_uuids,
+        )?;
+        let (messages, total) = message_db_service.get_messages(
+            &conversation_uuid,
+            &blocked_uuids,
+            page_size,
+            (page - 1).saturating_mul(page_size),
+        )?;
+        (conversation, messages, total)
+    };
+
+    let messages = messages
+        .into_iter()
+        .map(|message| MessageResponse::new(&conversation_uuid, message))
+        .collect();
+
+    return Ok(web::Json(MessageListResponse {
+        conversation: conversation_response(&user_db_state.lock().unwrap(), conversation),
+        page,
+        page_size,
+        total,
+        messages,
+    }));
+}
+
+#[post("/conversations/{conversation_uuid}/read")]
+async fn user_mark_conversation_read(
+    user_auth: UserAuthentication,
+    path: web::Path<String>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    message_db_state: web::Data<Mutex<MessageDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, MessageError> {
+    let conversation_uuid = path.into_inner();
+    log::info!("/conversations/{}/read", conversation_uuid);
+
+    let blocked_uuids = blocked_uuids(&social_db_state, &user_auth.uuid)?;
+    let conversation = {
+        let message_db_service = message_db_state.lock().unwrap();
+        message_db_service.get_conversation(&conversation_uuid, &user_auth.uuid, &blocked_uuids)?;
+        message_db_service.mark_read(&conversation_uuid, &user_auth.uuid)?;
+        message_db_service.get_conversation(&conversation_uuid, &user_auth.uuid, &blocked_uuids)?
+    };
+
+    return Ok(web::Json(conversation_response(
+        &user_db_state.lock().unwrap(),
+        conversation,
+    )));
+}
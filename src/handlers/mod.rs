@@ -1,5 +1,22 @@
+pub mod analytics;
 pub mod auth;
+pub mod block;
+pub mod blog;
+pub mod engagement;
 pub mod error_response;
+pub mod events;
+pub mod feed;
 pub mod health_check;
+pub mod link_preview;
+pub mod media;
+pub mod message;
+pub mod moderation;
+pub mod notification;
+pub mod poll;
+pub mod post_revision;
+pub mod profile;
+pub mod search;
+pub mod social;
+pub mod thread;
 pub mod user;
 pub mod user_auth_token_extractor;
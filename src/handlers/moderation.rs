@@ -0,0 +1,596 @@
+use std::sync::Mutex;
+
+use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
+use chrono::Utc;
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    env_settings::EnvSettings,
+    moderation_db_service::{
+        ModerationAction, ModerationActionKind, ModerationDbError, ModerationDbService, Report,
+        ReportReason, ReportState,
+    },
+    post_db_service::{PostDbError, PostDbService},
+    search_db_service::{SearchDbError, SearchDbService},
+    social_db_service::{SocialDbService, StreamEventKind},
+    user_db_service::UserDbService,
+};
+
+use super::{
+    blog::{find_author, AuthorResponse, BlogError},
+    error_response::AppErrorResponse,
+    events::publish_event,
+    notification::{notify_moderation, sync_mentions},
+    search::page_params,
+    social::sync_timelines,
+    thread::sync_reply_count,
+    user::{sync_search_index, PostDataResponse},
+    user_auth_token_extractor::UserAuthentication,
+};
+
+const MAX_DETAILS_LENGTH: usize = 500;
+
+#[derive(Serialize, Debug, Display)]
+pub enum ModerationError {
+    GenericError = 20901,
+    NotModerator,
+    PostNotFound,
+    UserNotFound,
+    ReportNotFound,
+    InvalidReportTarget,
+    CannotReportSelf,
+    DetailsTooLong,
+    ReasonMissing,
+    ActionNotApplicable,
+    ReportAlreadyResolved,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ReportReasonRequest {
+    Spam,
+    Harassment,
+    Hate,
+    Violence,
+    Sexual,
+    Misinformation,
+    Other,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ReportStateQuery {
+    Open,
+    Actioned,
+    Dismissed,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+enum ModerationActionRequest {
+    HidePost,
+    SuspendUser,
+    Warn,
+    Dismiss,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReportRequest {
+    /// the public post to report, either this or `user`
+    post_uuid: Option<String>,
+    /// uuid or display name of the user to report
+    user: Option<String>,
+    reason: ReportReasonRequest,
+    /// free text for the moderators, optional
+    details: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReportListQuery {
+    /// reports in this state only, all reports when left out
+    state: Option<ReportStateQuery>,
+    page: Option<u32>,
+    page_size: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ModerationActionBody {
+    action: ModerationActionRequest,
+    /// recorded with the action and shown to the reported user
+    reason: String,
+    /// length of a suspension, indefinite when left out
+    suspend_days: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReportFiledResponse {
+    id: i64,
+    reason: ReportReasonRequest,
+    state: ReportStateQuery,
+    created_at: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ModerationActionResponse {
+    id: i64,
+    moderator_uuid: String,
+    action: ModerationActionRequest,
+    reason: String,
+    created_at: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReportResponse {
+    id: i64,
+    /// `None` when the account no longer exists
+    reporter: Option<AuthorResponse>,
+    /// the reported user, the author for post reports
+    user: Option<AuthorResponse>,
+    post_uuid: Option<String>,
+    reason: ReportReasonRequest,
+    details: Option<String>,
+    state: ReportStateQuery,
+    created_at: i64,
+    resolved_at: Option<i64>,
+    actions: Vec<ModerationActionResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReportListResponse {
+    page: u32,
+    page_size: u32,
+    total: i64,
+    reports: Vec<ReportResponse>,
+}
+
+impl ResponseError for ModerationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ModerationError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            ModerationError::NotModerator => StatusCode::FORBIDDEN,
+            ModerationError::PostNotFound => StatusCode::NOT_FOUND,
+            ModerationError::UserNotFound => StatusCode::NOT_FOUND,
+            ModerationError::ReportNotFound => StatusCode::NOT_FOUND,
+            ModerationError::InvalidReportTarget => StatusCode::BAD_REQUEST,
+            ModerationError::CannotReportSelf => StatusCode::BAD_REQUEST,
+            ModerationError::DetailsTooLong => StatusCode::BAD_REQUEST,
+            ModerationError::ReasonMissing => StatusCode::BAD_REQUEST,
+            ModerationError::ActionNotApplicable => StatusCode::BAD_REQUEST,
+            ModerationError::ReportAlreadyResolved => StatusCode::CONFLICT,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            ModerationError::GenericError => HttpResponse::build(status)
+                .json(AppErrorResponse::from(ModerationError::GenericError)),
+            ModerationError::NotModerator => HttpResponse::build(status)
+                .json(AppErrorResponse::from(ModerationError::NotModerator)),
+            ModerationError::PostNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(ModerationError::PostNotFound)),
+            ModerationError::UserNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(ModerationError::UserNotFound)),
+            ModerationError::ReportNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(ModerationError::ReportNotFound)),
+            ModerationError::InvalidReportTarget => HttpResponse::build(status)
+                .json(AppErrorResponse::from(ModerationError::InvalidReportTarget)),
+            ModerationError::CannotReportSelf => HttpResponse::build(status)
+                .json(AppErrorResponse::from(ModerationError::CannotReportSelf)),
+            ModerationError::DetailsTooLong => HttpResponse::build(status)
+                .json(AppErrorResponse::from(ModerationError::DetailsTooLong)),
+            ModerationError::ReasonMissing => HttpResponse::build(status)
+                .json(AppErrorResponse::from(ModerationError::ReasonMissing)),
+            ModerationError::ActionNotApplicable => HttpResponse::build(status)
+                .json(AppErrorResponse::from(ModerationError::ActionNotApplicable)),
+            ModerationError::ReportAlreadyResolved => HttpResponse::build(status).json(
+                AppErrorResponse::from(ModerationError::ReportAlreadyResolved),
+            ),
+        }
+    }
+}
+
+impl From<ModerationDbError> for ModerationError {
+    fn from(value: ModerationDbError) -> Self {
+        match value {
+            ModerationDbError::ReportNotFound => ModerationError::ReportNotFound,
+            ModerationDbError::ReportAlreadyResolved => ModerationError::ReportAlreadyResolved,
+            ModerationDbError::GenericError => ModerationError::GenericError,
+        }
+    }
+}
+
+impl From<BlogError> for ModerationError {
+    fn from(value: BlogError) -> Self {
+        match value {
+            BlogError::UserNotFound => ModerationError::UserNotFound,
+            _ => ModerationError::GenericError,
+        }
+    }
+}
+
+impl From<PostDbError> for ModerationError {
+    fn from(value: PostDbError) -> Self {
+        match value {
+            PostDbError::PostNotFound => ModerationError::PostNotFound,
+            _ => ModerationError::GenericError,
+        }
+    }
+}
+
+impl From<SearchDbError> for ModerationError {
+    fn from(_value: SearchDbError) -> Self {
+        ModerationError::GenericError
+    }
+}
+
+impl From<ReportReasonRequest> for ReportReason {
+    fn from(value: ReportReasonRequest) -> Self {
+        match value {
+            ReportReasonRequest::Spam => ReportReason::Spam,
+            ReportReasonRequest::Harassment => ReportReason::Harassment,
+            ReportReasonRequest::Hate => ReportReason::Hate,
+            ReportReasonRequest::Violence => ReportReason::Violence,
+            ReportReasonRequest::Sexual => ReportReason::Sexual,
+            ReportReasonRequest::Misinformation => ReportReason::Misinformation,
+            ReportReasonRequest::Other => ReportReason::Other,
+        }
+    }
+}
+
+impl From<ReportReason> for ReportReasonRequest {
+    fn from(value: ReportReason) -> Self {
+        match value {
+            ReportReason::Spam => ReportReasonRequest::Spam,
+            ReportReason::Harassment => ReportReasonRequest::Harassment,
+            ReportReason::Hate => ReportReasonRequest::Hate,
+            ReportReason::Violence => ReportReasonRequest::Violence,
+            ReportReason::Sexual => ReportReasonRequest::Sexual,
+            ReportReason::Misinformation => ReportReasonRequest::Misinformation,
+            ReportReason::Other => ReportReasonRequest::Other,
+        }
+    }
+}
+
+impl From<ReportStateQuery> for ReportState {
+    fn from(value: ReportStateQuery) -> Self {
+        match value {
+            ReportStateQuery::Open => ReportState::Open,
+            ReportStateQuery::Actioned => ReportState::Actioned,
+            ReportStateQuery::Dismissed => ReportState::Dismissed,
+        }
+    }
+}
+
+impl From<ReportState> for ReportStateQuery {
+    fn from(value: ReportState) -> Self {
+        match value {
+            ReportState::Open => ReportStateQuery::Open,
+            ReportState::Actioned => ReportStateQuery::Actioned,
+            ReportState::Dismissed => ReportStateQuery::Dismissed,
+        }
+    }
+}
+
+impl From<ModerationActionRequest> for ModerationActionKind {
+    fn from(value: ModerationActionRequest) -> Self {
+        match value {
+            ModerationActionRequest::HidePost => ModerationActionKind::HidePost,
+            ModerationActionRequest::SuspendUser => ModerationActionKind::SuspendUser,
+            ModerationActionRequest::Warn => ModerationActionKind::Warn,
+            ModerationActionRequest::Dismiss => ModerationActionKind::Dismiss,
+        }
+    }
+}
+
+impl From<ModerationActionKind> for ModerationActionRequest {
+    fn from(value: ModerationActionKind) -> Self {
+        match value {
+            ModerationActionKind::HidePost => ModerationActionRequest::HidePost,
+            ModerationActionKind::SuspendUser => ModerationActionRequest::SuspendUser,
+            ModerationActionKind::Warn => ModerationActionRequest::Warn,
+            ModerationActionKind::Dismiss => ModerationActionRequest::Dismiss,
+        }
+    }
+}
+
+impl From<ModerationAction> for ModerationActionResponse {
+    fn from(value: ModerationAction) -> Self {
+        Self {
+            id: value.id,
+            moderator_uuid: value.moderator_uuid,
+            action: ModerationActionRequest::from(value.action),
+            reason: value.reason,
+            created_at: value.created_at,
+        }
+    }
+}
+
+fn require_moderator(
+    env_settings: &EnvSettings,
+    user_auth: &UserAuthentication,
+) -> Result<(), ModerationError> {
+    if !env_settings.is_moderator(&user_auth.uuid) {
+        return Err(ModerationError::NotModerator);
+    }
+    return Ok(());
+}
+
+/// Adds the reporter, the reported user and the actions taken to a report.
+fn report_response(
+    user_db_service: &UserDbService,
+    moderation_db_service: &ModerationDbService,
+    report: Report,
+) -> Result<ReportResponse, ModerationError> {
+    let actions = moderation_db_service
+        .get_actions(report.id)?
+        .into_iter()
+        .map(ModerationActionResponse::from)
+        .collect();
+    let reporter = user_db_service
+        .get_user_from_uuid(&report.reporter_uuid)
+        .ok()
+        .map(AuthorResponse::from);
+    let user = user_db_service
+        .get_user_from_uuid(&report.user_uuid)
+        .ok()
+        .map(AuthorResponse::from);
+
+    return Ok(ReportResponse {
+        id: report.id,
+        reporter,
+        user,
+        post_uuid: report.post_uuid,
+        reason: ReportReasonRequest::from(report.reason),
+        details: report.details,
+        state: ReportStateQuery::from(report.state),
+        created_at: report.created_at,
+        resolved_at: report.resolved_at,
+        actions,
+    });
+}
+
+/// Hides a reported post from everyone but its author: it leaves the search index, the
+/// timelines, the feeds and the thread of its parent, like a post turned private.
+fn hide_post(
+    env_settings: &EnvSettings,
+    user_db_state: &Mutex<UserDbService>,
+    search_db_state: &Mutex<SearchDbService>,
+    social_db_state: &Mutex<SocialDbService>,
+    author_uuid: &str,
+    post_uuid: &str,
+) -> Result<(), ModerationError> {
+    if !PostDbService::exists(&env_settings.db_collection_path, author_uuid) {
+        return Err(ModerationError::PostNotFound);
+    }
+
+    let post = PostDbService::connect(&env_settings.db_collection_path, author_uuid)?
+        .set_hidden(post_uuid, true)?;
+    sync_search_index(user_db_state, search_db_state, author_uuid, &post);
+    sync_timelines(social_db_state, author_uuid, &post);
+    sync_mentions(user_db_state, social_db_state, author_uuid, &post);
+    sync_reply_count(env_settings, search_db_state, social_db_state, &post, false);
+
+    publish_event(
+        social_db_state,
+        &[author_uuid.to_owned()],
+        StreamEventKind::PostUpdated,
+        &PostDataResponse::from(post),
+    );
+    return Ok(());
+}
+
+/// Reports a public post or a user to the moderators. Reporting the same post or user
+/// again while the first report is open returns the open report.
+#[post("/report")]
+async fn user_report(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<ReportRequest>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    moderation_db_state: web::Data<Mutex<ModerationDbService>>,
+) -> Result<impl Responder, ModerationError> {
+    let payload = param_obj.into_inner();
+    log::info!("/report {:?}", payload);
+
+    let details = payload
+        .details
+        .as_deref()
+        .map(str::trim)
+        .filter(|details| !details.is_empty());
+    if details.is_some_and(|details| details.chars().count() > MAX_DETAILS_LENGTH) {
+        return Err(ModerationError::DetailsTooLong);
+    }
+
+    let (user_uuid, post_uuid) = match (&payload.post_uuid, &payload.user) {
+        (Some(post_uuid), None) => {
+            let post = search_db_state
+                .lock()
+                .unwrap()
+                .get_posts(std::slice::from_ref(post_uuid))?
+                .pop()
+                .ok_or(ModerationError::PostNotFound)?;
+            (post.author_uuid, Some(post.post_uuid))
+        }
+        (None, Some(user)) => (
+            find_author(&user_db_state.lock().unwrap(), user)?.uuid,
+            None,
+        ),
+        _ => return Err(ModerationError::InvalidReportTarget),
+    };
+    if user_uuid == user_auth.uuid {
+        return Err(ModerationError::CannotReportSelf);
+    }
+
+    let report = moderation_db_state.lock().unwrap().add_report(
+        &user_auth.uuid,
+        &user_uuid,
+        post_uuid.as_deref(),
+        ReportReason::from(payload.reason),
+        details,
+    )?;
+
+    return Ok(web::Json(ReportFiledResponse {
+        id: report.id,
+        reason: ReportReasonRequest::from(report.reason),
+        state: ReportStateQuery::from(report.state),
+        created_at: report.created_at,
+    }));
+}
+
+/// The moderation queue, oldest reports first. Moderators only.
+#[get("/reports")]
+async fn moderation_get_reports(
+    user_auth: UserAuthentication,
+    query: web::Query<ReportListQuery>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    moderation_db_state: web::Data<Mutex<ModerationDbService>>,
+) -> Result<impl Responder, ModerationError> {
+    log::info!("/moderation/reports {:?}", query);
+    require_moderator(&env_settings, &user_auth)?;
+
+    let (page, page_size) = page_params(query.page, query.page_size);
+    let moderation_db_service = moderation_db_state.lock().unwrap();
+    let (reports, total) = moderation_db_service.get_reports(
+        query.state.map(ReportState::from),
+        page_size,
+        (page - 1).saturating_mul(page_size),
+    )?;
+    let user_db_service = user_db_state.lock().unwrap();
+    let reports = reports
+        .into_iter()
+        .map(|report| report_response(&user_db_service, &moderation_db_service, report))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    return Ok(web::Json(ReportListResponse {
+        page,
+        page_size,
+        total,
+        reports,
+    }));
+}
+
+#[get("/reports/{report_id}")]
+async fn moderation_get_report(
+    user_auth: UserAuthentication,
+    path: web::Path<i64>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    moderation_db_state: web::Data<Mutex<ModerationDbService>>,
+) -> Result<impl Responder, ModerationError> {
+    let report_id = path.into_inner();
+    log::info!("/moderation/reports/{}", report_id);
+    require_moderator(&env_settings, &user_auth)?;
+
+    let moderation_db_service = moderation_db_state.lock().unwrap();
+    let report = moderation_db_service.get_report(report_id)?;
+
+    return Ok(web::Json(report_response(
+        &user_db_state.lock().unwrap(),
+        &moderation_db_service,
+        report,
+    )?));
+}
+
+/// Acts on an open report: hides the reported post, suspends or warns the reported user, or
+/// dismisses the report. The other open reports about the same post or user are resolved
+/// with it, and the reported user is notified of everything but a dismissal. Suspended
+/// users can still sign in and read, but can't change anything until the suspension ends.
+#[post("/reports/{report_id}/action")]
+#[allow(clippy::too_many_arguments)]
+async fn moderation_act_on_report(
+    user_auth: UserAuthentication,
+    path: web::Path<i64>,
+    param_obj: web::Json<ModerationActionBody>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    moderation_db_state: web::Data<Mutex<ModerationDbService>>,
+) -> Result<impl Responder, ModerationError> {
+    let report_id = path.into_inner();
+    let payload = param_obj.into_inner();
+    log::info!("/moderation/reports/{}/action {:?}", report_id, payload);
+    require_moderator(&env_settings, &user_auth)?;
+
+    let reason = payload.reason.trim();
+    if reason.is_empty() {
+        return Err(ModerationError::ReasonMissing);
+    }
+    if reason.chars().count() > MAX_DETAILS_LENGTH {
+        return Err(ModerationError::DetailsTooLong);
+    }
+
+    let report = moderation_db_state.lock().unwrap().get_report(report_id)?;
+    if report.state != ReportState::Open {
+        return Err(ModerationError::ReportAlreadyResolved);
+    }
+
+    let action = ModerationActionKind::from(payload.action);
+    let mut suspended_until = None;
+    let message = match action {
+        ModerationActionKind::HidePost => {
+            let post_uuid = report
+                .post_uuid
+                .as_deref()
+                .ok_or(ModerationError::ActionNotApplicable)?;
+            hide_post(
+                &env_settings,
+                &user_db_state,
+                &search_db_state,
+                &social_db_state,
+                &report.user_uuid,
+                post_uuid,
+            )?;
+            Some(format!("A moderator hid your post: {}", reason))
+        }
+        ModerationActionKind::SuspendUser => match payload.suspend_days {
+            Some(days) => {
+                suspended_until =
+                    Some(Utc::now().timestamp() + i64::from(days).saturating_mul(86400));
+                Some(format!(
+                    "Your account was suspended for {} days: {}",
+                    days, reason
+                ))
+            }
+            None => Some(format!("Your account was suspended: {}", reason)),
+        },
+        ModerationActionKind::Warn => Some(format!("A moderator warned you: {}", reason)),
+        ModerationActionKind::Dismiss => None,
+    };
+
+    let report = moderation_db_state.lock().unwrap().resolve_report(
+        report_id,
+        &user_auth.uuid,
+        action,
+        reason,
+        suspended_until,
+    )?;
+    if let Some(message) = message {
+        notify_moderation(
+            &user_db_state,
+            &social_db_state,
+            &report.user_uuid,
+            &user_auth.uuid,
+            report.post_uuid.as_deref(),
+            &message,
+        );
+    }
+
+    let moderation_db_service = moderation_db_state.lock().unwrap();
+    return Ok(web::Json(report_response(
+        &user_db_state.lock().unwrap(),
+        &moderation_db_service,
+        report,
+    )?));
+}
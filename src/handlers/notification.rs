@@ -0,0 +1,354 @@
+use std::sync::Mutex;
+
+use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    post_db_service::Post,
+    post_entities::extract_mentions,
+    social_db_service::{
+        Notification, NotificationKind, SocialDbError, SocialDbService, StreamEventKind,
+    },
+    user_db_service::UserDbService,
+};
+
+use super::{
+    blog::AuthorResponse, error_response::AppErrorResponse, events::publish_event,
+    search::page_params, user_auth_token_extractor::UserAuthentication,
+};
+
+/// Mentions beyond this many per post are ignored.
+const MAX_MENTIONS: usize = 20;
+
+#[derive(Serialize, Debug, Display)]
+pub enum NotificationError {
+    GenericError = 20501,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NotificationListQuery {
+    #[serde(default)]
+    unread_only: bool,
+    page: Option<u32>,
+    page_size: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MarkReadRequest {
+    /// notifications to mark as read, every notification when left out
+    notification_ids: Option<Vec<i64>>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum NotificationKindResponse {
+    Mention,
+    Reply,
+    Follow,
+    Like,
+    Moderation,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NotificationResponse {
+    id: i64,
+    kind: NotificationKindResponse,
+    /// `None` for moderation notices, moderators stay anonymous
+    actor: Option<AuthorResponse>,
+    /// the post with the mention, the reply, the liked or the moderated post, `None` for
+    /// new followers
+    post_uuid: Option<String>,
+    created_at: i64,
+    read: bool,
+    /// what a moderator did and why, only set for moderation notices
+    message: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NotificationListResponse {
+    page: u32,
+    page_size: u32,
+    total: i64,
+    unread: i64,
+    notifications: Vec<NotificationResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct UnreadCountResponse {
+    unread: i64,
+}
+
+impl From<NotificationKind> for NotificationKindResponse {
+    fn from(value: NotificationKind) -> Self {
+        match value {
+            NotificationKind::Mention => NotificationKindResponse::Mention,
+            NotificationKind::Reply => NotificationKindResponse::Reply,
+            NotificationKind::Follow => NotificationKindResponse::Follow,
+            NotificationKind::Like => NotificationKindResponse::Like,
+            NotificationKind::Moderation => NotificationKindResponse::Moderation,
+        }
+    }
+}
+
+impl ResponseError for NotificationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            NotificationError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            NotificationError::GenericError => HttpResponse::build(status)
+                .json(AppErrorResponse::from(NotificationError::GenericError)),
+        }
+    }
+}
+
+impl From<SocialDbError> for NotificationError {
+    fn from(_value: SocialDbError) -> Self {
+        NotificationError::GenericError
+    }
+}
+
+/// Adds the actor details, notifications of users which no longer exist are dropped.
+fn notification_response(
+    user_db_service: &UserDbService,
+    notification: Notification,
+) -> Option<NotificationResponse> {
+    let actor = match notification.kind {
+        NotificationKind::Moderation => None,
+        _ => Some(AuthorResponse::from(
+            user_db_service
+                .get_user_from_uuid(&notification.actor_uuid)
+                .ok()?,
+        )),
+    };
+    Some(NotificationResponse {
+        id: notification.id,
+        kind: NotificationKindResponse::from(notification.kind),
+        actor,
+        post_uuid: notification.post_uuid,
+        created_at: notification.created_at,
+        read: notification.read,
+        message: notification.message,
+    })
+}
+
+/// Notifies `recipient_uuid` about something `actor_uuid` did and streams the notification
+/// to the recipient. Users aren't notified about their own actions, nor about actions of
+/// users they blocked, muted or are blocked by. The action already happened at this point,
+/// failures are only logged.
+pub fn notify(
+    user_db_state: &Mutex<UserDbService>,
+    social_db_state: &Mutex<SocialDbService>,
+    recipient_uuid: &str,
+    kind: NotificationKind,
+    actor_uuid: &str,
+    post_uuid: Option<&str>,
+) {
+    if recipient_uuid == actor_uuid {
+        return;
+    }
+
+    let added = {
+        let social_db_service = social_db_state.lock().unwrap();
+        match social_db_service.is_hidden(recipient_uuid, actor_uuid) {
+            Ok(true) => return,
+            Ok(false) => {
+                social_db_service.add_notification(recipient_uuid, kind, actor_uuid, post_uuid)
+            }
+            Err(err) => Err(err),
+        }
+    };
+    let notification = match added {
+        Ok(Some(notification)) => notification,
+        Ok(None) => return,
+        Err(err) => {
+            log::error!("notifications, adding notification failed {:?}", err);
+            return;
+        }
+    };
+    let response = notification_response(&user_db_state.lock().unwrap(), notification);
+    if let Some(response) = response {
+        publish_event(
+            social_db_state,
+            &[recipient_uuid.to_owned()],
+            StreamEventKind::Notification,
+            &response,
+        );
+    }
+}
+
+/// Tells a user what a moderator did about them or one of their posts and streams the
+/// notice. Unlike `notify` it ignores blocks and mutes. Failures are only logged.
+pub fn notify_moderation(
+    user_db_state: &Mutex<UserDbService>,
+    social_db_state: &Mutex<SocialDbService>,
+    recipient_uuid: &str,
+    moderator_uuid: &str,
+    post_uuid: Option<&str>,
+    message: &str,
+) {
+    let added = social_db_state.lock().unwrap().add_moderation_notice(
+        recipient_uuid,
+        moderator_uuid,
+        post_uuid,
+        message,
+    );
+    let notification = match added {
+        Ok(notification) => notification,
+        Err(err) => {
+            log::error!("notifications, adding moderation notice failed {:?}", err);
+            return;
+        }
+    };
+    let response = notification_response(&user_db_state.lock().unwrap(), notification);
+    if let Some(response) = response {
+        publish_event(
+            social_db_state,
+            &[recipient_uuid.to_owned()],
+            StreamEventKind::Notification,
+            &response,
+        );
+    }
+}
+
+/// Links the `@displayName` mentions of a post written by `user_uuid` to the mentioned
+/// users and notifies the ones mentioned for the first time. Mentions in posts others
+/// can't read are dropped, they are picked up again once the post becomes readable.
+/// Mentions of users blocking the author or blocked by them are dropped as well.
+pub fn sync_mentions(
+    user_db_state: &Mutex<UserDbService>,
+    social_db_state: &Mutex<SocialDbService>,
+    user_uuid: &str,
+    post: &Post,
+) {
+    let mentioned_uuids: Vec<String> = if post.is_readable_by_others() {
+        let user_db_service = user_db_state.lock().unwrap();
+        extract_mentions(&post.post)
+            .iter()
+            .filter_map(|display_name| {
+                user_db_service
+                    .get_user_from_display_name(display_name)
+                    .ok()
+            })
+            .map(|user| user.uuid)
+            .filter(|mentioned_uuid| mentioned_uuid != user_uuid)
+            .take(MAX_MENTIONS)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let added = {
+        let mut social_db_service = social_db_state.lock().unwrap();
+        match social_db_service.get_blocked_uuids(user_uuid) {
+            Ok(blocked_uuids) => {
+                let mentioned_uuids: Vec<String> = mentioned_uuids
+                    .into_iter()
+                    .filter(|mentioned_uuid| !blocked_uuids.contains(mentioned_uuid))
+                    .collect();
+                social_db_service.set_mentions(&post.uuid, &mentioned_uuids)
+            }
+            Err(err) => Err(err),
+        }
+    };
+    match added {
+        Ok(added) => {
+            for mentioned_uuid in added {
+                notify(
+                    user_db_state,
+                    social_db_state,
+                    &mentioned_uuid,
+                    NotificationKind::Mention,
+                    user_uuid,
+                    Some(&post.uuid),
+                );
+            }
+        }
+        Err(err) => log::error!("notifications, linking mentions failed {:?}", err),
+    }
+}
+
+/// Notifications of the user, newest first. Notifications of users which no longer exist
+/// are left out of the page, the ones of blocked and muted users aren't counted at all.
+#[get("/notifications")]
+async fn user_get_notifications(
+    user_auth: UserAuthentication,
+    query: web::Query<NotificationListQuery>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, NotificationError> {
+    let query = query.into_inner();
+    log::info!("/notifications {:?}", query);
+
+    let (page, page_size) = page_params(query.page, query.page_size);
+    let (notifications, total, unread) = {
+        let social_db_service = social_db_state.lock().unwrap();
+        let (notifications, total) = social_db_service.get_notifications(
+            &user_auth.uuid,
+            query.unread_only,
+            page_size,
+            (page - 1).saturating_mul(page_size),
+        )?;
+        let unread = social_db_service.count_unread_notifications(&user_auth.uuid)?;
+        (notifications, total, unread)
+    };
+
+    let user_db_service = user_db_state.lock().unwrap();
+    let notifications = notifications
+        .into_iter()
+        .filter_map(|notification| notification_response(&user_db_service, notification))
+        .collect();
+
+    return Ok(web::Json(NotificationListResponse {
+        page,
+        page_size,
+        total,
+        unread,
+        notifications,
+    }));
+}
+
+#[get("/notifications/unread-count")]
+async fn user_get_unread_notification_count(
+    user_auth: UserAuthentication,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, NotificationError> {
+    log::info!("/notifications/unread-count");
+
+    let unread = social_db_state
+        .lock()
+        .unwrap()
+        .count_unread_notifications(&user_auth.uuid)?;
+
+    return Ok(web::Json(UnreadCountResponse { unread }));
+}
+
+#[post("/notifications/mark-read")]
+async fn user_mark_notifications_read(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<MarkReadRequest>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, NotificationError> {
+    let payload = param_obj.into_inner();
+    log::info!("/notifications/mark-read {:?}", payload);
+
+    let unread = {
+        let mut social_db_service = social_db_state.lock().unwrap();
+        social_db_service
+            .mark_notifications_read(&user_auth.uuid, payload.notification_ids.as_deref())?;
+        social_db_service.count_unread_notifications(&user_auth.uuid)?
+    };
+
+    return Ok(web::Json(UnreadCountResponse { unread }));
+}
@@ -0,0 +1,271 @@
+use std::sync::Mutex;
+
+use actix_web::{http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
+use chrono::Utc;
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    env_settings::EnvSettings,
+    post_db_service::{Poll, PostDbError, PostDbService},
+    social_db_service::{SocialDbError, SocialDbService},
+};
+
+use super::{error_response::AppErrorResponse, user_auth_token_extractor::UserAuthentication};
+
+const MIN_POLL_OPTIONS: usize = 2;
+const MAX_POLL_OPTIONS: usize = 6;
+const MAX_POLL_OPTION_LENGTH: usize = 100;
+/// Polls close at the latest this long after they were written.
+const MAX_POLL_DURATION_SECONDS: i64 = 30 * 24 * 60 * 60;
+const MIN_POLL_DURATION_SECONDS: i64 = 5 * 60;
+
+#[derive(Serialize, Debug, Display)]
+pub enum PollError {
+    GenericError = 21101,
+    PollNotFound,
+    PollClosed,
+    AlreadyVoted,
+    InvalidChoice,
+}
+
+/// A poll sent along with a new post.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PollRequest {
+    options: Vec<String>,
+    #[serde(default)]
+    multiple: bool,
+    /// unix timestamp, at least five minutes and at most 30 days ahead
+    closes_at: i64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct VoteRequest {
+    post_uuid: String,
+    /// indexes of the picked options, exactly one unless the poll allows multiple choices
+    choices: Vec<usize>,
+}
+
+/// A poll as readers see it. The votes stay hidden from a reader until they voted or the
+/// poll closed, so the results can't sway the vote.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PollResponse {
+    options: Vec<PollOptionResponse>,
+    multiple: bool,
+    closes_at: i64,
+    closed: bool,
+    /// indexes of the options the reader picked, empty until they vote
+    own_choices: Vec<usize>,
+    /// number of users who voted, hidden like the votes
+    voter_count: Option<i64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct PollOptionResponse {
+    text: String,
+    votes: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct VoteResponse {
+    post_uuid: String,
+    poll: PollResponse,
+}
+
+impl ResponseError for PollError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PollError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            PollError::PollNotFound => StatusCode::NOT_FOUND,
+            PollError::PollClosed => StatusCode::CONFLICT,
+            PollError::AlreadyVoted => StatusCode::CONFLICT,
+            PollError::InvalidChoice => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            PollError::GenericError => {
+                HttpResponse::build(status).json(AppErrorResponse::from(PollError::GenericError))
+            }
+            PollError::PollNotFound => {
+                HttpResponse::build(status).json(AppErrorResponse::from(PollError::PollNotFound))
+            }
+            PollError::PollClosed => {
+                HttpResponse::build(status).json(AppErrorResponse::from(PollError::PollClosed))
+            }
+            PollError::AlreadyVoted => {
+                HttpResponse::build(status).json(AppErrorResponse::from(PollError::AlreadyVoted))
+            }
+            PollError::InvalidChoice => {
+                HttpResponse::build(status).json(AppErrorResponse::from(PollError::InvalidChoice))
+            }
+        }
+    }
+}
+
+impl From<PostDbError> for PollError {
+    fn from(value: PostDbError) -> Self {
+        match value {
+            PostDbError::PostNotFound => PollError::PollNotFound,
+            _ => PollError::GenericError,
+        }
+    }
+}
+
+impl From<SocialDbError> for PollError {
+    fn from(_value: SocialDbError) -> Self {
+        PollError::GenericError
+    }
+}
+
+impl PollRequest {
+    /// Trims the options, `None` when the poll breaks one of the limits.
+    pub fn into_poll(self) -> Option<Poll> {
+        let options: Vec<String> = self
+            .options
+            .iter()
+            .map(|option| option.trim().to_owned())
+            .collect();
+        if options.len() < MIN_POLL_OPTIONS
+            || options.len() > MAX_POLL_OPTIONS
+            || options
+                .iter()
+                .any(|option| option.is_empty() || option.chars().count() > MAX_POLL_OPTION_LENGTH)
+        {
+            return None;
+        }
+
+        let now = Utc::now().timestamp();
+        if self.closes_at < now + MIN_POLL_DURATION_SECONDS
+            || self.closes_at > now + MAX_POLL_DURATION_SECONDS
+        {
+            return None;
+        }
+
+        return Some(Poll {
+            options,
+            multiple: self.multiple,
+            closes_at: self.closes_at,
+        });
+    }
+}
+
+impl From<Poll> for PollResponse {
+    fn from(value: Poll) -> Self {
+        Self {
+            options: value
+                .options
+                .into_iter()
+                .map(|text| PollOptionResponse { text, votes: None })
+                .collect(),
+            multiple: value.multiple,
+            closed: value.closes_at <= Utc::now().timestamp(),
+            closes_at: value.closes_at,
+            own_choices: Vec::new(),
+            voter_count: None,
+        }
+    }
+}
+
+impl PollResponse {
+    /// Adds the choices of the reader, and the votes once the reader voted or the poll
+    /// closed. Anonymous readers only see the votes of closed polls.
+    pub fn load_votes(
+        &mut self,
+        social_db_service: &SocialDbService,
+        post_uuid: &str,
+        reader_uuid: Option<&str>,
+    ) -> Result<(), SocialDbError> {
+        if let Some(reader_uuid) = reader_uuid {
+            self.own_choices = social_db_service.poll_choices(reader_uuid, post_uuid)?;
+        }
+        if !self.closed && self.own_choices.is_empty() {
+            return Ok(());
+        }
+
+        let (votes, voter_count) = social_db_service.poll_results(post_uuid, self.options.len())?;
+        for (option, votes) in self.options.iter_mut().zip(votes) {
+            option.votes = Some(votes);
+        }
+        self.voter_count = Some(voter_count);
+        return Ok(());
+    }
+}
+
+/// Registers the poll of a new post, so votes can find the database of the author. The
+/// post is already saved at this point, failures are only logged.
+pub fn register_poll(social_db_state: &Mutex<SocialDbService>, author_uuid: &str, post_uuid: &str) {
+    if let Err(err) = social_db_state
+        .lock()
+        .unwrap()
+        .add_poll(post_uuid, author_uuid)
+    {
+        log::error!("poll, registering failed {:?}", err);
+    }
+}
+
+/// Votes in the poll of a post the user can read. Every user votes once, votes can't be
+/// changed or taken back.
+#[post("/vote")]
+async fn user_vote(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<VoteRequest>,
+    env_settings: web::Data<EnvSettings>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, PollError> {
+    let payload = param_obj.into_inner();
+    log::info!("/vote {:?}", payload);
+
+    let author_uuid = {
+        let social_db_service = social_db_state.lock().unwrap();
+        let author_uuid = social_db_service
+            .get_poll_author(&payload.post_uuid)?
+            .ok_or(PollError::PollNotFound)?;
+        if social_db_service.is_blocked(&user_auth.uuid, &author_uuid)? {
+            return Err(PollError::PollNotFound);
+        }
+        author_uuid
+    };
+    if !PostDbService::exists(&env_settings.db_collection_path, &author_uuid) {
+        return Err(PollError::PollNotFound);
+    }
+    let post = PostDbService::connect(&env_settings.db_collection_path, &author_uuid)?
+        .get_post(&payload.post_uuid)?;
+    if author_uuid != user_auth.uuid && !post.is_readable_by_others() {
+        return Err(PollError::PollNotFound);
+    }
+    let poll = post.poll.ok_or(PollError::PollNotFound)?;
+    if poll.closes_at <= Utc::now().timestamp() {
+        return Err(PollError::PollClosed);
+    }
+
+    let mut choices = payload.choices;
+    choices.sort_unstable();
+    choices.dedup();
+    if choices.is_empty()
+        || choices.iter().any(|choice| *choice >= poll.options.len())
+        || (!poll.multiple && choices.len() > 1)
+    {
+        return Err(PollError::InvalidChoice);
+    }
+
+    let mut social_db_service = social_db_state.lock().unwrap();
+    if !social_db_service.vote(&user_auth.uuid, &post.uuid, &choices)? {
+        return Err(PollError::AlreadyVoted);
+    }
+    let mut response = PollResponse::from(poll);
+    response.load_votes(&social_db_service, &post.uuid, Some(&user_auth.uuid))?;
+
+    return Ok(web::Json(VoteResponse {
+        post_uuid: post.uuid,
+        poll: response,
+    }));
+}
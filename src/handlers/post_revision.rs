@@ -0,0 +1,316 @@
+use std::sync::Mutex;
+
+use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+
+use crate::services::{
+    env_settings::EnvSettings,
+    link_preview_db_service::LinkPreviewDbService,
+    post_db_service::{PostDbError, PostDbService, PostRevision},
+    search_db_service::SearchDbService,
+    social_db_service::{SocialDbService, StreamEventKind},
+    user_db_service::UserDbService,
+};
+
+use super::{
+    error_response::AppErrorResponse,
+    events::publish_event,
+    link_preview::request_link_previews,
+    notification::sync_mentions,
+    social::sync_timelines,
+    user::{
+        check_post_length, load_link_previews, load_poll_votes, sync_search_index,
+        PostDataResponse, PostFormat, UserPostError,
+    },
+    user_auth_token_extractor::UserAuthentication,
+};
+
+#[derive(Serialize, Debug, Display)]
+pub enum PostRevisionError {
+    GenericError = 20101,
+    PostNotFound,
+    RevisionNotFound,
+    TitleTooLong,
+    PostTooLong,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PostDiffQuery {
+    from: i64,
+    to: i64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RestoreRevisionRequest {
+    post_uuid: String,
+    revision: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RevisionSummaryResponse {
+    revision: i64,
+    title: String,
+    created_at: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RevisionListResponse {
+    post_uuid: String,
+    revisions: Vec<RevisionSummaryResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RevisionDataResponse {
+    post_uuid: String,
+    revision: i64,
+    title: String,
+    post: String,
+    format: PostFormat,
+    created_at: i64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DiffChangeResponse {
+    op: DiffOp,
+    value: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PostDiffResponse {
+    post_uuid: String,
+    from: i64,
+    to: i64,
+    /// word-level changes of the title
+    title_changes: Vec<DiffChangeResponse>,
+    /// word-level changes of the post body
+    changes: Vec<DiffChangeResponse>,
+    /// line based unified diff of the post body
+    unified: String,
+}
+
+impl ResponseError for PostRevisionError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PostRevisionError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            PostRevisionError::PostNotFound => StatusCode::NOT_FOUND,
+            PostRevisionError::RevisionNotFound => StatusCode::NOT_FOUND,
+            PostRevisionError::TitleTooLong => StatusCode::BAD_REQUEST,
+            PostRevisionError::PostTooLong => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            PostRevisionError::GenericError => HttpResponse::build(status)
+                .json(AppErrorResponse::from(PostRevisionError::GenericError)),
+            PostRevisionError::PostNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(PostRevisionError::PostNotFound)),
+            PostRevisionError::RevisionNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(PostRevisionError::RevisionNotFound)),
+            PostRevisionError::TitleTooLong => HttpResponse::build(status)
+                .json(AppErrorResponse::from(PostRevisionError::TitleTooLong)),
+            PostRevisionError::PostTooLong => HttpResponse::build(status)
+                .json(AppErrorResponse::from(PostRevisionError::PostTooLong)),
+        }
+    }
+}
+
+impl From<PostDbError> for PostRevisionError {
+    fn from(value: PostDbError) -> Self {
+        match value {
+            PostDbError::PostNotFound => PostRevisionError::PostNotFound,
+            PostDbError::RevisionNotFound => PostRevisionError::RevisionNotFound,
+            _ => PostRevisionError::GenericError,
+        }
+    }
+}
+
+impl From<UserPostError> for PostRevisionError {
+    fn from(value: UserPostError) -> Self {
+        match value {
+            UserPostError::TitleTooLong => PostRevisionError::TitleTooLong,
+            UserPostError::PostTooLong => PostRevisionError::PostTooLong,
+            _ => PostRevisionError::GenericError,
+        }
+    }
+}
+
+fn connect_existing(
+    env_settings: &EnvSettings,
+    user_uuid: &str,
+) -> Result<PostDbService, PostRevisionError> {
+    if !PostDbService::exists(&env_settings.db_collection_path, user_uuid) {
+        return Err(PostRevisionError::PostNotFound);
+    }
+    return Ok(PostDbService::connect(
+        &env_settings.db_collection_path,
+        user_uuid,
+    )?);
+}
+
+/// Word-level diff, consecutive words with the same operation are merged into one change.
+fn word_changes(old: &str, new: &str) -> Vec<DiffChangeResponse> {
+    let mut changes: Vec<DiffChangeResponse> = Vec::new();
+
+    for change in TextDiff::from_words(old, new).iter_all_changes() {
+        let op = match change.tag() {
+            ChangeTag::Equal => DiffOp::Equal,
+            ChangeTag::Insert => DiffOp::Insert,
+            ChangeTag::Delete => DiffOp::Delete,
+        };
+        match changes.last_mut() {
+            Some(last) if last.op == op => {
+                last.value.push_str(change.value());
+            }
+            _ => changes.push(DiffChangeResponse {
+                op,
+                value: change.value().to_owned(),
+            }),
+        }
+    }
+
+    return changes;
+}
+
+fn revision_diff(post_uuid: String, from: &PostRevision, to: &PostRevision) -> PostDiffResponse {
+    let unified = TextDiff::from_lines(&from.post, &to.post)
+        .unified_diff()
+        .context_radius(3)
+        .header(
+            &format!("revision {}", from.revision),
+            &format!("revision {}", to.revision),
+        )
+        .to_string();
+
+    PostDiffResponse {
+        post_uuid,
+        from: from.revision,
+        to: to.revision,
+        title_changes: word_changes(&from.title, &to.title),
+        changes: word_changes(&from.post, &to.post),
+        unified,
+    }
+}
+
+#[get("/posts/{post_uuid}/revisions")]
+async fn user_get_post_revisions(
+    user_auth: UserAuthentication,
+    path: web::Path<String>,
+    env_settings: web::Data<EnvSettings>,
+) -> Result<impl Responder, PostRevisionError> {
+    let post_uuid = path.into_inner();
+    log::info!("/posts/{}/revisions", post_uuid);
+
+    let post_db_service = connect_existing(&env_settings, &user_auth.uuid)?;
+    let revisions = post_db_service
+        .get_revisions(&post_uuid)?
+        .into_iter()
+        .map(|revision| RevisionSummaryResponse {
+            revision: revision.revision,
+            title: revision.title,
+            created_at: revision.created_at,
+        })
+        .collect();
+
+    return Ok(web::Json(RevisionListResponse {
+        post_uuid,
+        revisions,
+    }));
+}
+
+#[get("/posts/{post_uuid}/revisions/{revision}")]
+async fn user_get_post_revision(
+    user_auth: UserAuthentication,
+    path: web::Path<(String, i64)>,
+    env_settings: web::Data<EnvSettings>,
+) -> Result<impl Responder, PostRevisionError> {
+    let (post_uuid, revision) = path.into_inner();
+    log::info!("/posts/{}/revisions/{}", post_uuid, revision);
+
+    let post_db_service = connect_existing(&env_settings, &user_auth.uuid)?;
+    let revision = post_db_service.get_revision(&post_uuid, revision)?;
+
+    return Ok(web::Json(RevisionDataResponse {
+        post_uuid,
+        revision: revision.revision,
+        title: revision.title,
+        post: revision.post,
+        format: PostFormat::from(revision.format),
+        created_at: revision.created_at,
+    }));
+}
+
+#[get("/posts/{post_uuid}/diff")]
+async fn user_get_post_diff(
+    user_auth: UserAuthentication,
+    path: web::Path<String>,
+    query: web::Query<PostDiffQuery>,
+    env_settings: web::Data<EnvSettings>,
+) -> Result<impl Responder, PostRevisionError> {
+    let post_uuid = path.into_inner();
+    log::info!("/posts/{}/diff {:?}", post_uuid, query);
+
+    let post_db_service = connect_existing(&env_settings, &user_auth.uuid)?;
+    let from = post_db_service.get_revision(&post_uuid, query.from)?;
+    let to = post_db_service.get_revision(&post_uuid, query.to)?;
+
+    return Ok(web::Json(revision_diff(post_uuid, &from, &to)));
+}
+
+#[post("/restore-post-revision")]
+async fn user_restore_post_revision(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<RestoreRevisionRequest>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
+) -> Result<impl Responder, PostRevisionError> {
+    let payload = param_obj.into_inner();
+    log::info!("/restore-post-revision {:?}", payload);
+
+    let mut post_db_service = connect_existing(&env_settings, &user_auth.uuid)?;
+    // revisions written before the limits were lowered can't come back past them
+    let revision = post_db_service.get_revision(&payload.post_uuid, payload.revision)?;
+    check_post_length(&env_settings.post_limits, &revision.title, &revision.post)?;
+    let post = post_db_service.restore_revision(&payload.post_uuid, payload.revision)?;
+    sync_search_index(&user_db_state, &search_db_state, &user_auth.uuid, &post);
+    sync_timelines(&social_db_state, &user_auth.uuid, &post);
+    sync_mentions(&user_db_state, &social_db_state, &user_auth.uuid, &post);
+    request_link_previews(&link_preview_db_state, &post);
+
+    let mut response = PostDataResponse::from(post);
+    load_poll_votes(
+        &social_db_state,
+        std::slice::from_mut(&mut response),
+        Some(&user_auth.uuid),
+    );
+    load_link_previews(&link_preview_db_state, std::slice::from_mut(&mut response));
+    publish_event(
+        &social_db_state,
+        &[user_auth.uuid],
+        StreamEventKind::PostUpdated,
+        &response,
+    );
+    return Ok(web::Json(response));
+}
@@ -0,0 +1,336 @@
+use std::sync::Mutex;
+
+use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    env_settings::EnvSettings,
+    media_db_service::{MediaDbError, MediaDbService, ProcessingState},
+    post_db_service::{PostDbError, PostDbService, PostFilter, Visibility},
+    social_db_service::{SocialDbError, SocialDbService},
+    user_db_service::{Profile, ProfileLink, User, UserDbError, UserDbService},
+};
+
+use super::{
+    blog::{find_author, is_blocked_reader, BlogError},
+    error_response::AppErrorResponse,
+    media::IMAGE_TYPES,
+    user_auth_token_extractor::UserAuthentication,
+};
+
+const MAX_BIO_LENGTH: usize = 500;
+const MAX_LOCATION_LENGTH: usize = 100;
+const MAX_PRONOUNS_LENGTH: usize = 40;
+const MAX_PROFILE_LINKS: usize = 5;
+const MAX_LINK_LABEL_LENGTH: usize = 50;
+const MAX_LINK_URL_LENGTH: usize = 2000;
+
+#[derive(Serialize, Debug, Display)]
+pub enum ProfileError {
+    GenericError = 21201,
+    UserNotFound,
+    InvalidProfile,
+    AvatarNotFound,
+}
+
+/// Fields left out keep their current value, an empty string clears them.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct EditProfileRequest {
+    bio: Option<String>,
+    /// uuid of an own uploaded image
+    avatar_uuid: Option<String>,
+    location: Option<String>,
+    pronouns: Option<String>,
+    /// replaces all links, in display order
+    links: Option<Vec<ProfileLinkData>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ProfileLinkData {
+    label: String,
+    /// `http` or `https` url
+    url: String,
+}
+
+/// The public profile of a user, it never includes the email.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ProfileResponse {
+    uuid: String,
+    display_name: String,
+    bio: String,
+    /// `/media/{uuid}` describes the image
+    avatar_uuid: Option<String>,
+    location: Option<String>,
+    pronouns: Option<String>,
+    links: Vec<ProfileLinkData>,
+    /// unix timestamp, unknown for early accounts
+    joined_at: Option<i64>,
+    /// public posts, the ones hidden by a moderator left out
+    post_count: i64,
+    follower_count: i64,
+    following_count: i64,
+}
+
+impl ResponseError for ProfileError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ProfileError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            ProfileError::UserNotFound => StatusCode::NOT_FOUND,
+            ProfileError::InvalidProfile => StatusCode::BAD_REQUEST,
+            ProfileError::AvatarNotFound => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            ProfileError::GenericError => {
+                HttpResponse::build(status).json(AppErrorResponse::from(ProfileError::GenericError))
+            }
+            ProfileError::UserNotFound => {
+                HttpResponse::build(status).json(AppErrorResponse::from(ProfileError::UserNotFound))
+            }
+            ProfileError::InvalidProfile => HttpResponse::build(status)
+                .json(AppErrorResponse::from(ProfileError::InvalidProfile)),
+            ProfileError::AvatarNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(ProfileError::AvatarNotFound)),
+        }
+    }
+}
+
+impl From<UserDbError> for ProfileError {
+    fn from(value: UserDbError) -> Self {
+        match value {
+            UserDbError::UserNotFound => ProfileError::UserNotFound,
+            _ => ProfileError::GenericError,
+        }
+    }
+}
+
+impl From<BlogError> for ProfileError {
+    fn from(value: BlogError) -> Self {
+        match value {
+            BlogError::UserNotFound => ProfileError::UserNotFound,
+            _ => ProfileError::GenericError,
+        }
+    }
+}
+
+impl From<PostDbError> for ProfileError {
+    fn from(_value: PostDbError) -> Self {
+        ProfileError::GenericError
+    }
+}
+
+impl From<SocialDbError> for ProfileError {
+    fn from(_value: SocialDbError) -> Self {
+        ProfileError::GenericError
+    }
+}
+
+impl From<MediaDbError> for ProfileError {
+    fn from(value: MediaDbError) -> Self {
+        match value {
+            MediaDbError::AttachmentNotFound => ProfileError::AvatarNotFound,
+            _ => ProfileError::GenericError,
+        }
+    }
+}
+
+impl From<ProfileLink> for ProfileLinkData {
+    fn from(value: ProfileLink) -> Self {
+        Self {
+            label: value.label,
+            url: value.url,
+        }
+    }
+}
+
+/// Trims a field, `None` when it is empty.
+fn optional_field(value: String) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    return Some(value.to_owned());
+}
+
+fn too_long(value: Option<&str>, max_length: usize) -> bool {
+    value.is_some_and(|value| value.chars().count() > max_length)
+}
+
+fn is_web_url(url: &str) -> bool {
+    match url.split_once("://") {
+        Some((scheme, rest)) => {
+            (scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https"))
+                && !rest.is_empty()
+                && !rest.contains(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+impl EditProfileRequest {
+    /// Applies the changes to the current profile, `None` when the result breaks one of
+    /// the limits.
+    fn into_profile(self, current: Profile) -> Option<Profile> {
+        let links = match self.links {
+            Some(links) => links
+                .into_iter()
+                .map(|link| ProfileLink {
+                    label: link.label.trim().to_owned(),
+                    url: link.url.trim().to_owned(),
+                })
+                .collect(),
+            None => current.links,
+        };
+        let profile = Profile {
+            bio: self.bio.map_or(current.bio, |bio| bio.trim().to_owned()),
+            avatar_uuid: self.avatar_uuid.map_or(current.avatar_uuid, optional_field),
+            location: self.location.map_or(current.location, optional_field),
+            pronouns: self.pronouns.map_or(current.pronouns, optional_field),
+            links,
+            joined_at: current.joined_at,
+        };
+
+        if too_long(Some(&profile.bio), MAX_BIO_LENGTH)
+            || too_long(profile.location.as_deref(), MAX_LOCATION_LENGTH)
+            || too_long(profile.pronouns.as_deref(), MAX_PRONOUNS_LENGTH)
+            || profile.links.len() > MAX_PROFILE_LINKS
+            || profile.links.iter().any(|link| {
+                link.label.is_empty()
+                    || too_long(Some(&link.label), MAX_LINK_LABEL_LENGTH)
+                    || link.url.len() > MAX_LINK_URL_LENGTH
+                    || !is_web_url(&link.url)
+            })
+        {
+            return None;
+        }
+        return Some(profile);
+    }
+}
+
+/// Adds the counts to a profile. Post counts come from the user's own database, which
+/// only exists once they wrote a post.
+fn profile_response(
+    env_settings: &EnvSettings,
+    social_db_state: &Mutex<SocialDbService>,
+    user: User,
+    profile: Profile,
+) -> Result<ProfileResponse, ProfileError> {
+    let post_count = if PostDbService::exists(&env_settings.db_collection_path, &user.uuid) {
+        PostDbService::connect(&env_settings.db_collection_path, &user.uuid)?.count_posts(
+            &PostFilter {
+                visibilities: vec![Visibility::Public],
+                exclude_hidden: true,
+                ..PostFilter::default()
+            },
+        )?
+    } else {
+        0
+    };
+    let (follower_count, following_count) =
+        social_db_state.lock().unwrap().follow_counts(&user.uuid)?;
+
+    return Ok(ProfileResponse {
+        uuid: user.uuid,
+        display_name: user.display_name,
+        bio: profile.bio,
+        avatar_uuid: profile.avatar_uuid,
+        location: profile.location,
+        pronouns: profile.pronouns,
+        links: profile
+            .links
+            .into_iter()
+            .map(ProfileLinkData::from)
+            .collect(),
+        joined_at: profile.joined_at,
+        post_count,
+        follower_count,
+        following_count,
+    });
+}
+
+/// Public profile of a user, `user` is either the uuid or the display name. Signed in readers
+/// don't find the profiles of users blocking them or blocked by them.
+#[get("/{user}")]
+async fn blog_get_profile(
+    user_auth: Option<UserAuthentication>,
+    path: web::Path<String>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, ProfileError> {
+    let user = path.into_inner();
+    log::info!("/users/{}", user);
+
+    let (user, profile) = {
+        let user_db_service = user_db_state.lock().unwrap();
+        let user = find_author(&user_db_service, &user)?;
+        let profile = user_db_service.get_profile(&user.uuid)?;
+        (user, profile)
+    };
+    if is_blocked_reader(&social_db_state, user_auth.as_ref(), &user.uuid)? {
+        return Err(ProfileError::UserNotFound);
+    }
+
+    return Ok(web::Json(profile_response(
+        &env_settings,
+        &social_db_state,
+        user,
+        profile,
+    )?));
+}
+
+/// Changes the profile of the user. The avatar has to be an own image which didn't fail
+/// processing.
+#[post("/edit-profile")]
+async fn user_edit_profile(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<EditProfileRequest>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    media_db_state: web::Data<Mutex<MediaDbService>>,
+) -> Result<impl Responder, ProfileError> {
+    let payload = param_obj.into_inner();
+    log::info!("/edit-profile {:?}", payload);
+
+    let user = user_db_state
+        .lock()
+        .unwrap()
+        .get_user_from_uuid(&user_auth.uuid)?;
+    let current = user_db_state.lock().unwrap().get_profile(&user.uuid)?;
+    let current_avatar_uuid = current.avatar_uuid.clone();
+    let profile = payload
+        .into_profile(current)
+        .ok_or(ProfileError::InvalidProfile)?;
+
+    if let Some(avatar_uuid) = &profile.avatar_uuid {
+        if profile.avatar_uuid != current_avatar_uuid {
+            let attachment = media_db_state.lock().unwrap().get_attachment(avatar_uuid)?;
+            if attachment.owner_uuid != user.uuid
+                || !IMAGE_TYPES.contains(&attachment.mime_type.as_str())
+                || matches!(attachment.processing, ProcessingState::Failed)
+            {
+                return Err(ProfileError::AvatarNotFound);
+            }
+        }
+    }
+    user_db_state
+        .lock()
+        .unwrap()
+        .update_profile(&user.uuid, &profile)?;
+
+    return Ok(web::Json(profile_response(
+        &env_settings,
+        &social_db_state,
+        user,
+        profile,
+    )?));
+}
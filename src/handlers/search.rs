@@ -0,0 +1,337 @@
+use std::sync::Mutex;
+
+use actix_web::{get, http::StatusCode, web, HttpResponse, Responder, ResponseError};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    env_settings::EnvSettings,
+    link_preview_db_service::LinkPreviewDbService,
+    post_db_service::{normalize_tag, PostDbError, PostDbService},
+    post_entities::extract_urls,
+    search_db_service::{PublicPost, PublicPostFilter, SearchDbError, SearchDbService},
+    search_query::{highlight_to_html, to_fts_query},
+    social_db_service::{SocialDbError, SocialDbService},
+};
+
+use super::{
+    error_response::AppErrorResponse,
+    link_preview::{find_link_previews, previewed_links, LinkPreviewResponse},
+    user::{load_link_previews, load_poll_votes, PostDataResponse},
+    user_auth_token_extractor::UserAuthentication,
+};
+
+const DEFAULT_PAGE_SIZE: u32 = 20;
+const MAX_PAGE_SIZE: u32 = 50;
+
+#[derive(Serialize, Debug, Display)]
+pub enum SearchError {
+    GenericError = 20201,
+    InvalidSearchQuery,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SearchQuery {
+    q: String,
+    page: Option<u32>,
+    page_size: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PublicSearchQuery {
+    q: Option<String>,
+    /// display name of the author
+    author: Option<String>,
+    tag: Option<String>,
+    /// unix timestamp, posts created at or after
+    from: Option<i64>,
+    /// unix timestamp, posts created at or before
+    to: Option<i64>,
+    page: Option<u32>,
+    page_size: Option<u32>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicPostResponse {
+    post_uuid: String,
+    author_uuid: String,
+    author_display_name: String,
+    slug: String,
+    title: String,
+    post: String,
+    post_html: String,
+    tags: Vec<String>,
+    created_at: i64,
+    updated_at: i64,
+    reply_to: Option<String>,
+    reply_count: i64,
+    like_count: i64,
+    repost_count: i64,
+    /// uuids of the attached media, `/media/{uuid}` describes each
+    attachments: Vec<String>,
+    /// previews of the first links in the post, once they are fetched
+    link_previews: Vec<LinkPreviewResponse>,
+    #[serde(skip)]
+    links: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PublicSearchHitResponse {
+    #[serde(flatten)]
+    post: PublicPostResponse,
+    /// html escaped title, matched terms are wrapped in `<mark>`
+    title_highlight: Option<String>,
+    /// html escaped fragment of the post, matched terms are wrapped in `<mark>`
+    snippet: Option<String>,
+    rank: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PublicSearchResponse {
+    page: u32,
+    page_size: u32,
+    total: i64,
+    results: Vec<PublicSearchHitResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SearchHitResponse {
+    #[serde(flatten)]
+    post: PostDataResponse,
+    /// html escaped title, matched terms are wrapped in `<mark>`
+    title_highlight: String,
+    /// html escaped fragment of the post, matched terms are wrapped in `<mark>`
+    snippet: String,
+    rank: f64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SearchResponse {
+    page: u32,
+    page_size: u32,
+    total: i64,
+    results: Vec<SearchHitResponse>,
+}
+
+impl ResponseError for SearchError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SearchError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            SearchError::InvalidSearchQuery => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            SearchError::GenericError => {
+                HttpResponse::build(status).json(AppErrorResponse::from(SearchError::GenericError))
+            }
+            SearchError::InvalidSearchQuery => HttpResponse::build(status)
+                .json(AppErrorResponse::from(SearchError::InvalidSearchQuery)),
+        }
+    }
+}
+
+impl From<PostDbError> for SearchError {
+    fn from(_value: PostDbError) -> Self {
+        SearchError::GenericError
+    }
+}
+
+impl From<SearchDbError> for SearchError {
+    fn from(_value: SearchDbError) -> Self {
+        SearchError::GenericError
+    }
+}
+
+impl From<SocialDbError> for SearchError {
+    fn from(_value: SocialDbError) -> Self {
+        SearchError::GenericError
+    }
+}
+
+impl From<PublicPost> for PublicPostResponse {
+    fn from(value: PublicPost) -> Self {
+        // the index keeps the source of the post, its links are the ones the author's
+        // database stores for it
+        let links = previewed_links(&extract_urls(&value.post));
+        Self {
+            post_uuid: value.post_uuid,
+            author_uuid: value.author_uuid,
+            author_display_name: value.author_display_name,
+            slug: value.slug,
+            title: value.title,
+            post: value.post,
+            post_html: value.post_html,
+            tags: value.tags,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+            reply_to: value.reply_to,
+            reply_count: value.reply_count,
+            like_count: value.like_count,
+            repost_count: value.repost_count,
+            attachments: value.attachments,
+            link_previews: Vec::new(),
+            links,
+        }
+    }
+}
+
+/// Adds the previews fetched for the links of public posts, failures only leave them out.
+pub fn load_public_link_previews<'a>(
+    link_preview_db_state: &Mutex<LinkPreviewDbService>,
+    posts: impl IntoIterator<Item = &'a mut PublicPostResponse>,
+) {
+    let link_preview_db_service = link_preview_db_state.lock().unwrap();
+    for post in posts {
+        post.link_previews = find_link_previews(&link_preview_db_service, &post.links);
+    }
+}
+
+/// Paging parameters of list endpoints.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PageQuery {
+    pub page: Option<u32>,
+    pub page_size: Option<u32>,
+}
+
+/// Returns the 1 based page number and the page size, clamped to sane values.
+pub fn page_params(page: Option<u32>, page_size: Option<u32>) -> (u32, u32) {
+    let page = page.unwrap_or(1).max(1);
+    let page_size = page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    return (page, page_size);
+}
+
+#[get("/search")]
+async fn user_search_posts(
+    user_auth: UserAuthentication,
+    query: web::Query<SearchQuery>,
+    env_settings: web::Data<EnvSettings>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
+) -> Result<impl Responder, SearchError> {
+    let query = query.into_inner();
+    log::info!("/search {:?}", query);
+
+    let fts_query = to_fts_query(&query.q).ok_or(SearchError::InvalidSearchQuery)?;
+    let (page, page_size) = page_params(query.page, query.page_size);
+
+    if !PostDbService::exists(&env_settings.db_collection_path, &user_auth.uuid) {
+        return Ok(web::Json(SearchResponse {
+            page,
+            page_size,
+            total: 0,
+            results: Vec::new(),
+        }));
+    }
+
+    let post_db_service =
+        PostDbService::connect(&env_settings.db_collection_path, &user_auth.uuid)?;
+    let (hits, total) = post_db_service.search_posts(
+        &fts_query,
+        page_size,
+        (page - 1).saturating_mul(page_size),
+    )?;
+    let mut results: Vec<SearchHitResponse> = hits
+        .into_iter()
+        .map(|hit| SearchHitResponse {
+            post: PostDataResponse::from(hit.post),
+            title_highlight: highlight_to_html(&hit.title_highlight),
+            snippet: highlight_to_html(&hit.snippet),
+            rank: hit.rank,
+        })
+        .collect();
+    for result in results.iter_mut() {
+        load_poll_votes(
+            &social_db_state,
+            std::slice::from_mut(&mut result.post),
+            Some(&user_auth.uuid),
+        );
+        load_link_previews(
+            &link_preview_db_state,
+            std::slice::from_mut(&mut result.post),
+        );
+    }
+
+    return Ok(web::Json(SearchResponse {
+        page,
+        page_size,
+        total,
+        results,
+    }));
+}
+
+/// Searches the public posts of all users, no authentication needed.
+/// Without `q` the matching posts are listed newest first. Signed in readers don't see the
+/// posts of users blocking them or blocked by them.
+#[get("/search")]
+async fn search_public_posts(
+    user_auth: Option<UserAuthentication>,
+    query: web::Query<PublicSearchQuery>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
+) -> Result<impl Responder, SearchError> {
+    let query = query.into_inner();
+    log::info!("/search {:?}", query);
+
+    let fts_query = match query.q.as_deref().map(str::trim) {
+        Some(q) if !q.is_empty() => Some(to_fts_query(q).ok_or(SearchError::InvalidSearchQuery)?),
+        _ => None,
+    };
+    let filter = PublicPostFilter {
+        fts_query,
+        author_uuid: None,
+        author_display_name: query.author.map(|author| author.trim().to_owned()),
+        tag: query.tag.as_deref().and_then(normalize_tag),
+        created_from: query.from,
+        created_to: query.to,
+    };
+    let (page, page_size) = page_params(query.page, query.page_size);
+
+    let (hits, total) = search_db_state.lock().unwrap().search(
+        &filter,
+        page_size,
+        (page - 1).saturating_mul(page_size),
+    )?;
+    let blocked_uuids = match &user_auth {
+        Some(user_auth) => social_db_state
+            .lock()
+            .unwrap()
+            .get_blocked_uuids(&user_auth.uuid)?,
+        None => Vec::new(),
+    };
+    let mut results: Vec<PublicSearchHitResponse> = hits
+        .into_iter()
+        .filter(|hit| !blocked_uuids.contains(&hit.post.author_uuid))
+        .map(|hit| PublicSearchHitResponse {
+            post: PublicPostResponse::from(hit.post),
+            title_highlight: hit.title_highlight.as_deref().map(highlight_to_html),
+            snippet: hit.snippet.as_deref().map(highlight_to_html),
+            rank: hit.rank,
+        })
+        .collect();
+    load_public_link_previews(
+        &link_preview_db_state,
+        results.iter_mut().map(|result| &mut result.post),
+    );
+
+    return Ok(web::Json(PublicSearchResponse {
+        page,
+        page_size,
+        total,
+        results,
+    }));
+}
@@ -0,0 +1,416 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    link_preview_db_service::LinkPreviewDbService,
+    post_db_service::Post,
+    search_db_service::{PublicPostFilter, SearchDbError, SearchDbService},
+    social_db_service::{
+        FollowEntry, NotificationKind, SocialDbError, SocialDbService, TimelinePost,
+    },
+    user_db_service::UserDbService,
+};
+
+use super::{
+    blog::{find_author, AuthorResponse, BlogError},
+    error_response::AppErrorResponse,
+    notification::notify,
+    search::{load_public_link_previews, page_params, PageQuery, PublicPostResponse},
+    user_auth_token_extractor::UserAuthentication,
+};
+
+/// Number of recent posts of a followed user copied to the home timeline of the follower.
+const TIMELINE_BACKFILL_SIZE: u32 = 50;
+
+#[derive(Serialize, Debug, Display)]
+pub enum SocialError {
+    GenericError = 20301,
+    UserNotFound,
+    CannotFollowSelf,
+    UserBlocked,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FollowRequest {
+    /// uuid or display name of the user to follow
+    user: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FollowResponse {
+    user: AuthorResponse,
+    following: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FollowCountsResponse {
+    user: AuthorResponse,
+    followers: i64,
+    following: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FollowUserResponse {
+    uuid: String,
+    display_name: String,
+    followed_at: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FollowListResponse {
+    user: AuthorResponse,
+    page: u32,
+    page_size: u32,
+    total: i64,
+    users: Vec<FollowUserResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TimelineResponse {
+    page: u32,
+    page_size: u32,
+    total: i64,
+    posts: Vec<PublicPostResponse>,
+}
+
+impl ResponseError for SocialError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SocialError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            SocialError::UserNotFound => StatusCode::NOT_FOUND,
+            SocialError::CannotFollowSelf => StatusCode::BAD_REQUEST,
+            SocialError::UserBlocked => StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            SocialError::GenericError => {
+                HttpResponse::build(status).json(AppErrorResponse::from(SocialError::GenericError))
+            }
+            SocialError::UserNotFound => {
+                HttpResponse::build(status).json(AppErrorResponse::from(SocialError::UserNotFound))
+            }
+            SocialError::CannotFollowSelf => HttpResponse::build(status)
+                .json(AppErrorResponse::from(SocialError::CannotFollowSelf)),
+            SocialError::UserBlocked => {
+                HttpResponse::build(status).json(AppErrorResponse::from(SocialError::UserBlocked))
+            }
+        }
+    }
+}
+
+impl From<BlogError> for SocialError {
+    fn from(value: BlogError) -> Self {
+        match value {
+            BlogError::UserNotFound => SocialError::UserNotFound,
+            _ => SocialError::GenericError,
+        }
+    }
+}
+
+impl From<SocialDbError> for SocialError {
+    fn from(_value: SocialDbError) -> Self {
+        SocialError::GenericError
+    }
+}
+
+impl From<SearchDbError> for SocialError {
+    fn from(_value: SearchDbError) -> Self {
+        SocialError::GenericError
+    }
+}
+
+/// Puts a post written by `user_uuid` on the home timelines of the author and the followers,
+/// or takes it off when it isn't public. Like the search index the timelines are derived
+/// data, failures are only logged and `cargo run -- reindex` rebuilds them.
+pub fn sync_timelines(social_db_state: &Mutex<SocialDbService>, user_uuid: &str, post: &Post) {
+    if !post.is_public() {
+        remove_from_timelines(social_db_state, &post.uuid);
+        return;
+    }
+
+    let timeline_post = TimelinePost {
+        post_uuid: post.uuid.clone(),
+        author_uuid: user_uuid.to_owned(),
+        created_at: post.created_at,
+    };
+    if let Err(err) = social_db_state.lock().unwrap().fan_out_post(&timeline_post) {
+        log::error!("timelines, fan-out failed {:?}", err);
+    }
+}
+
+pub fn remove_from_timelines(social_db_state: &Mutex<SocialDbService>, post_uuid: &str) {
+    if let Err(err) = social_db_state
+        .lock()
+        .unwrap()
+        .remove_from_timelines(post_uuid)
+    {
+        log::error!("timelines, removing post failed {:?}", err);
+    }
+}
+
+/// Users a signed in reader blocks or is blocked by, nobody for anonymous readers.
+fn reader_blocked_uuids(
+    social_db_service: &SocialDbService,
+    user_auth: Option<&UserAuthentication>,
+) -> Result<Vec<String>, SocialDbError> {
+    return match user_auth {
+        Some(user_auth) => social_db_service.get_blocked_uuids(&user_auth.uuid),
+        None => Ok(Vec::new()),
+    };
+}
+
+/// Adds the display names to a page of follow entries, users which no longer exist are left out,
+/// as are the users in `blocked_uuids`.
+fn follow_users(
+    user_db_service: &UserDbService,
+    entries: Vec<FollowEntry>,
+    blocked_uuids: &[String],
+) -> Vec<FollowUserResponse> {
+    return entries
+        .into_iter()
+        .filter(|entry| !blocked_uuids.contains(&entry.user_uuid))
+        .filter_map(|entry| {
+            let user = user_db_service.get_user_from_uuid(&entry.user_uuid).ok()?;
+            Some(FollowUserResponse {
+                uuid: user.uuid,
+                display_name: user.display_name,
+                followed_at: entry.created_at,
+            })
+        })
+        .collect();
+}
+
+#[post("/follow")]
+async fn user_follow(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<FollowRequest>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, SocialError> {
+    let payload = param_obj.into_inner();
+    log::info!("/follow {:?}", payload);
+
+    let followee = find_author(&user_db_state.lock().unwrap(), &payload.user)?;
+    if followee.uuid == user_auth.uuid {
+        return Err(SocialError::CannotFollowSelf);
+    }
+
+    let followed = {
+        let social_db_service = social_db_state.lock().unwrap();
+        if social_db_service.is_blocked(&user_auth.uuid, &followee.uuid)? {
+            return Err(SocialError::UserBlocked);
+        }
+        social_db_service.follow(&user_auth.uuid, &followee.uuid)?
+    };
+    if followed {
+        // the timeline shouldn't start out empty, copy the latest posts of the followed user
+        let filter = PublicPostFilter {
+            author_uuid: Some(followee.uuid.clone()),
+            ..PublicPostFilter::default()
+        };
+        let (hits, _total) =
+            search_db_state
+                .lock()
+                .unwrap()
+                .search(&filter, TIMELINE_BACKFILL_SIZE, 0)?;
+        let posts: Vec<TimelinePost> = hits
+            .into_iter()
+            .map(|hit| TimelinePost {
+                post_uuid: hit.post.post_uuid,
+                author_uuid: hit.post.author_uuid,
+                created_at: hit.post.created_at,
+            })
+            .collect();
+        social_db_state
+            .lock()
+            .unwrap()
+            .add_to_timeline(&user_auth.uuid, &posts)?;
+        notify(
+            &user_db_state,
+            &social_db_state,
+            &followee.uuid,
+            NotificationKind::Follow,
+            &user_auth.uuid,
+            None,
+        );
+    }
+
+    return Ok(web::Json(FollowResponse {
+        user: AuthorResponse::from(followee),
+        following: true,
+    }));
+}
+
+#[post("/unfollow")]
+async fn user_unfollow(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<FollowRequest>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, SocialError> {
+    let payload = param_obj.into_inner();
+    log::info!("/unfollow {:?}", payload);
+
+    let followee = find_author(&user_db_state.lock().unwrap(), &payload.user)?;
+    social_db_state
+        .lock()
+        .unwrap()
+        .unfollow(&user_auth.uuid, &followee.uuid)?;
+
+    return Ok(web::Json(FollowResponse {
+        user: AuthorResponse::from(followee),
+        following: false,
+    }));
+}
+
+/// Home timeline, the public posts of the user and of everyone they follow, newest first.
+/// Posts of muted users are left out.
+#[get("/timeline")]
+async fn user_get_timeline(
+    user_auth: UserAuthentication,
+    page_query: web::Query<PageQuery>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
+) -> Result<impl Responder, SocialError> {
+    let page_query = page_query.into_inner();
+    log::info!("/timeline {:?}", page_query);
+
+    let (page, page_size) = page_params(page_query.page, page_query.page_size);
+    let (post_uuids, total) = social_db_state.lock().unwrap().get_timeline(
+        &user_auth.uuid,
+        page_size,
+        (page - 1).saturating_mul(page_size),
+    )?;
+
+    let mut posts_by_uuid: HashMap<String, PublicPostResponse> = search_db_state
+        .lock()
+        .unwrap()
+        .get_posts(&post_uuids)?
+        .into_iter()
+        .map(|post| (post.post_uuid.clone(), PublicPostResponse::from(post)))
+        .collect();
+    let mut posts: Vec<PublicPostResponse> = post_uuids
+        .iter()
+        .filter_map(|post_uuid| posts_by_uuid.remove(post_uuid))
+        .collect();
+    load_public_link_previews(&link_preview_db_state, &mut posts);
+
+    return Ok(web::Json(TimelineResponse {
+        page,
+        page_size,
+        total,
+        posts,
+    }));
+}
+
+#[get("/{user}/follow-counts")]
+async fn blog_get_follow_counts(
+    path: web::Path<String>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, SocialError> {
+    let user = path.into_inner();
+    log::info!("/users/{}/follow-counts", user);
+
+    let user = find_author(&user_db_state.lock().unwrap(), &user)?;
+    let (followers, following) = social_db_state.lock().unwrap().follow_counts(&user.uuid)?;
+
+    return Ok(web::Json(FollowCountsResponse {
+        user: AuthorResponse::from(user),
+        followers,
+        following,
+    }));
+}
+
+/// Users following the given user, the most recent followers first. Signed in readers don't
+/// see users blocking them or blocked by them, and don't find the lists of those users.
+#[get("/{user}/followers")]
+async fn blog_get_followers(
+    user_auth: Option<UserAuthentication>,
+    path: web::Path<String>,
+    page_query: web::Query<PageQuery>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, SocialError> {
+    let user = path.into_inner();
+    log::info!("/users/{}/followers {:?}", user, page_query);
+
+    let user = find_author(&user_db_state.lock().unwrap(), &user)?;
+    let (page, page_size) = page_params(page_query.page, page_query.page_size);
+    let (total, entries, blocked_uuids) = {
+        let social_db_service = social_db_state.lock().unwrap();
+        let blocked_uuids = reader_blocked_uuids(&social_db_service, user_auth.as_ref())?;
+        if blocked_uuids.contains(&user.uuid) {
+            return Err(SocialError::UserNotFound);
+        }
+        let (followers, _following) = social_db_service.follow_counts(&user.uuid)?;
+        let entries = social_db_service.get_followers(
+            &user.uuid,
+            page_size,
+            (page - 1).saturating_mul(page_size),
+        )?;
+        (followers, entries, blocked_uuids)
+    };
+
+    return Ok(web::Json(FollowListResponse {
+        user: AuthorResponse::from(user),
+        page,
+        page_size,
+        total,
+        users: follow_users(&user_db_state.lock().unwrap(), entries, &blocked_uuids),
+    }));
+}
+
+/// Users the given user follows, the most recently followed first. Signed in readers don't
+/// see users blocking them or blocked by them, and don't find the lists of those users.
+#[get("/{user}/following")]
+async fn blog_get_following(
+    user_auth: Option<UserAuthentication>,
+    path: web::Path<String>,
+    page_query: web::Query<PageQuery>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+) -> Result<impl Responder, SocialError> {
+    let user = path.into_inner();
+    log::info!("/users/{}/following {:?}", user, page_query);
+
+    let user = find_author(&user_db_state.lock().unwrap(), &user)?;
+    let (page, page_size) = page_params(page_query.page, page_query.page_size);
+    let (total, entries, blocked_uuids) = {
+        let social_db_service = social_db_state.lock().unwrap();
+        let blocked_uuids = reader_blocked_uuids(&social_db_service, user_auth.as_ref())?;
+        if blocked_uuids.contains(&user.uuid) {
+            return Err(SocialError::UserNotFound);
+        }
+        let (_followers, following) = social_db_service.follow_counts(&user.uuid)?;
+        let entries = social_db_service.get_following(
+            &user.uuid,
+            page_size,
+            (page - 1).saturating_mul(page_size),
+        )?;
+        (following, entries, blocked_uuids)
+    };
+
+    return Ok(web::Json(FollowListResponse {
+        user: AuthorResponse::from(user),
+        page,
+        page_size,
+        total,
+        users: follow_users(&user_db_state.lock().unwrap(), entries, &blocked_uuids),
+    }));
+}
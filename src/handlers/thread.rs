@@ -0,0 +1,289 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use actix_web::{get, http::StatusCode, web, HttpResponse, Responder, ResponseError};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::services::{
+    env_settings::EnvSettings,
+    link_preview_db_service::LinkPreviewDbService,
+    post_db_service::{Post, PostDbError, PostDbService},
+    search_db_service::{PublicPost, SearchDbError, SearchDbService},
+    social_db_service::{ReplyLink, SocialDbError, SocialDbService, ThreadReply},
+};
+
+use super::{
+    error_response::AppErrorResponse,
+    search::{load_public_link_previews, PublicPostResponse},
+    user_auth_token_extractor::UserAuthentication,
+};
+
+const DEFAULT_THREAD_DEPTH: u32 = 5;
+const MAX_THREAD_DEPTH: u32 = 10;
+/// Upper bound of replies loaded for one thread, deeper levels are cut off first.
+const MAX_THREAD_REPLIES: u32 = 500;
+const MAX_THREAD_ANCESTORS: u32 = 100;
+
+#[derive(Serialize, Debug, Display)]
+pub enum ThreadError {
+    GenericError = 30201,
+    PostNotFound,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ThreadQuery {
+    /// levels of replies to include below the post
+    depth: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ThreadPostResponse {
+    post_uuid: String,
+    /// `None` when the post was deleted or isn't public anymore
+    post: Option<PublicPostResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ThreadNodeResponse {
+    post_uuid: String,
+    /// `None` when the post was deleted or isn't public anymore, its replies are still listed
+    post: Option<PublicPostResponse>,
+    replies: Vec<ThreadNodeResponse>,
+    /// there are replies below the depth limit, request the thread of this post to see them
+    more_replies: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ThreadResponse {
+    /// the posts this one answers, the start of the conversation first
+    ancestors: Vec<ThreadPostResponse>,
+    post: ThreadNodeResponse,
+}
+
+impl ResponseError for ThreadError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ThreadError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
+            ThreadError::PostNotFound => StatusCode::NOT_FOUND,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        match self {
+            ThreadError::GenericError => {
+                HttpResponse::build(status).json(AppErrorResponse::from(ThreadError::GenericError))
+            }
+            ThreadError::PostNotFound => {
+                HttpResponse::build(status).json(AppErrorResponse::from(ThreadError::PostNotFound))
+            }
+        }
+    }
+}
+
+impl From<SearchDbError> for ThreadError {
+    fn from(_value: SearchDbError) -> Self {
+        ThreadError::GenericError
+    }
+}
+
+impl From<SocialDbError> for ThreadError {
+    fn from(_value: SocialDbError) -> Self {
+        ThreadError::GenericError
+    }
+}
+
+/// Links a new reply to the post it answers. The reply is already saved at this point,
+/// failures are only logged.
+pub fn link_reply(
+    social_db_state: &Mutex<SocialDbService>,
+    user_uuid: &str,
+    post: &Post,
+    parent: &PublicPost,
+) {
+    let reply = ReplyLink {
+        post_uuid: post.uuid.clone(),
+        author_uuid: user_uuid.to_owned(),
+        parent_uuid: parent.post_uuid.clone(),
+        parent_author_uuid: parent.author_uuid.clone(),
+        created_at: post.created_at,
+    };
+    if let Err(err) = social_db_state.lock().unwrap().add_reply(&reply) {
+        log::error!("thread, linking reply failed {:?}", err);
+    }
+}
+
+/// Refreshes the reply count of the parent after a reply was written, changed its
+/// visibility or was deleted. The count is stored with the parent in the database of its
+/// author and in the search index, so every listing of the parent can show it.
+pub fn sync_reply_count(
+    env_settings: &EnvSettings,
+    search_db_state: &Mutex<SearchDbService>,
+    social_db_state: &Mutex<SocialDbService>,
+    post: &Post,
+    deleted: bool,
+) {
+    if post.reply_to.is_none() {
+        return;
+    }
+
+    let (reply, reply_count) = {
+        let social_db_service = social_db_state.lock().unwrap();
+        let result = social_db_service.get_reply(&post.uuid).and_then(|reply| {
+            let Some(reply) = reply else {
+                return Ok(None);
+            };
+            let public = !deleted && post.is_public();
+            social_db_service.set_reply_public(&reply.post_uuid, public)?;
+            let reply_count = social_db_service.count_replies(&reply.parent_uuid)?;
+            Ok(Some((reply, reply_count)))
+        });
+        match result {
+            Ok(Some(counted)) => counted,
+            Ok(None) => return,
+            Err(err) => {
+                log::error!("thread, counting replies failed {:?}", err);
+                return;
+            }
+        }
+    };
+
+    if PostDbService::exists(&env_settings.db_collection_path, &reply.parent_author_uuid) {
+        let result =
+            PostDbService::connect(&env_settings.db_collection_path, &reply.parent_author_uuid)
+                .and_then(|post_db_service| {
+                    post_db_service.set_reply_count(&reply.parent_uuid, reply_count)
+                });
+        match result {
+            // the parent was deleted, its replies live on
+            Ok(()) | Err(PostDbError::PostNotFound) => {}
+            Err(err) => log::error!("thread, storing reply count failed {:?}", err),
+        }
+    }
+    if let Err(err) = search_db_state
+        .lock()
+        .unwrap()
+        .set_reply_count(&reply.parent_uuid, reply_count)
+    {
+        log::error!("thread, indexing reply count failed {:?}", err);
+    }
+}
+
+/// Builds the reply tree below `post_uuid`. Posts which were deleted or aren't public
+/// anymore are kept as placeholders while they have visible replies, and dropped otherwise.
+fn thread_node(
+    post_uuid: &str,
+    posts: &mut HashMap<String, PublicPostResponse>,
+    replies: &HashMap<String, Vec<ThreadReply>>,
+    max_depth: u32,
+) -> Option<ThreadNodeResponse> {
+    let children = replies
+        .get(post_uuid)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let more_replies = children.iter().any(|reply| reply.depth > max_depth);
+    let child_nodes: Vec<ThreadNodeResponse> = children
+        .iter()
+        .filter(|reply| reply.depth <= max_depth)
+        .filter_map(|reply| thread_node(&reply.post_uuid, posts, replies, max_depth))
+        .collect();
+    let post = posts.remove(post_uuid);
+
+    if post.is_none() && child_nodes.is_empty() && !more_replies {
+        return None;
+    }
+    return Some(ThreadNodeResponse {
+        post_uuid: post_uuid.to_owned(),
+        post,
+        replies: child_nodes,
+        more_replies,
+    });
+}
+
+/// Conversation around a public post: the posts it answers and the tree of replies below it.
+/// Signed in readers don't find threads of users blocking them or blocked by them, posts of
+/// those users inside a thread are left as placeholders.
+#[get("/posts/{post_uuid}/thread")]
+async fn get_post_thread(
+    user_auth: Option<UserAuthentication>,
+    path: web::Path<String>,
+    query: web::Query<ThreadQuery>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
+) -> Result<impl Responder, ThreadError> {
+    let post_uuid = path.into_inner();
+    log::info!("/posts/{}/thread {:?}", post_uuid, query);
+
+    let max_depth = query
+        .depth
+        .unwrap_or(DEFAULT_THREAD_DEPTH)
+        .clamp(1, MAX_THREAD_DEPTH);
+
+    let (ancestors, thread_replies, blocked_uuids) = {
+        let social_db_service = social_db_state.lock().unwrap();
+        let ancestors = social_db_service.get_ancestors(&post_uuid, MAX_THREAD_ANCESTORS)?;
+        // one level more than requested, to know which posts have replies below the limit
+        let thread_replies =
+            social_db_service.get_thread_replies(&post_uuid, max_depth + 1, MAX_THREAD_REPLIES)?;
+        let blocked_uuids = match &user_auth {
+            Some(user_auth) => social_db_service.get_blocked_uuids(&user_auth.uuid)?,
+            None => Vec::new(),
+        };
+        (ancestors, thread_replies, blocked_uuids)
+    };
+
+    let mut post_uuids: Vec<String> = ancestors.clone();
+    post_uuids.push(post_uuid.clone());
+    post_uuids.extend(
+        thread_replies
+            .iter()
+            .filter(|reply| reply.depth <= max_depth)
+            .map(|reply| reply.post_uuid.clone()),
+    );
+    let mut posts: HashMap<String, PublicPost> = search_db_state
+        .lock()
+        .unwrap()
+        .get_posts(&post_uuids)?
+        .into_iter()
+        .map(|post| (post.post_uuid.clone(), post))
+        .collect();
+    if posts
+        .get(&post_uuid)
+        .is_some_and(|post| blocked_uuids.contains(&post.author_uuid))
+    {
+        return Err(ThreadError::PostNotFound);
+    }
+    posts.retain(|_, post| !blocked_uuids.contains(&post.author_uuid));
+    let mut posts: HashMap<String, PublicPostResponse> = posts
+        .into_iter()
+        .map(|(post_uuid, post)| (post_uuid, PublicPostResponse::from(post)))
+        .collect();
+    load_public_link_previews(&link_preview_db_state, posts.values_mut());
+
+    let mut replies: HashMap<String, Vec<ThreadReply>> = HashMap::new();
+    for reply in thread_replies {
+        replies
+            .entry(reply.parent_uuid.clone())
+            .or_default()
+            .push(reply);
+    }
+
+    let ancestors = ancestors
+        .into_iter()
+        .rev()
+        .map(|ancestor_uuid| ThreadPostResponse {
+            post: posts.remove(&ancestor_uuid),
+            post_uuid: ancestor_uuid,
+        })
+        .collect();
+    let post = thread_node(&post_uuid, &mut posts, &replies, max_depth)
+        .ok_or(ThreadError::PostNotFound)?;
+
+    return Ok(web::Json(ThreadResponse { ancestors, post }));
+}
@@ -1,25 +1,77 @@
-use std::{fs::File, path::Path};
+use std::sync::Mutex;
 
-use actix_web::{http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
+use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder, ResponseError};
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
 
-use crate::services::env_settings::EnvSettings;
+use crate::services::{
+    analytics_db_service::AnalyticsDbService,
+    env_settings::{EnvSettings, PostLimits},
+    link_preview_db_service::LinkPreviewDbService,
+    media_db_service::{MediaDbError, MediaDbService},
+    post_db_service::{
+        normalize_category, normalize_tags, NameCount, Post, PostDbError, PostDbService,
+        PostFilter, PostInput, TagMatch, Visibility,
+    },
+    post_entities::{extract_hashtags, text_length},
+    post_renderer::ContentFormat,
+    search_db_service::SearchDbService,
+    search_query::strip_highlight_markers,
+    social_db_service::{NotificationKind, SocialDbService, StreamEventKind},
+    user_db_service::UserDbService,
+};
 
-use super::{error_response::AppErrorResponse, user_auth_token_extractor::UserAuthentication};
+use super::{
+    analytics::remove_views,
+    engagement::remove_engagement,
+    error_response::AppErrorResponse,
+    events::{publish_event, publish_new_post, PostDeletedEvent},
+    link_preview::{
+        find_link_previews, previewed_links, request_link_previews, LinkPreviewResponse,
+    },
+    notification::{notify, sync_mentions},
+    poll::{register_poll, PollRequest, PollResponse},
+    social::{remove_from_timelines, sync_timelines},
+    thread::{link_reply, sync_reply_count},
+    user_auth_token_extractor::UserAuthentication,
+};
 
 #[derive(Serialize, Debug, Display)]
 pub enum UserPostError {
     GenericError = 20011,
     PostNotFound,
+    ReplyToNotFound,
+    AttachmentNotFound,
+    TooManyAttachments,
+    InvalidPoll,
+    TooManyPinnedPosts,
+    PinnedPostNotPublic,
+    TitleTooLong,
+    PostTooLong,
 }
 
+const MAX_POST_ATTACHMENTS: usize = 4;
+const MAX_PINNED_POSTS: usize = 5;
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct UserPostRequest {
     title: String,
     post: String,
+    #[serde(default)]
+    format: PostFormat,
+    #[serde(default)]
+    visibility: PostVisibility,
+    #[serde(default)]
+    tags: Vec<String>,
+    category: Option<String>,
+    /// uuid of a public post, possibly by another user, which the new post answers
+    reply_to: Option<String>,
+    /// uuids of media uploaded by the user, in display order
+    #[serde(default)]
+    attachments: Vec<String>,
+    /// can't be changed once the post is written
+    poll: Option<PollRequest>,
 }
 
 #[derive(Serialize, Debug)]
@@ -28,6 +80,70 @@ struct UserPostResponse {
     post_uuid: String,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Fields left out keep their current value. An empty `category` or `tags` list clears them,
+/// `#hashtags` in the post are always added to the tags. The poll of a post can't be
+/// changed.
+struct UserEditPostRequest {
+    post_uuid: String,
+    title: String,
+    post: String,
+    format: Option<PostFormat>,
+    visibility: Option<PostVisibility>,
+    tags: Option<Vec<String>>,
+    category: Option<String>,
+    /// an empty list removes all attachments
+    attachments: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PostFormat {
+    #[default]
+    Plain,
+    Markdown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PostVisibility {
+    #[default]
+    Public,
+    Unlisted,
+    Private,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum TagMatchQuery {
+    Any,
+    All,
+}
+
+/// Query string of post listings, `tags` is a comma separated list.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PostListQuery {
+    tags: Option<String>,
+    tag_match: Option<TagMatchQuery>,
+    category: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct UserDeletePostRequest {
+    post_uuid: String,
+}
+
+/// Replaces all pinned posts, an empty list unpins them.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PinPostsRequest {
+    /// uuids of own public posts, in the order they are shown
+    post_uuids: Vec<String>,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct PostGetByPostIdRequest {
@@ -36,9 +152,33 @@ struct PostGetByPostIdRequest {
 
 #[derive(Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-struct PostDataResponse {
+pub struct PostDataResponse {
+    post_uuid: String,
+    slug: String,
     title: String,
     post: String,
+    format: PostFormat,
+    /// sanitized html rendered from `post`
+    post_html: String,
+    visibility: PostVisibility,
+    category: Option<String>,
+    tags: Vec<String>,
+    created_at: i64,
+    updated_at: i64,
+    /// uuid of the post this one answers
+    reply_to: Option<String>,
+    reply_count: i64,
+    like_count: i64,
+    repost_count: i64,
+    /// hidden by a moderator, only the author can still read it
+    hidden: bool,
+    /// uuids of the attached media, `/media/{uuid}` describes each
+    attachments: Vec<String>,
+    poll: Option<PollResponse>,
+    /// previews of the first links in the post, once they are fetched
+    link_previews: Vec<LinkPreviewResponse>,
+    #[serde(skip)]
+    links: Vec<String>,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -47,11 +187,220 @@ struct PostListDataResponse {
     posts: Vec<PostDataResponse>,
 }
 
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TagCountResponse {
+    tag: String,
+    count: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TagListResponse {
+    tags: Vec<TagCountResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CategoryCountResponse {
+    category: String,
+    count: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CategoryListResponse {
+    categories: Vec<CategoryCountResponse>,
+}
+
+impl From<Post> for PostDataResponse {
+    fn from(value: Post) -> Self {
+        Self {
+            post_uuid: value.uuid,
+            slug: value.slug,
+            title: value.title,
+            post: value.post,
+            format: PostFormat::from(value.format),
+            post_html: value.post_html,
+            visibility: PostVisibility::from(value.visibility),
+            category: value.category,
+            tags: value.tags,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+            reply_to: value.reply_to,
+            reply_count: value.reply_count,
+            like_count: value.like_count,
+            repost_count: value.repost_count,
+            hidden: value.hidden,
+            attachments: value.attachments,
+            poll: value.poll.map(PollResponse::from),
+            link_previews: Vec::new(),
+            links: previewed_links(&value.links),
+        }
+    }
+}
+
+impl From<PostFormat> for ContentFormat {
+    fn from(value: PostFormat) -> Self {
+        match value {
+            PostFormat::Plain => ContentFormat::Plain,
+            PostFormat::Markdown => ContentFormat::Markdown,
+        }
+    }
+}
+
+impl From<ContentFormat> for PostFormat {
+    fn from(value: ContentFormat) -> Self {
+        match value {
+            ContentFormat::Plain => PostFormat::Plain,
+            ContentFormat::Markdown => PostFormat::Markdown,
+        }
+    }
+}
+
+impl From<PostVisibility> for Visibility {
+    fn from(value: PostVisibility) -> Self {
+        match value {
+            PostVisibility::Public => Visibility::Public,
+            PostVisibility::Unlisted => Visibility::Unlisted,
+            PostVisibility::Private => Visibility::Private,
+        }
+    }
+}
+
+impl From<Visibility> for PostVisibility {
+    fn from(value: Visibility) -> Self {
+        match value {
+            Visibility::Public => PostVisibility::Public,
+            Visibility::Unlisted => PostVisibility::Unlisted,
+            Visibility::Private => PostVisibility::Private,
+        }
+    }
+}
+
+/// Tags given with the post followed by the `#hashtags` written in its body.
+fn post_tags(mut tags: Vec<String>, post: &str) -> Vec<String> {
+    tags.extend(extract_hashtags(post));
+    return normalize_tags(&tags);
+}
+
+fn unique_uuids(uuids: Vec<String>) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::new();
+    for uuid in uuids {
+        if !unique.contains(&uuid) {
+            unique.push(uuid);
+        }
+    }
+    return unique;
+}
+
+/// Title and body lengths are counted in grapheme clusters, urls with a fixed length.
+pub fn check_post_length(
+    post_limits: &PostLimits,
+    title: &str,
+    post: &str,
+) -> Result<(), UserPostError> {
+    if text_length(title, post_limits.url_length) > post_limits.max_title_length {
+        return Err(UserPostError::TitleTooLong);
+    }
+    if text_length(post, post_limits.url_length) > post_limits.max_body_length {
+        return Err(UserPostError::PostTooLong);
+    }
+    return Ok(());
+}
+
+/// Only the own uploads of the author can be attached to a post.
+fn check_attachments(
+    media_db_state: &Mutex<MediaDbService>,
+    user_uuid: &str,
+    attachments: &[String],
+) -> Result<(), UserPostError> {
+    if attachments.len() > MAX_POST_ATTACHMENTS {
+        return Err(UserPostError::TooManyAttachments);
+    }
+
+    let media_db_service = media_db_state.lock().unwrap();
+    for attachment_uuid in attachments {
+        match media_db_service.get_attachment(attachment_uuid) {
+            Ok(attachment) if attachment.owner_uuid == user_uuid => {}
+            Ok(_) | Err(MediaDbError::AttachmentNotFound) => {
+                return Err(UserPostError::AttachmentNotFound)
+            }
+            Err(MediaDbError::GenericError | MediaDbError::QuotaExceeded) => {
+                return Err(UserPostError::GenericError)
+            }
+        }
+    }
+    return Ok(());
+}
+
+impl From<UserPostRequest> for PostInput {
+    fn from(value: UserPostRequest) -> Self {
+        let tags = post_tags(value.tags, &value.post);
+        Self {
+            title: value.title,
+            post: value.post,
+            format: ContentFormat::from(value.format),
+            visibility: Visibility::from(value.visibility),
+            category: normalize_category(value.category.as_deref()),
+            tags,
+            attachments: unique_uuids(value.attachments),
+        }
+    }
+}
+
+impl UserEditPostRequest {
+    fn into_post_input(self, current: Post) -> PostInput {
+        let tags = post_tags(self.tags.unwrap_or(current.tags), &self.post);
+        PostInput {
+            title: self.title,
+            post: self.post,
+            format: self.format.map_or(current.format, ContentFormat::from),
+            visibility: self.visibility.map_or(current.visibility, Visibility::from),
+            category: match self.category {
+                Some(category) => normalize_category(Some(&category)),
+                None => current.category,
+            },
+            tags,
+            attachments: self.attachments.map_or(current.attachments, unique_uuids),
+        }
+    }
+}
+
+impl From<PostListQuery> for PostFilter {
+    fn from(value: PostListQuery) -> Self {
+        let tags: Vec<String> = value
+            .tags
+            .map(|tags| tags.split(',').map(str::to_owned).collect())
+            .unwrap_or_default();
+
+        Self {
+            tags: normalize_tags(&tags),
+            tag_match: match value.tag_match {
+                Some(TagMatchQuery::All) => TagMatch::All,
+                _ => TagMatch::Any,
+            },
+            category: normalize_category(value.category.as_deref()),
+            visibilities: Vec::new(),
+            exclude_hidden: false,
+            exclude_pinned: false,
+        }
+    }
+}
+
 impl ResponseError for UserPostError {
     fn status_code(&self) -> StatusCode {
         match self {
             UserPostError::GenericError => StatusCode::INTERNAL_SERVER_ERROR,
             UserPostError::PostNotFound => StatusCode::NOT_FOUND,
+            UserPostError::ReplyToNotFound => StatusCode::BAD_REQUEST,
+            UserPostError::AttachmentNotFound => StatusCode::BAD_REQUEST,
+            UserPostError::TooManyAttachments => StatusCode::BAD_REQUEST,
+            UserPostError::InvalidPoll => StatusCode::BAD_REQUEST,
+            UserPostError::TooManyPinnedPosts => StatusCode::BAD_REQUEST,
+            UserPostError::PinnedPostNotPublic => StatusCode::BAD_REQUEST,
+            UserPostError::TitleTooLong => StatusCode::BAD_REQUEST,
+            UserPostError::PostTooLong => StatusCode::BAD_REQUEST,
         }
     }
 
@@ -63,66 +412,286 @@ impl ResponseError for UserPostError {
                 .json(AppErrorResponse::from(UserPostError::GenericError)),
             UserPostError::PostNotFound => HttpResponse::build(status)
                 .json(AppErrorResponse::from(UserPostError::PostNotFound)),
+            UserPostError::ReplyToNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(UserPostError::ReplyToNotFound)),
+            UserPostError::AttachmentNotFound => HttpResponse::build(status)
+                .json(AppErrorResponse::from(UserPostError::AttachmentNotFound)),
+            UserPostError::TooManyAttachments => HttpResponse::build(status)
+                .json(AppErrorResponse::from(UserPostError::TooManyAttachments)),
+            UserPostError::InvalidPoll => {
+                HttpResponse::build(status).json(AppErrorResponse::from(UserPostError::InvalidPoll))
+            }
+            UserPostError::TooManyPinnedPosts => HttpResponse::build(status)
+                .json(AppErrorResponse::from(UserPostError::TooManyPinnedPosts)),
+            UserPostError::PinnedPostNotPublic => HttpResponse::build(status)
+                .json(AppErrorResponse::from(UserPostError::PinnedPostNotPublic)),
+            UserPostError::TitleTooLong => HttpResponse::build(status)
+                .json(AppErrorResponse::from(UserPostError::TitleTooLong)),
+            UserPostError::PostTooLong => {
+                HttpResponse::build(status).json(AppErrorResponse::from(UserPostError::PostTooLong))
+            }
+        }
+    }
+}
+
+impl From<PostDbError> for UserPostError {
+    fn from(value: PostDbError) -> Self {
+        match value {
+            PostDbError::PostNotFound => UserPostError::PostNotFound,
+            _ => UserPostError::GenericError,
         }
     }
 }
 
+/// Refreshes the site-wide search index after a post was written, only public posts are
+/// listed there. The post itself is already saved at this point, so failures are only
+/// logged, `cargo run -- reindex` repairs the index.
+pub fn sync_search_index(
+    user_db_state: &Mutex<UserDbService>,
+    search_db_state: &Mutex<SearchDbService>,
+    user_uuid: &str,
+    post: &Post,
+) {
+    if !post.is_public() {
+        remove_from_search_index(search_db_state, &post.uuid);
+        return;
+    }
+
+    let author = match user_db_state.lock().unwrap().get_user_from_uuid(user_uuid) {
+        Ok(author) => author,
+        Err(err) => {
+            log::error!("search index, author lookup failed {:?}", err);
+            return;
+        }
+    };
+
+    let mut search_db_service = search_db_state.lock().unwrap();
+    if let Err(err) = search_db_service.index_post(user_uuid, &author.display_name, post) {
+        log::error!("search index, indexing post failed {:?}", err);
+    }
+}
+
+/// Adds the poll votes `reader_uuid` may see to posts, failures only leave them hidden.
+pub fn load_poll_votes(
+    social_db_state: &Mutex<SocialDbService>,
+    posts: &mut [PostDataResponse],
+    reader_uuid: Option<&str>,
+) {
+    let social_db_service = social_db_state.lock().unwrap();
+    for post in posts.iter_mut() {
+        if let Some(poll) = &mut post.poll {
+            if let Err(err) = poll.load_votes(&social_db_service, &post.post_uuid, reader_uuid) {
+                log::error!("poll, loading votes failed {:?}", err);
+            }
+        }
+    }
+}
+
+/// Adds the previews fetched for the links of posts, failures only leave them out.
+pub fn load_link_previews(
+    link_preview_db_state: &Mutex<LinkPreviewDbService>,
+    posts: &mut [PostDataResponse],
+) {
+    let link_preview_db_service = link_preview_db_state.lock().unwrap();
+    for post in posts.iter_mut() {
+        post.link_previews = find_link_previews(&link_preview_db_service, &post.links);
+    }
+}
+
+pub fn remove_from_search_index(search_db_state: &Mutex<SearchDbService>, post_uuid: &str) {
+    if let Err(err) = search_db_state.lock().unwrap().remove_post(post_uuid) {
+        log::error!("search index, removing post failed {:?}", err);
+    }
+}
+
 #[post("/post")]
+#[allow(clippy::too_many_arguments)]
 async fn user_post(
     user_auth: UserAuthentication,
     param_obj: web::Json<UserPostRequest>,
     env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    media_db_state: web::Data<Mutex<MediaDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
 ) -> Result<impl Responder, UserPostError> {
-    let payload = param_obj.into_inner();
+    let mut payload = param_obj.into_inner();
     log::info!("/post {:?}", payload);
+    payload.title = strip_highlight_markers(&payload.title);
+    payload.post = strip_highlight_markers(&payload.post);
+    check_post_length(&env_settings.post_limits, &payload.title, &payload.post)?;
 
-    let user_db_file = format!(
-        "{}/user_{}.db",
-        env_settings.db_collection_path, user_auth.uuid
-    );
-
-    if !Path::new(&user_db_file).exists() {
-        // Only create the file if it doesn't exist
-        if let Err(err) = File::create(&user_db_file) {
-            log::error!("{:?}", err);
-            return Err(UserPostError::GenericError);
-        }
-    }
-
-    let res = match rusqlite::Connection::open(&user_db_file) {
-        Ok(conn) => {
-            match conn.execute(
-                "CREATE TABLE IF NOT EXISTS post (
-                 id      INTEGER PRIMARY KEY,
-                 title   TEXT NOT NULL,
-                 post    TEXT NOT NULL,
-                 uuid    TEXT NOT NULL UNIQUE
-          )",
-                (), // empty list of parameters.
-            ) {
-                Ok(_) => {
-                    let post_uuid = Uuid::new_v4();
-                    let post_uuid_str = post_uuid.to_string();
-                    if let Err(err) = conn.execute(
-                        "INSERT INTO post (title, post, uuid) VALUES (?1, ?2, ?3)",
-                        (&payload.title, &payload.post, &post_uuid_str),
-                    ) {
-                        log::error!("insert post error, {:?}", err);
-                        Err(UserPostError::GenericError)
-                    } else {
-                        Ok(post_uuid_str.clone())
-                    }
-                }
-                Err(_) => Err(UserPostError::GenericError),
+    // only public posts can be answered, the search index lists exactly those, and posts of
+    // users blocking the author or blocked by them are invisible
+    let parent = match payload.reply_to.take() {
+        Some(reply_to) => {
+            let parent = search_db_state
+                .lock()
+                .unwrap()
+                .get_posts(&[reply_to])
+                .map_err(|_| UserPostError::GenericError)?
+                .pop()
+                .ok_or(UserPostError::ReplyToNotFound)?;
+            if social_db_state
+                .lock()
+                .unwrap()
+                .is_blocked(&user_auth.uuid, &parent.author_uuid)
+                .map_err(|_| UserPostError::GenericError)?
+            {
+                return Err(UserPostError::ReplyToNotFound);
             }
+            Some(parent)
         }
-        Err(_) => Err(UserPostError::GenericError),
+        None => None,
+    };
+
+    let poll = match payload.poll.take() {
+        Some(poll) => Some(poll.into_poll().ok_or(UserPostError::InvalidPoll)?),
+        None => None,
     };
+    let input = PostInput::from(payload);
+    check_attachments(&media_db_state, &user_auth.uuid, &input.attachments)?;
+    let mut post_db_service =
+        PostDbService::connect(&env_settings.db_collection_path, &user_auth.uuid)?;
+    let post = post_db_service.add_post(
+        &input,
+        parent.as_ref().map(|parent| parent.post_uuid.as_str()),
+        poll.as_ref(),
+    )?;
+    if post.poll.is_some() {
+        register_poll(&social_db_state, &user_auth.uuid, &post.uuid);
+    }
+    sync_search_index(&user_db_state, &search_db_state, &user_auth.uuid, &post);
+    sync_timelines(&social_db_state, &user_auth.uuid, &post);
+    publish_new_post(&search_db_state, &social_db_state, &user_auth.uuid, &post);
+    sync_mentions(&user_db_state, &social_db_state, &user_auth.uuid, &post);
+    request_link_previews(&link_preview_db_state, &post);
+    if let Some(parent) = &parent {
+        link_reply(&social_db_state, &user_auth.uuid, &post, parent);
+        if post.is_readable_by_others() {
+            notify(
+                &user_db_state,
+                &social_db_state,
+                &parent.author_uuid,
+                NotificationKind::Reply,
+                &user_auth.uuid,
+                Some(&post.uuid),
+            );
+        }
+        sync_reply_count(
+            &env_settings,
+            &search_db_state,
+            &social_db_state,
+            &post,
+            false,
+        );
+    }
 
-    match res {
-        Ok(post_uuid) => Ok(web::Json(UserPostResponse { post_uuid })),
-        Err(err) => Err(err),
+    return Ok(web::Json(UserPostResponse {
+        post_uuid: post.uuid,
+    }));
+}
+
+#[post("/edit-post")]
+#[allow(clippy::too_many_arguments)]
+async fn user_edit_post(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<UserEditPostRequest>,
+    env_settings: web::Data<EnvSettings>,
+    user_db_state: web::Data<Mutex<UserDbService>>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    media_db_state: web::Data<Mutex<MediaDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
+) -> Result<impl Responder, UserPostError> {
+    let mut payload = param_obj.into_inner();
+    log::info!("/edit-post {:?}", payload);
+    payload.title = strip_highlight_markers(&payload.title);
+    payload.post = strip_highlight_markers(&payload.post);
+    check_post_length(&env_settings.post_limits, &payload.title, &payload.post)?;
+
+    if !PostDbService::exists(&env_settings.db_collection_path, &user_auth.uuid) {
+        return Err(UserPostError::PostNotFound);
     }
+
+    let mut post_db_service =
+        PostDbService::connect(&env_settings.db_collection_path, &user_auth.uuid)?;
+    let post_uuid = payload.post_uuid.clone();
+    let current = post_db_service.get_post(&post_uuid)?;
+    let input = payload.into_post_input(current);
+    check_attachments(&media_db_state, &user_auth.uuid, &input.attachments)?;
+    let post = post_db_service.update_post(&post_uuid, &input)?;
+    sync_search_index(&user_db_state, &search_db_state, &user_auth.uuid, &post);
+    sync_timelines(&social_db_state, &user_auth.uuid, &post);
+    sync_mentions(&user_db_state, &social_db_state, &user_auth.uuid, &post);
+    request_link_previews(&link_preview_db_state, &post);
+    sync_reply_count(
+        &env_settings,
+        &search_db_state,
+        &social_db_state,
+        &post,
+        false,
+    );
+
+    let mut response = PostDataResponse::from(post);
+    load_poll_votes(
+        &social_db_state,
+        std::slice::from_mut(&mut response),
+        Some(&user_auth.uuid),
+    );
+    load_link_previews(&link_preview_db_state, std::slice::from_mut(&mut response));
+    publish_event(
+        &social_db_state,
+        &[user_auth.uuid],
+        StreamEventKind::PostUpdated,
+        &response,
+    );
+    return Ok(web::Json(response));
+}
+
+#[post("/delete-post")]
+async fn user_delete_post(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<UserDeletePostRequest>,
+    env_settings: web::Data<EnvSettings>,
+    search_db_state: web::Data<Mutex<SearchDbService>>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    analytics_db_state: web::Data<Mutex<AnalyticsDbService>>,
+) -> Result<impl Responder, UserPostError> {
+    let payload = param_obj.into_inner();
+    log::info!("/delete-post {:?}", payload);
+
+    if !PostDbService::exists(&env_settings.db_collection_path, &user_auth.uuid) {
+        return Err(UserPostError::PostNotFound);
+    }
+
+    let mut post_db_service =
+        PostDbService::connect(&env_settings.db_collection_path, &user_auth.uuid)?;
+    let post = post_db_service.delete_post(&payload.post_uuid)?;
+    remove_from_search_index(&search_db_state, &post.uuid);
+    remove_from_timelines(&social_db_state, &post.uuid);
+    remove_engagement(&social_db_state, &post.uuid);
+    remove_views(&analytics_db_state, &post.uuid);
+    sync_reply_count(
+        &env_settings,
+        &search_db_state,
+        &social_db_state,
+        &post,
+        true,
+    );
+    publish_event(
+        &social_db_state,
+        &[user_auth.uuid],
+        StreamEventKind::PostDeleted,
+        &PostDeletedEvent {
+            post_uuid: post.uuid.clone(),
+        },
+    );
+
+    return Ok(web::Json(UserPostResponse {
+        post_uuid: post.uuid,
+    }));
 }
 
 #[post("/get-post-by-id")]
@@ -130,87 +699,180 @@ async fn user_get_post_by_id(
     user_auth: UserAuthentication,
     param_obj: web::Json<PostGetByPostIdRequest>,
     env_settings: web::Data<EnvSettings>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
 ) -> Result<impl Responder, UserPostError> {
     let payload = param_obj.into_inner();
     log::info!("/get-post-by-id {:?}", payload);
 
-    let user_db_file = format!(
-        "{}/user_{}.db",
-        env_settings.db_collection_path, user_auth.uuid
-    );
-
-    if !Path::new(&user_db_file).exists() {
+    if !PostDbService::exists(&env_settings.db_collection_path, &user_auth.uuid) {
         log::error!("user DB does not exist! {:?}", user_auth.uuid);
         return Err(UserPostError::GenericError);
     }
 
-    if let Ok(conn) = rusqlite::Connection::open(&user_db_file) {
-        if let Ok(mut statement) =
-            conn.prepare("SELECT title, post FROM post WHERE uuid=:uuid limit 1;")
-        {
-            if let Ok(post_iter) = statement.query_map(&[(":uuid", &payload.post_uuid)], |row| {
-                let post = PostDataResponse {
-                    title: row.get(0)?,
-                    post: row.get(1)?,
-                };
-                Ok(post)
-            }) {
-                let post_vec: Vec<_> = post_iter.collect();
-
-                if let Some(selected_post) = post_vec.get(0) {
-                    if let Ok(post) = selected_post {
-                        return Ok(web::Json(post.clone()));
-                    }
-                }
-            }
-        }
-    }
+    let post_db_service =
+        PostDbService::connect(&env_settings.db_collection_path, &user_auth.uuid)?;
+    let post = post_db_service.get_post(&payload.post_uuid)?;
+    let mut response = PostDataResponse::from(post);
+    load_poll_votes(
+        &social_db_state,
+        std::slice::from_mut(&mut response),
+        Some(&user_auth.uuid),
+    );
+    load_link_previews(&link_preview_db_state, std::slice::from_mut(&mut response));
 
-    return Err(UserPostError::PostNotFound);
+    return Ok(web::Json(response));
 }
 
 #[post("/get-posts")]
 async fn user_get_posts(
     user_auth: UserAuthentication,
+    query: web::Query<PostListQuery>,
     env_settings: web::Data<EnvSettings>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
 ) -> Result<impl Responder, UserPostError> {
-    log::info!("/get-posts");
+    let query = query.into_inner();
+    log::info!("/get-posts {:?}", query);
 
-    let user_db_file = format!(
-        "{}/user_{}.db",
-        env_settings.db_collection_path, user_auth.uuid
-    );
-
-    if !Path::new(&user_db_file).exists() {
+    if !PostDbService::exists(&env_settings.db_collection_path, &user_auth.uuid) {
         log::error!("user DB does not exist! {:?}", user_auth.uuid);
         return Ok(web::Json(PostListDataResponse { posts: [].to_vec() }));
     }
 
-    if let Ok(conn) = rusqlite::Connection::open(&user_db_file) {
-        // todo: pagination
-        if let Ok(mut statement) = conn.prepare("SELECT title, post FROM post limit 100;") {
-            if let Ok(post_iter) = statement.query_map([], |row| {
-                let post = PostDataResponse {
-                    title: row.get(0)?,
-                    post: row.get(1)?,
-                };
-                Ok(post)
-            }) {
-                let post_vec: Vec<_> = post_iter
-                    .into_iter()
-                    .map(|d| {
-                        let post_data = d.unwrap();
-                        PostDataResponse {
-                            title: post_data.title.clone(),
-                            post: post_data.post.clone(),
-                        }
-                    })
-                    .collect();
-
-                return Ok(web::Json(PostListDataResponse { posts: post_vec }));
-            }
+    let post_db_service =
+        PostDbService::connect(&env_settings.db_collection_path, &user_auth.uuid)?;
+    // todo: pagination
+    let mut posts: Vec<PostDataResponse> = post_db_service
+        .get_posts(&PostFilter::from(query), 100, 0)?
+        .into_iter()
+        .map(PostDataResponse::from)
+        .collect();
+    load_poll_votes(&social_db_state, &mut posts, Some(&user_auth.uuid));
+    load_link_previews(&link_preview_db_state, &mut posts);
+
+    return Ok(web::Json(PostListDataResponse { posts }));
+}
+
+#[get("/tags")]
+async fn user_get_tags(
+    user_auth: UserAuthentication,
+    env_settings: web::Data<EnvSettings>,
+) -> Result<impl Responder, UserPostError> {
+    log::info!("/tags");
+
+    if !PostDbService::exists(&env_settings.db_collection_path, &user_auth.uuid) {
+        return Ok(web::Json(TagListResponse { tags: Vec::new() }));
+    }
+
+    let post_db_service =
+        PostDbService::connect(&env_settings.db_collection_path, &user_auth.uuid)?;
+    let tags = post_db_service
+        .get_tags()?
+        .into_iter()
+        .map(|NameCount { name, count }| TagCountResponse { tag: name, count })
+        .collect();
+
+    return Ok(web::Json(TagListResponse { tags }));
+}
+
+#[get("/categories")]
+async fn user_get_categories(
+    user_auth: UserAuthentication,
+    env_settings: web::Data<EnvSettings>,
+) -> Result<impl Responder, UserPostError> {
+    log::info!("/categories");
+
+    if !PostDbService::exists(&env_settings.db_collection_path, &user_auth.uuid) {
+        return Ok(web::Json(CategoryListResponse {
+            categories: Vec::new(),
+        }));
+    }
+
+    let post_db_service =
+        PostDbService::connect(&env_settings.db_collection_path, &user_auth.uuid)?;
+    let categories = post_db_service
+        .get_categories()?
+        .into_iter()
+        .map(|NameCount { name, count }| CategoryCountResponse {
+            category: name,
+            count,
+        })
+        .collect();
+
+    return Ok(web::Json(CategoryListResponse { categories }));
+}
+
+/// Pins up to five own posts to the top of the public blog listing, in the given order.
+/// Only public posts can be pinned, a pinned post which later turns private or hidden is
+/// left out of the public listing until it is public again.
+#[post("/pin-posts")]
+async fn user_pin_posts(
+    user_auth: UserAuthentication,
+    param_obj: web::Json<PinPostsRequest>,
+    env_settings: web::Data<EnvSettings>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
+) -> Result<impl Responder, UserPostError> {
+    let payload = param_obj.into_inner();
+    log::info!("/pin-posts {:?}", payload);
+
+    let post_uuids = unique_uuids(payload.post_uuids);
+    if post_uuids.len() > MAX_PINNED_POSTS {
+        return Err(UserPostError::TooManyPinnedPosts);
+    }
+    if !PostDbService::exists(&env_settings.db_collection_path, &user_auth.uuid) {
+        if post_uuids.is_empty() {
+            return Ok(web::Json(PostListDataResponse { posts: Vec::new() }));
         }
+        return Err(UserPostError::PostNotFound);
     }
 
-    return Err(UserPostError::PostNotFound);
+    let mut post_db_service =
+        PostDbService::connect(&env_settings.db_collection_path, &user_auth.uuid)?;
+    for post_uuid in &post_uuids {
+        let post = post_db_service.get_post(post_uuid)?;
+        if !post.is_public() || post.hidden {
+            return Err(UserPostError::PinnedPostNotPublic);
+        }
+    }
+    post_db_service.set_pinned_posts(&post_uuids)?;
+
+    let mut posts: Vec<PostDataResponse> = post_db_service
+        .get_pinned_posts(&PostFilter::default())?
+        .into_iter()
+        .map(PostDataResponse::from)
+        .collect();
+    load_poll_votes(&social_db_state, &mut posts, Some(&user_auth.uuid));
+    load_link_previews(&link_preview_db_state, &mut posts);
+
+    return Ok(web::Json(PostListDataResponse { posts }));
+}
+
+/// The pinned posts in their order, including those currently left out of the public
+/// listing.
+#[get("/pinned-posts")]
+async fn user_get_pinned_posts(
+    user_auth: UserAuthentication,
+    env_settings: web::Data<EnvSettings>,
+    social_db_state: web::Data<Mutex<SocialDbService>>,
+    link_preview_db_state: web::Data<Mutex<LinkPreviewDbService>>,
+) -> Result<impl Responder, UserPostError> {
+    log::info!("/pinned-posts");
+
+    if !PostDbService::exists(&env_settings.db_collection_path, &user_auth.uuid) {
+        return Ok(web::Json(PostListDataResponse { posts: Vec::new() }));
+    }
+
+    let post_db_service =
+        PostDbService::connect(&env_settings.db_collection_path, &user_auth.uuid)?;
+    let mut posts: Vec<PostDataResponse> = post_db_service
+        .get_pinned_posts(&PostFilter::default())?
+        .into_iter()
+        .map(PostDataResponse::from)
+        .collect();
+    load_poll_votes(&social_db_state, &mut posts, Some(&user_auth.uuid));
+    load_link_previews(&link_preview_db_state, &mut posts);
+
+    return Ok(web::Json(PostListDataResponse { posts }));
 }
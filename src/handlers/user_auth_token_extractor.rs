@@ -1,14 +1,16 @@
 use actix_web::dev::Payload;
-use actix_web::error::ErrorUnauthorized;
+use actix_web::error::{ErrorForbidden, ErrorUnauthorized};
 use actix_web::http::header::HeaderValue;
+use actix_web::http::Method;
 use actix_web::{web, Error as ActixWebError, FromRequest, HttpRequest};
 use jsonwebtoken::{
     decode, errors::Error as JwtError, Algorithm, DecodingKey, TokenData, Validation,
 };
 use serde::{Deserialize, Serialize};
 use std::future::{ready, Ready};
+use std::sync::Mutex;
 
-use crate::services::env_settings::EnvSettings;
+use crate::services::{env_settings::EnvSettings, moderation_db_service::ModerationDbService};
 
 use super::auth::UserClaims;
 
@@ -18,6 +20,17 @@ pub struct UserAuthentication {
     pub uuid: String,
 }
 
+fn is_suspended(req: &HttpRequest, user_uuid: &str) -> bool {
+    let Some(moderation_db_state) = req.app_data::<web::Data<Mutex<ModerationDbService>>>() else {
+        return false;
+    };
+    return moderation_db_state
+        .lock()
+        .unwrap()
+        .is_suspended(user_uuid)
+        .unwrap_or(false);
+}
+
 impl FromRequest for UserAuthentication {
     type Error = ActixWebError;
     type Future = Ready<Result<Self, Self::Error>>;
@@ -52,6 +65,10 @@ impl FromRequest for UserAuthentication {
         match token_result {
             Ok(token) => {
                 let user_claims = token.claims;
+                // suspended users keep read access, every other request is refused
+                if req.method() != Method::GET && is_suspended(&req, &user_claims.uuid) {
+                    return ready(Err(ErrorForbidden("Account suspended!")));
+                }
                 ready(Ok(UserAuthentication {
                     authentication_token,
                     uuid: user_claims.uuid,
@@ -1,17 +1,65 @@
+#![allow(clippy::needless_return)]
+
+mod commands;
 mod handlers;
 mod services;
 
-use std::sync::Mutex;
+use std::{env, sync::Mutex};
 
 use actix_web::{error, middleware, web, App, HttpResponse, HttpServer};
 use dotenv::dotenv;
 use handlers::{
+    analytics::user_get_analytics,
     auth::{auth_login, auth_register, AppError},
+    block::{user_block, user_get_blocks, user_get_mutes, user_mute, user_unblock, user_unmute},
+    blog::{blog_get_post, blog_get_post_by_slug, blog_get_posts},
+    engagement::{
+        get_post_likes, get_post_reposts, user_bookmark, user_get_bookmarks, user_like,
+        user_repost, user_unbookmark, user_unlike, user_unrepost,
+    },
     error_response::AppErrorResponse,
+    events::user_events,
+    feed::{blog_feed, blog_tag_feed, site_feed_all, site_tag_feed},
     health_check::health_check,
-    user::{user_get_post_by_id, user_get_posts, user_post},
+    media::{
+        get_media, get_media_file, get_media_variant, user_delete_media, user_get_media,
+        user_upload_media,
+    },
+    message::{
+        user_get_conversations, user_get_messages, user_mark_conversation_read, user_send_message,
+        user_start_conversation,
+    },
+    moderation::{
+        moderation_act_on_report, moderation_get_report, moderation_get_reports, user_report,
+    },
+    notification::{
+        user_get_notifications, user_get_unread_notification_count, user_mark_notifications_read,
+    },
+    poll::user_vote,
+    post_revision::{
+        user_get_post_diff, user_get_post_revision, user_get_post_revisions,
+        user_restore_post_revision,
+    },
+    profile::{blog_get_profile, user_edit_profile},
+    search::{search_public_posts, user_search_posts},
+    social::{
+        blog_get_follow_counts, blog_get_followers, blog_get_following, user_follow,
+        user_get_timeline, user_unfollow,
+    },
+    thread::get_post_thread,
+    user::{
+        user_delete_post, user_edit_post, user_get_categories, user_get_pinned_posts,
+        user_get_post_by_id, user_get_posts, user_get_tags, user_pin_posts, user_post,
+    },
+};
+use services::{
+    analytics_db_service::AnalyticsDbService, env_settings::EnvSettings,
+    link_preview_db_service::LinkPreviewDbService, link_preview_fetcher::spawn_link_preview_worker,
+    media_db_service::MediaDbService, media_processor::spawn_media_worker,
+    media_storage::MediaStorage, message_db_service::MessageDbService,
+    moderation_db_service::ModerationDbService, search_db_service::SearchDbService,
+    social_db_service::SocialDbService, user_db_service::UserDbService,
 };
-use services::{env_settings::EnvSettings, user_db_service::UserDbService};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -19,18 +67,64 @@ async fn main() -> std::io::Result<()> {
     dotenv().ok();
 
     let env_settings = EnvSettings::new();
+
+    if let Some(command) = env::args().nth(1) {
+        return commands::run(&command, &env_settings);
+    }
+
     let user_db_service = UserDbService::connect(&env_settings.db_collection_path)
         .expect("UserDbService error! db_collection_path folder maybe missing");
     let user_db_state = web::Data::new(Mutex::new(user_db_service));
+    let search_db_service = SearchDbService::connect(&env_settings.db_collection_path)
+        .expect("SearchDbService error! search index could not be opened");
+    let search_db_state = web::Data::new(Mutex::new(search_db_service));
+    let social_db_service = SocialDbService::connect(&env_settings.db_collection_path)
+        .expect("SocialDbService error! social db could not be opened");
+    let social_db_state = web::Data::new(Mutex::new(social_db_service));
+    let message_db_service = MessageDbService::connect(&env_settings.db_collection_path)
+        .expect("MessageDbService error! message db could not be opened");
+    let message_db_state = web::Data::new(Mutex::new(message_db_service));
+    let moderation_db_service = ModerationDbService::connect(&env_settings.db_collection_path)
+        .expect("ModerationDbService error! moderation db could not be opened");
+    let moderation_db_state = web::Data::new(Mutex::new(moderation_db_service));
+    let analytics_db_service = AnalyticsDbService::connect(&env_settings.db_collection_path)
+        .expect("AnalyticsDbService error! analytics db could not be opened");
+    let analytics_db_state = web::Data::new(Mutex::new(analytics_db_service));
+    let link_preview_db_service = LinkPreviewDbService::connect(&env_settings.db_collection_path)
+        .expect("LinkPreviewDbService error! link preview db could not be opened");
+    let link_preview_db_state = web::Data::new(Mutex::new(link_preview_db_service));
+    let media_db_service = MediaDbService::connect(&env_settings.db_collection_path)
+        .expect("MediaDbService error! media db could not be opened");
+    let media_db_state = web::Data::new(Mutex::new(media_db_service));
+    let media_storage = web::Data::new(
+        MediaStorage::new(&env_settings)
+            .expect("MediaStorage error! media folder could not be created"),
+    );
+    spawn_media_worker(
+        media_db_state.clone().into_inner(),
+        media_storage.clone().into_inner(),
+    );
+    spawn_link_preview_worker(
+        link_preview_db_state.clone().into_inner(),
+        env_settings.link_preview_allow_private_hosts,
+    );
 
     HttpServer::new(move || {
         App::new()
             .wrap(middleware::Logger::default())
             .app_data(web::Data::new(env_settings.clone()))
             .app_data(user_db_state.clone())
+            .app_data(search_db_state.clone())
+            .app_data(social_db_state.clone())
+            .app_data(message_db_state.clone())
+            .app_data(moderation_db_state.clone())
+            .app_data(media_db_state.clone())
+            .app_data(analytics_db_state.clone())
+            .app_data(link_preview_db_state.clone())
+            .app_data(media_storage.clone())
             .app_data(
                 web::JsonConfig::default()
-                    .limit(1024)
+                    .limit(env_settings.max_json_payload_bytes)
                     .error_handler(|err, _req| {
                         return error::InternalError::from_response(
                             err,
@@ -41,6 +135,12 @@ async fn main() -> std::io::Result<()> {
                     }),
             )
             .service(health_check)
+            .service(search_public_posts)
+            .service(site_feed_all)
+            .service(site_tag_feed)
+            .service(get_post_thread)
+            .service(get_post_likes)
+            .service(get_post_reposts)
             .service(
                 web::scope("/auth")
                     .service(auth_login)
@@ -49,9 +149,77 @@ async fn main() -> std::io::Result<()> {
             .service(
                 web::scope("/user")
                     .service(user_post)
+                    .service(user_edit_post)
+                    .service(user_delete_post)
                     .service(user_get_posts)
-                    .service(user_get_post_by_id),
+                    .service(user_get_post_by_id)
+                    .service(user_get_tags)
+                    .service(user_get_categories)
+                    .service(user_pin_posts)
+                    .service(user_get_pinned_posts)
+                    .service(user_search_posts)
+                    .service(user_get_post_revisions)
+                    .service(user_get_post_revision)
+                    .service(user_get_post_diff)
+                    .service(user_restore_post_revision)
+                    .service(user_follow)
+                    .service(user_unfollow)
+                    .service(user_block)
+                    .service(user_unblock)
+                    .service(user_mute)
+                    .service(user_unmute)
+                    .service(user_get_blocks)
+                    .service(user_get_mutes)
+                    .service(user_get_timeline)
+                    .service(user_like)
+                    .service(user_unlike)
+                    .service(user_repost)
+                    .service(user_unrepost)
+                    .service(user_bookmark)
+                    .service(user_unbookmark)
+                    .service(user_get_bookmarks)
+                    .service(user_vote)
+                    .service(user_edit_profile)
+                    .service(user_get_analytics)
+                    .service(user_get_notifications)
+                    .service(user_get_unread_notification_count)
+                    .service(user_mark_notifications_read)
+                    .service(user_start_conversation)
+                    .service(user_send_message)
+                    .service(user_get_conversations)
+                    .service(user_get_messages)
+                    .service(user_mark_conversation_read)
+                    .service(user_report)
+                    .service(user_upload_media)
+                    .service(user_get_media)
+                    .service(user_delete_media)
+                    .service(user_events),
+            )
+            .service(
+                web::scope("/media")
+                    .service(get_media)
+                    .service(get_media_file)
+                    .service(get_media_variant),
+            )
+            .service(
+                web::scope("/moderation")
+                    .service(moderation_get_reports)
+                    .service(moderation_get_report)
+                    .service(moderation_act_on_report),
+            )
+            .service(
+                web::scope("/users")
+                    .service(blog_get_posts)
+                    .service(blog_get_post)
+                    .service(blog_feed)
+                    .service(blog_tag_feed)
+                    .service(blog_get_follow_counts)
+                    .service(blog_get_followers)
+                    .service(blog_get_following)
+                    .service(blog_get_profile),
             )
+            // catch-all `/{displayName}/{slug}` permalinks, must stay the last service
+            .service(blog_get_post_by_slug)
     })
     .bind(("127.0.0.1", 8080))?
     .run()
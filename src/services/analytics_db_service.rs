@@ -0,0 +1,257 @@
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::db_migrations::migrate;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Debug)]
+pub enum AnalyticsDbError {
+    GenericError,
+}
+
+/// Post views, counted once per visitor and day, backed by `analytics.db` inside the db
+/// collection folder. Visitors are only known by a hash salted with a secret which lives in
+/// memory and changes every day, so neither raw addresses nor anything linking a visitor
+/// across days is ever stored. Only the current day's hashes are kept, the views go into
+/// daily rollups.
+#[derive(Debug)]
+pub struct AnalyticsDbService {
+    conn: Connection,
+    /// the day the salt belongs to, and the salt
+    salt: (i64, [u8; 16]),
+}
+
+/// A view as the handlers see it, the visitor fields are hashed before anything is stored.
+#[derive(Debug)]
+pub struct PostView<'a> {
+    pub author_uuid: &'a str,
+    pub post_uuid: &'a str,
+    /// the signed in reader, or the remote address and user agent of anonymous ones
+    pub visitor: &'a str,
+    /// host of the referring page, if the view came from another site
+    pub referrer: Option<&'a str>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DayCount {
+    /// days since the unix epoch
+    pub day: i64,
+    pub views: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct NameViews {
+    pub name: String,
+    pub views: i64,
+}
+
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE post_view_visitor (
+        day         INTEGER NOT NULL,
+        postUuid    TEXT NOT NULL,
+        visitorHash TEXT NOT NULL,
+        PRIMARY KEY (day, postUuid, visitorHash)
+    ) WITHOUT ROWID;",
+    "CREATE TABLE post_view_daily (
+        postUuid    TEXT NOT NULL,
+        day         INTEGER NOT NULL,
+        authorUuid  TEXT NOT NULL,
+        views       INTEGER NOT NULL,
+        PRIMARY KEY (postUuid, day)
+    ) WITHOUT ROWID;
+    CREATE INDEX post_view_daily_author ON post_view_daily(authorUuid, day);",
+    "CREATE TABLE referrer_daily (
+        postUuid    TEXT NOT NULL,
+        day         INTEGER NOT NULL,
+        referrer    TEXT NOT NULL,
+        authorUuid  TEXT NOT NULL,
+        views       INTEGER NOT NULL,
+        PRIMARY KEY (postUuid, day, referrer)
+    ) WITHOUT ROWID;
+    CREATE INDEX referrer_daily_author ON referrer_daily(authorUuid, day);",
+];
+
+fn db_error(err: rusqlite::Error) -> AnalyticsDbError {
+    log::error!("{:?}", err);
+    AnalyticsDbError::GenericError
+}
+
+fn new_salt() -> [u8; 16] {
+    *Uuid::new_v4().as_bytes()
+}
+
+/// Days since the unix epoch.
+pub fn today() -> i64 {
+    Utc::now().timestamp().div_euclid(SECONDS_PER_DAY)
+}
+
+impl AnalyticsDbService {
+    pub fn connect(db_collection_path: &str) -> Result<Self, AnalyticsDbError> {
+        let mut conn =
+            Connection::open(format!("{}/analytics.db", db_collection_path)).map_err(db_error)?;
+        migrate(&mut conn, MIGRATIONS).map_err(db_error)?;
+
+        return Ok(Self {
+            conn,
+            salt: (today(), new_salt()),
+        });
+    }
+
+    /// Counts a view unless the visitor already read the post today. A restart forgets the
+    /// salt, visitors coming back later that day are counted again.
+    pub fn record_view(&mut self, view: &PostView) -> Result<(), AnalyticsDbError> {
+        let day = today();
+        if self.salt.0 != day {
+            self.salt = (day, new_salt());
+        }
+        let visitor_hash = Sha256::new()
+            .chain_update(self.salt.1)
+            .chain_update(view.visitor.as_bytes())
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+
+        let tx = self.conn.transaction().map_err(db_error)?;
+        tx.execute("DELETE FROM post_view_visitor WHERE day<?1", params![day])
+            .map_err(db_error)?;
+        let inserted = tx
+            .execute(
+                "INSERT OR IGNORE INTO post_view_visitor (day, postUuid, visitorHash)
+                    VALUES (?1, ?2, ?3)",
+                params![day, view.post_uuid, visitor_hash],
+            )
+            .map_err(db_error)?;
+        if inserted == 0 {
+            tx.commit().map_err(db_error)?;
+            return Ok(());
+        }
+
+        tx.execute(
+            "INSERT INTO post_view_daily (postUuid, day, authorUuid, views) VALUES (?1, ?2, ?3, 1)
+                ON CONFLICT(postUuid, day) DO UPDATE SET views=views+1",
+            params![view.post_uuid, day, view.author_uuid],
+        )
+        .map_err(db_error)?;
+        if let Some(referrer) = view.referrer {
+            tx.execute(
+                "INSERT INTO referrer_daily (postUuid, day, referrer, authorUuid, views)
+                    VALUES (?1, ?2, ?3, ?4, 1)
+                    ON CONFLICT(postUuid, day, referrer) DO UPDATE SET views=views+1",
+                params![view.post_uuid, day, referrer, view.author_uuid],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        return Ok(());
+    }
+
+    /// Views per day from `from_day` on, days without views are left out. Limited to one
+    /// post when `post_uuid` is given.
+    pub fn daily_views(
+        &self,
+        author_uuid: &str,
+        post_uuid: Option<&str>,
+        from_day: i64,
+    ) -> Result<Vec<DayCount>, AnalyticsDbError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT day, SUM(views) FROM post_view_daily
+                    WHERE authorUuid=?1 AND day>=?2 AND (?3 IS NULL OR postUuid=?3)
+                    GROUP BY day ORDER BY day",
+            )
+            .map_err(db_error)?;
+        let days = statement
+            .query_map(params![author_uuid, from_day, post_uuid], |row| {
+                Ok(DayCount {
+                    day: row.get(0)?,
+                    views: row.get(1)?,
+                })
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(days);
+    }
+
+    /// The most read posts of an author from `from_day` on, `name` is the post uuid.
+    pub fn top_posts(
+        &self,
+        author_uuid: &str,
+        from_day: i64,
+        limit: u32,
+    ) -> Result<Vec<NameViews>, AnalyticsDbError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT postUuid, SUM(views) AS total FROM post_view_daily
+                    WHERE authorUuid=?1 AND day>=?2
+                    GROUP BY postUuid ORDER BY total DESC, postUuid LIMIT ?3",
+            )
+            .map_err(db_error)?;
+        let posts = statement
+            .query_map(params![author_uuid, from_day, limit], name_views_from_row)
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(posts);
+    }
+
+    /// The sites sending the most readers from `from_day` on, limited to one post when
+    /// `post_uuid` is given.
+    pub fn top_referrers(
+        &self,
+        author_uuid: &str,
+        post_uuid: Option<&str>,
+        from_day: i64,
+        limit: u32,
+    ) -> Result<Vec<NameViews>, AnalyticsDbError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT referrer, SUM(views) AS total FROM referrer_daily
+                    WHERE authorUuid=?1 AND day>=?2 AND (?3 IS NULL OR postUuid=?3)
+                    GROUP BY referrer ORDER BY total DESC, referrer LIMIT ?4",
+            )
+            .map_err(db_error)?;
+        let referrers = statement
+            .query_map(
+                params![author_uuid, from_day, post_uuid, limit],
+                name_views_from_row,
+            )
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(referrers);
+    }
+
+    /// Forgets the views of a deleted post.
+    pub fn remove_post(&mut self, post_uuid: &str) -> Result<(), AnalyticsDbError> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        for table in ["post_view_visitor", "post_view_daily", "referrer_daily"] {
+            tx.execute(
+                &format!("DELETE FROM {} WHERE postUuid=?1", table),
+                params![post_uuid],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        return Ok(());
+    }
+}
+
+fn name_views_from_row(row: &rusqlite::Row) -> rusqlite::Result<NameViews> {
+    Ok(NameViews {
+        name: row.get(0)?,
+        views: row.get(1)?,
+    })
+}
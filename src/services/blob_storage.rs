@@ -0,0 +1,70 @@
+use std::{
+    fmt::Debug,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Where stored content ends up. Keys are content hashes, so a key is written once and
+/// never changes.
+pub trait BlobStorage: Debug + Send + Sync {
+    /// Stores a finished temporary file under the key, the file is left in place.
+    fn put_file(&self, key: &str, path: &Path) -> io::Result<()>;
+    fn get(&self, key: &str) -> io::Result<Vec<u8>>;
+    fn delete(&self, key: &str) -> io::Result<()>;
+    /// A time limited url clients can download the content from directly, `None` when the
+    /// storage can't hand out such urls and the api has to serve the content itself.
+    fn presigned_url(
+        &self,
+        key: &str,
+        mime_type: &str,
+        content_disposition: &str,
+    ) -> Option<String>;
+}
+
+/// Files in a local folder, `ab/cd/abcd…`.
+#[derive(Debug)]
+pub struct LocalBlobStorage {
+    root: PathBuf,
+}
+
+impl LocalBlobStorage {
+    pub fn new(root: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&root)?;
+        return Ok(Self { root });
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(&key[0..2]).join(&key[2..4]).join(key)
+    }
+}
+
+impl BlobStorage for LocalBlobStorage {
+    fn put_file(&self, key: &str, path: &Path) -> io::Result<()> {
+        let blob_path = self.path(key);
+        if let Some(parent) = blob_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // a link appears complete or not at all, the temporary folder is on the same disk
+        match fs::hard_link(path, &blob_path) {
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.path(key))
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        fs::remove_file(self.path(key))
+    }
+
+    fn presigned_url(
+        &self,
+        _key: &str,
+        _mime_type: &str,
+        _content_disposition: &str,
+    ) -> Option<String> {
+        None
+    }
+}
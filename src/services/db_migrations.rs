@@ -0,0 +1,17 @@
+use rusqlite::Connection;
+
+/// Applies the migrations the database hasn't seen yet, in order. Progress is tracked with
+/// `PRAGMA user_version`, so never edit an entry once released, append a new one instead.
+/// Returns the version the database had before, data fix-ups written in Rust can use it.
+pub fn migrate(conn: &mut Connection, migrations: &[&str]) -> rusqlite::Result<usize> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (index, migration) in migrations.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+
+    return Ok(version);
+}
@@ -5,6 +5,52 @@ pub struct EnvSettings {
     pub db_collection_path: String,
     pub user_jwt_secret: String,
     pub user_jwt_expiration_minutes: i64,
+    /// absolute url the api is reachable at, used for links in feeds
+    pub public_base_url: String,
+    /// users allowed to work the moderation queue
+    pub moderator_uuids: Vec<String>,
+    /// where uploaded media is kept
+    pub media_storage: MediaStorageSettings,
+    /// lets link previews be fetched from loopback and private network addresses, only
+    /// meant for local development
+    pub link_preview_allow_private_hosts: bool,
+    /// how long posts may be, in grapheme clusters
+    pub post_limits: PostLimits,
+    /// largest json request body accepted, in bytes
+    pub max_json_payload_bytes: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PostLimits {
+    pub max_title_length: usize,
+    pub max_body_length: usize,
+    /// quote written above a repost
+    pub max_quote_length: usize,
+    /// what every url in a title or body counts as, however long it is
+    pub url_length: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum MediaStorageSettings {
+    /// the `media` folder inside the db collection folder
+    Local,
+    S3(S3Settings),
+}
+
+/// An S3 compatible object storage, AWS or a self hosted one like MinIO.
+#[derive(Debug, Clone)]
+pub struct S3Settings {
+    /// e.g. `https://s3.eu-central-1.amazonaws.com` or `http://127.0.0.1:9000`
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// `endpoint/bucket/key` urls instead of `bucket.endpoint/key`, most self hosted
+    /// storages need it
+    pub path_style: bool,
+    /// how long a presigned download url stays valid
+    pub presign_expiration_seconds: u64,
 }
 
 impl EnvSettings {
@@ -18,6 +64,82 @@ impl EnvSettings {
                 .expect("JWT_EXPIRATION_MINUTES must be a valid i64 number"),
             user_jwt_secret: env::var("USER_JWT_SECRET")
                 .expect("USER_JWT_SECRET in .env file is missing"),
+            public_base_url: env::var("PUBLIC_BASE_URL")
+                .unwrap_or("http://127.0.0.1:8080".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            moderator_uuids: env::var("MODERATOR_UUIDS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|uuid| !uuid.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            media_storage: match env::var("MEDIA_STORAGE")
+                .unwrap_or("local".to_string())
+                .as_str()
+            {
+                "local" => MediaStorageSettings::Local,
+                "s3" => MediaStorageSettings::S3(S3Settings::new()),
+                _ => panic!("MEDIA_STORAGE must be local or s3"),
+            },
+            link_preview_allow_private_hosts: env::var("LINK_PREVIEW_ALLOW_PRIVATE_HOSTS")
+                .map(|value| value == "true")
+                .unwrap_or(false),
+            post_limits: PostLimits::new(),
+            max_json_payload_bytes: env::var("MAX_JSON_PAYLOAD_BYTES")
+                .unwrap_or("65536".to_string())
+                .parse::<usize>()
+                .expect("MAX_JSON_PAYLOAD_BYTES must be a valid usize number"),
+        }
+    }
+
+    pub fn is_moderator(&self, user_uuid: &str) -> bool {
+        self.moderator_uuids.iter().any(|uuid| uuid == user_uuid)
+    }
+}
+
+impl PostLimits {
+    fn new() -> Self {
+        Self {
+            max_title_length: env::var("POST_MAX_TITLE_LENGTH")
+                .unwrap_or("200".to_string())
+                .parse::<usize>()
+                .expect("POST_MAX_TITLE_LENGTH must be a valid usize number"),
+            max_body_length: env::var("POST_MAX_BODY_LENGTH")
+                .unwrap_or("5000".to_string())
+                .parse::<usize>()
+                .expect("POST_MAX_BODY_LENGTH must be a valid usize number"),
+            max_quote_length: env::var("POST_MAX_QUOTE_LENGTH")
+                .unwrap_or("500".to_string())
+                .parse::<usize>()
+                .expect("POST_MAX_QUOTE_LENGTH must be a valid usize number"),
+            url_length: env::var("POST_URL_LENGTH")
+                .unwrap_or("23".to_string())
+                .parse::<usize>()
+                .expect("POST_URL_LENGTH must be a valid usize number"),
+        }
+    }
+}
+
+impl S3Settings {
+    fn new() -> Self {
+        Self {
+            endpoint: env::var("S3_ENDPOINT")
+                .expect("S3_ENDPOINT in .env file is missing")
+                .trim_end_matches('/')
+                .to_string(),
+            region: env::var("S3_REGION").unwrap_or("us-east-1".to_string()),
+            bucket: env::var("S3_BUCKET").expect("S3_BUCKET in .env file is missing"),
+            access_key: env::var("S3_ACCESS_KEY").expect("S3_ACCESS_KEY in .env file is missing"),
+            secret_key: env::var("S3_SECRET_KEY").expect("S3_SECRET_KEY in .env file is missing"),
+            path_style: env::var("S3_PATH_STYLE")
+                .map(|value| value == "true")
+                .unwrap_or(false),
+            presign_expiration_seconds: env::var("S3_PRESIGN_EXPIRATION_SECONDS")
+                .unwrap_or("3600".to_string())
+                .parse::<u64>()
+                .expect("S3_PRESIGN_EXPIRATION_SECONDS must be a valid u64 number"),
         }
     }
 }
@@ -0,0 +1,166 @@
+use chrono::Utc;
+use rusqlite::{params, params_from_iter, Connection, Row};
+
+use super::db_migrations::migrate;
+
+/// Previews are fetched again once they are older than this, so changed pages catch up.
+/// Failed fetches are retried after the same time.
+const PREVIEW_MAX_AGE_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug)]
+pub enum LinkPreviewDbError {
+    GenericError,
+}
+
+/// Open Graph and Twitter card metadata of the urls written in posts, one entry per url
+/// shared by all posts linking it. Backed by `link_previews.db` inside the db collection
+/// folder. An entry is `pending` until the worker fetched it, then `ready` or `failed`.
+#[derive(Debug)]
+pub struct LinkPreviewDbService {
+    conn: Connection,
+}
+
+/// What a page tells about itself, the `title` is always there.
+#[derive(Debug, Clone)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// absolute `http` or `https` url
+    pub image_url: Option<String>,
+    pub site_name: Option<String>,
+}
+
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE link_preview (
+        url         TEXT PRIMARY KEY,
+        state       TEXT NOT NULL,
+        title       TEXT,
+        description TEXT,
+        imageUrl    TEXT,
+        siteName    TEXT,
+        requestedAt INTEGER NOT NULL,
+        fetchedAt   INTEGER
+    ) WITHOUT ROWID;",
+    "CREATE INDEX link_preview_pending ON link_preview(state, requestedAt);",
+];
+
+fn db_error(err: rusqlite::Error) -> LinkPreviewDbError {
+    log::error!("{:?}", err);
+    LinkPreviewDbError::GenericError
+}
+
+fn link_preview_from_row(row: &Row) -> rusqlite::Result<LinkPreview> {
+    Ok(LinkPreview {
+        url: row.get(0)?,
+        title: row.get(1)?,
+        description: row.get(2)?,
+        image_url: row.get(3)?,
+        site_name: row.get(4)?,
+    })
+}
+
+impl LinkPreviewDbService {
+    pub fn connect(db_collection_path: &str) -> Result<Self, LinkPreviewDbError> {
+        let mut conn = Connection::open(format!("{}/link_previews.db", db_collection_path))
+            .map_err(db_error)?;
+        migrate(&mut conn, MIGRATIONS).map_err(db_error)?;
+
+        return Ok(Self { conn });
+    }
+
+    /// Queues the urls which have no preview yet, or only an outdated one, for the worker.
+    pub fn request_previews(&mut self, urls: &[String]) -> Result<(), LinkPreviewDbError> {
+        let now = Utc::now().timestamp();
+        let tx = self.conn.transaction().map_err(db_error)?;
+        for url in urls {
+            tx.execute(
+                "INSERT INTO link_preview (url, state, requestedAt) VALUES (?1, 'pending', ?2)
+                    ON CONFLICT(url) DO UPDATE SET state='pending', requestedAt=?2
+                    WHERE state!='pending' AND fetchedAt<?3",
+                params![url, now, now - PREVIEW_MAX_AGE_SECONDS],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        return Ok(());
+    }
+
+    /// Urls waiting for the worker, the longest waiting first.
+    pub fn get_pending_urls(&self, limit: u32) -> Result<Vec<String>, LinkPreviewDbError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT url FROM link_preview WHERE state='pending'
+                    ORDER BY requestedAt LIMIT ?1",
+            )
+            .map_err(db_error)?;
+        let urls = statement
+            .query_map(params![limit], |row| row.get(0))
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(urls);
+    }
+
+    /// Stores the outcome of a fetch, `None` when the page couldn't be fetched or had no
+    /// title.
+    pub fn complete_preview(
+        &self,
+        url: &str,
+        preview: Option<&LinkPreview>,
+    ) -> Result<(), LinkPreviewDbError> {
+        let now = Utc::now().timestamp();
+        match preview {
+            Some(preview) => self.conn.execute(
+                "UPDATE link_preview SET state='ready', title=?2, description=?3, imageUrl=?4,
+                    siteName=?5, fetchedAt=?6
+                    WHERE url=?1",
+                params![
+                    url,
+                    preview.title,
+                    preview.description,
+                    preview.image_url,
+                    preview.site_name,
+                    now
+                ],
+            ),
+            // an earlier preview is kept until the page can be fetched again
+            None => self.conn.execute(
+                "UPDATE link_preview SET state=IIF(title IS NULL, 'failed', 'ready'), fetchedAt=?2
+                    WHERE url=?1",
+                params![url, now],
+            ),
+        }
+        .map_err(db_error)?;
+
+        return Ok(());
+    }
+
+    /// The previews known for the urls, in no particular order. Urls still waiting for
+    /// their first fetch or without a usable page are left out.
+    pub fn get_previews(&self, urls: &[String]) -> Result<Vec<LinkPreview>, LinkPreviewDbError> {
+        if urls.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = vec!["?"; urls.len()].join(", ");
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT url, title, description, imageUrl, siteName FROM link_preview
+                    WHERE title IS NOT NULL AND url IN ({})",
+                placeholders
+            ))
+            .map_err(db_error)?;
+        let previews = statement
+            .query_map(params_from_iter(urls.iter()), link_preview_from_row)
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(previews);
+    }
+}
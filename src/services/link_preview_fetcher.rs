@@ -0,0 +1,664 @@
+use std::{
+    io::{self, Read},
+    net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use super::link_preview_db_service::{LinkPreview, LinkPreviewDbService};
+
+/// How often the worker looks for new urls when it has nothing to do.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const BATCH_SIZE: u32 = 10;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Limit for the whole fetch including redirects, slow pages don't hold up the queue.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REDIRECTS: u32 = 3;
+/// Only the start of a page is read, the metadata sits in its head.
+const MAX_PAGE_SIZE: u64 = 512 * 1024;
+const MAX_TITLE_LENGTH: usize = 300;
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+const MAX_SITE_NAME_LENGTH: usize = 100;
+const MAX_IMAGE_URL_LENGTH: usize = 2000;
+const USER_AGENT: &str = "micro-blog-api link preview";
+
+/// Starts the background worker which fetches the pages of queued urls and keeps their Open
+/// Graph or Twitter card metadata. Hosts resolving to loopback, private or otherwise
+/// internal addresses are refused on every redirect, unless `allow_private_hosts` is set.
+pub fn spawn_link_preview_worker(
+    link_preview_db_state: Arc<Mutex<LinkPreviewDbService>>,
+    allow_private_hosts: bool,
+) {
+    let agent = build_agent(
+        move |netloc: &str| resolve(netloc, allow_private_hosts),
+        FETCH_TIMEOUT,
+    );
+
+    thread::spawn(move || loop {
+        let pending = link_preview_db_state
+            .lock()
+            .unwrap()
+            .get_pending_urls(BATCH_SIZE);
+        match pending {
+            Ok(urls) if !urls.is_empty() => {
+                for url in urls {
+                    let preview = fetch_preview(&agent, &url);
+                    if let Err(err) = link_preview_db_state
+                        .lock()
+                        .unwrap()
+                        .complete_preview(&url, preview.as_ref())
+                    {
+                        log::error!("link preview worker, storing {} failed {:?}", url, err);
+                    }
+                }
+            }
+            Ok(_) => thread::sleep(POLL_INTERVAL),
+            Err(err) => {
+                log::error!("link preview worker, reading pending urls failed {:?}", err);
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    });
+}
+
+/// Agent for fetching pages, the host of every request and redirect is looked up with
+/// `resolver`.
+fn build_agent(resolver: impl ureq::Resolver + 'static, fetch_timeout: Duration) -> ureq::Agent {
+    return ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout(fetch_timeout)
+        .redirects(MAX_REDIRECTS)
+        .user_agent(USER_AGENT)
+        .resolver(resolver)
+        .build();
+}
+
+/// Resolves a `host:port`, keeping only the public addresses.
+fn resolve(netloc: &str, allow_private_hosts: bool) -> io::Result<Vec<SocketAddr>> {
+    let addresses: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+    if allow_private_hosts {
+        return Ok(addresses);
+    }
+
+    let public: Vec<SocketAddr> = addresses
+        .into_iter()
+        .filter(|address| is_public_ip(address.ip()))
+        .collect();
+    if public.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} has no public address", netloc),
+        ));
+    }
+    return Ok(public);
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    return !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // 0.0.0.0/8 "this network"
+        || first == 0
+        // 100.64.0.0/10 shared address space of carrier grade NAT
+        || (first == 100 && (second & 0xc0) == 64)
+        // 192.0.0.0/24 protocol assignments
+        || (first == 192 && second == 0 && ip.octets()[2] == 0)
+        // 198.18.0.0/15 benchmarking
+        || (first == 198 && (second & 0xfe) == 18)
+        // 240.0.0.0/4 reserved
+        || first >= 240);
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    let ip = match ip {
+        IpAddr::V4(ip) => return is_public_ipv4(ip),
+        IpAddr::V6(ip) => ip,
+    };
+    if let Some(mapped) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(mapped);
+    }
+    let segments = ip.segments();
+    // 64:ff9b::/96 translates to the IPv4 address in its last 32 bits
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [.., high, low] = segments;
+        return is_public_ipv4(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)));
+    }
+    return !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // fc00::/7 unique local
+        || (segments[0] & 0xfe00) == 0xfc00
+        // fe80::/10 link local
+        || (segments[0] & 0xffc0) == 0xfe80
+        // 2001:db8::/32 documentation
+        || (segments[0] == 0x2001 && segments[1] == 0xdb8));
+}
+
+/// Fetches an html page and reads its preview, `None` when it can't be fetched or tells
+/// nothing usable. Failures are expected for a share of the urls people write, they are
+/// only logged.
+fn fetch_preview(agent: &ureq::Agent, url: &str) -> Option<LinkPreview> {
+    let response = match agent.get(url).call() {
+        Ok(response) => response,
+        Err(err) => {
+            log::info!("link preview worker, fetching {} failed {}", url, err);
+            return None;
+        }
+    };
+    if response.content_type() != "text/html" && response.content_type() != "application/xhtml+xml"
+    {
+        log::info!(
+            "link preview worker, {} is {} and not html",
+            url,
+            response.content_type()
+        );
+        return None;
+    }
+
+    let page_url = response.get_url().to_owned();
+    let mut content = Vec::new();
+    if let Err(err) = response
+        .into_reader()
+        .take(MAX_PAGE_SIZE)
+        .read_to_end(&mut content)
+    {
+        log::info!("link preview worker, reading {} failed {}", url, err);
+        return None;
+    }
+
+    return read_preview(url, &page_url, &String::from_utf8_lossy(&content));
+}
+
+/// Reads the Open Graph tags of a page, falling back to the Twitter card tags and the plain
+/// html title and description.
+fn read_preview(url: &str, page_url: &str, html: &str) -> Option<LinkPreview> {
+    let meta = read_meta_tags(html);
+    let find = |names: &[&str]| {
+        names.iter().find_map(|name| {
+            meta.iter()
+                .find(|(key, value)| key == name && !value.is_empty())
+                .map(|(_, value)| value.clone())
+        })
+    };
+
+    let title = find(&["og:title", "twitter:title"])
+        .or_else(|| read_title(html))
+        .map(|title| clean_text(&title, MAX_TITLE_LENGTH))
+        .filter(|title| !title.is_empty())?;
+    return Some(LinkPreview {
+        url: url.to_owned(),
+        title,
+        description: find(&["og:description", "twitter:description", "description"])
+            .map(|description| clean_text(&description, MAX_DESCRIPTION_LENGTH)),
+        image_url: find(&["og:image:secure_url", "og:image", "twitter:image"])
+            .and_then(|image| absolute_url(page_url, image.trim()))
+            .filter(|image| image.len() <= MAX_IMAGE_URL_LENGTH),
+        site_name: find(&["og:site_name"])
+            .map(|site_name| clean_text(&site_name, MAX_SITE_NAME_LENGTH)),
+    });
+}
+
+/// The `property` or `name` of every `<meta>` tag, lowercased, with its decoded `content`.
+fn read_meta_tags(html: &str) -> Vec<(String, String)> {
+    // lowercasing only ascii keeps the byte offsets of both strings the same
+    let lowercase = html.to_ascii_lowercase();
+    let mut tags = Vec::new();
+    let mut offset = 0;
+
+    while let Some(start) = lowercase[offset..].find("<meta") {
+        let start = offset + start + "<meta".len();
+        let Some(end) = lowercase[start..].find('>') else {
+            break;
+        };
+        let end = start + end;
+        offset = end;
+
+        let attributes = read_attributes(&html[start..end]);
+        let key = attributes
+            .iter()
+            .find(|(name, _)| name == "property" || name == "name")
+            .map(|(_, value)| value.to_ascii_lowercase());
+        let content = attributes
+            .iter()
+            .find(|(name, _)| name == "content")
+            .map(|(_, value)| decode_entities(value));
+        if let (Some(key), Some(content)) = (key, content) {
+            tags.push((key, content));
+        }
+    }
+
+    return tags;
+}
+
+/// Attributes of a tag as `name="value"`, `name='value'`, `name=value` or a bare `name`.
+fn read_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            break;
+        }
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                let (value, remaining) = match after.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let inner = &after[1..];
+                        let end = inner.find(quote).unwrap_or(inner.len());
+                        (&inner[..end], &inner[(end + 1).min(inner.len())..])
+                    }
+                    _ => {
+                        let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                        (&after[..end], &after[end..])
+                    }
+                };
+                rest = remaining;
+                value.to_owned()
+            }
+            None => String::new(),
+        };
+        if name.is_empty() {
+            // a stray `=`, skip a character so the loop moves on
+            rest = rest.get(1..).unwrap_or_default();
+            continue;
+        }
+        attributes.push((name, value));
+    }
+
+    return attributes;
+}
+
+fn read_title(html: &str) -> Option<String> {
+    let lowercase = html.to_ascii_lowercase();
+    let start = lowercase.find("<title")?;
+    let start = start + lowercase[start..].find('>')? + 1;
+    let end = start + lowercase[start..].find("</title")?;
+    return Some(decode_entities(&html[start..end]));
+}
+
+/// Decodes the character references metadata commonly uses, unknown ones are kept as they
+/// are.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let character = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => match reference.strip_prefix('#') {
+                Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16)
+                    .ok()
+                    .and_then(char::from_u32),
+                Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                None => None,
+            },
+        });
+        match (reference, character) {
+            (Some(reference), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    return decoded;
+}
+
+/// Collapses whitespace and cuts the text to `max_length` characters.
+fn clean_text(text: &str, max_length: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= max_length {
+        return text;
+    }
+    let mut cut: String = text.chars().take(max_length - 1).collect();
+    cut.push('…');
+    return cut;
+}
+
+/// Resolves a link found on the page at `base`, only `http` and `https` urls are kept.
+fn absolute_url(base: &str, link: &str) -> Option<String> {
+    let lowercase = link.to_ascii_lowercase();
+    if lowercase.starts_with("https://") || lowercase.starts_with("http://") {
+        return Some(link.to_owned());
+    }
+    if link.contains(':') && !link.starts_with('/') {
+        // another scheme, e.g. `data:` or `javascript:`
+        return None;
+    }
+
+    let (scheme, rest) = base.split_once("://")?;
+    if let Some(network_path) = link.strip_prefix("//") {
+        return Some(format!("{}://{}", scheme, network_path));
+    }
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let origin = format!("{}://{}", scheme, &rest[..authority_end]);
+    if link.starts_with('/') {
+        return Some(format!("{}{}", origin, link));
+    }
+
+    let path = rest[authority_end..]
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default();
+    let directory = path.rsplit_once('/').map_or("", |(directory, _)| directory);
+    return Some(format!("{}{}/{}", origin, directory, link));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, ErrorKind, Write},
+        net::{Ipv6Addr, TcpListener},
+        time::Instant,
+    };
+
+    use super::*;
+
+    /// Serves `response` to every connection, after reading the request head.
+    fn stub_server(response: String) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                    line.clear();
+                }
+                // the client may hang up before it read everything
+                let _ = reader.get_mut().write_all(response.as_bytes());
+            }
+        });
+        return address;
+    }
+
+    fn html_response(body: &str) -> String {
+        return format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nConnection: close\r\n\r\n{}",
+            body
+        );
+    }
+
+    /// Agent which treats `public.test` as a public host living on 127.0.0.1, every other
+    /// host is checked like in the worker.
+    fn test_agent(fetch_timeout: Duration) -> ureq::Agent {
+        return build_agent(
+            |netloc: &str| match netloc.strip_prefix("public.test:") {
+                Some(port) => Ok(vec![SocketAddr::from((
+                    Ipv4Addr::LOCALHOST,
+                    port.parse::<u16>().unwrap(),
+                ))]),
+                None => resolve(netloc, false),
+            },
+            fetch_timeout,
+        );
+    }
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        let internal = [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "172.31.255.255",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "64:ff9b::a00:1",
+        ];
+        for ip in internal {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{} is internal", ip);
+        }
+
+        let public = [
+            "93.184.216.34",
+            "172.32.0.1",
+            "2606:4700::1111",
+            "::ffff:1.1.1.1",
+        ];
+        for ip in public {
+            assert!(is_public_ip(ip.parse().unwrap()), "{} is public", ip);
+        }
+    }
+
+    #[test]
+    fn resolve_refuses_internal_hosts() {
+        for netloc in ["127.0.0.1:80", "localhost:80", "[::1]:80", "10.0.0.1:80"] {
+            let err = resolve(netloc, false).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::PermissionDenied, "{}", netloc);
+        }
+
+        let addresses = resolve("127.0.0.1:80", true).unwrap();
+        assert_eq!(addresses, [SocketAddr::from((Ipv4Addr::LOCALHOST, 80))]);
+        assert_eq!(
+            resolve("[fc00::1]:80", true).unwrap()[0].ip(),
+            IpAddr::V6(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 1))
+        );
+    }
+
+    #[test]
+    fn fetch_reads_a_page_of_a_public_host() {
+        let address = stub_server(html_response(
+            "<html><head><meta property=\"og:title\" content=\"Stub page\"></head></html>",
+        ));
+        let url = format!("http://public.test:{}/", address.port());
+
+        let preview = fetch_preview(&test_agent(FETCH_TIMEOUT), &url).unwrap();
+        assert_eq!(preview.url, url);
+        assert_eq!(preview.title, "Stub page");
+    }
+
+    #[test]
+    fn redirect_to_an_internal_address_is_refused() {
+        let internal = TcpListener::bind("127.0.0.1:0").unwrap();
+        internal.set_nonblocking(true).unwrap();
+        let redirect = stub_server(format!(
+            "HTTP/1.1 302 Found\r\nLocation: http://{}/\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            internal.local_addr().unwrap()
+        ));
+
+        let url = format!("http://public.test:{}/", redirect.port());
+        assert!(fetch_preview(&test_agent(FETCH_TIMEOUT), &url).is_none());
+        // the internal host was never connected to
+        assert_eq!(internal.accept().unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn only_the_start_of_a_page_is_read() {
+        let padding = " ".repeat(MAX_PAGE_SIZE as usize);
+        let late_title = stub_server(html_response(&format!(
+            "<html><head>{}<title>Too late</title></head></html>",
+            padding
+        )));
+        let early_title = stub_server(html_response(&format!(
+            "<html><head><title>In time</title>{}</head></html>",
+            padding
+        )));
+        let agent = test_agent(FETCH_TIMEOUT);
+
+        let url = format!("http://public.test:{}/", late_title.port());
+        assert!(fetch_preview(&agent, &url).is_none());
+        let url = format!("http://public.test:{}/", early_title.port());
+        assert_eq!(fetch_preview(&agent, &url).unwrap().title, "In time");
+    }
+
+    #[test]
+    fn fetch_gives_up_on_a_host_which_never_answers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            // keeps the connections open without answering
+            let _streams: Vec<_> = listener.incoming().collect();
+        });
+
+        let started = Instant::now();
+        let url = format!("http://public.test:{}/", port);
+        assert!(fetch_preview(&test_agent(Duration::from_millis(300)), &url).is_none());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn non_html_pages_have_no_preview() {
+        let address = stub_server(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\n\r\n{}"
+                .to_owned(),
+        );
+        let url = format!("http://public.test:{}/", address.port());
+        assert!(fetch_preview(&test_agent(FETCH_TIMEOUT), &url).is_none());
+    }
+
+    #[test]
+    fn open_graph_tags_come_first() {
+        let html = r#"<head>
+            <title>Html title</title>
+            <meta name="twitter:title" content="Twitter title">
+            <meta property="og:title" content="Open Graph title">
+            <meta name="description" content="Html description">
+            <meta name="twitter:description" content="Twitter description">
+            <meta property="og:image" content="/og.png">
+            <meta name="twitter:image" content="https://example.com/twitter.png">
+            <meta property="og:site_name" content="Example">
+        </head>"#;
+
+        let preview =
+            read_preview("https://example.com/a", "https://example.com/a/b", html).unwrap();
+        assert_eq!(preview.url, "https://example.com/a");
+        assert_eq!(preview.title, "Open Graph title");
+        assert_eq!(preview.description.as_deref(), Some("Twitter description"));
+        assert_eq!(
+            preview.image_url.as_deref(),
+            Some("https://example.com/og.png")
+        );
+        assert_eq!(preview.site_name.as_deref(), Some("Example"));
+    }
+
+    #[test]
+    fn twitter_tags_come_before_the_html_title() {
+        let html = r#"<title>Html title</title>
+            <meta name="twitter:title" content="Twitter title">
+            <meta property="og:title" content="">
+            <meta name="description" content="Html description">"#;
+
+        let preview = read_preview("https://example.com/", "https://example.com/", html).unwrap();
+        assert_eq!(preview.title, "Twitter title");
+        assert_eq!(preview.description.as_deref(), Some("Html description"));
+        assert_eq!(preview.image_url, None);
+        assert_eq!(preview.site_name, None);
+    }
+
+    #[test]
+    fn html_title_is_the_last_fallback() {
+        let html = "<html><head><TITLE>\n  Html   &amp; title\n</TITLE></head></html>";
+
+        let preview = read_preview("https://example.com/", "https://example.com/", html).unwrap();
+        assert_eq!(preview.title, "Html & title");
+        assert_eq!(preview.description, None);
+    }
+
+    #[test]
+    fn pages_without_a_title_have_no_preview() {
+        let html = r#"<meta name="description" content="Only a description"><title> </title>"#;
+        assert!(read_preview("https://example.com/", "https://example.com/", html).is_none());
+    }
+
+    #[test]
+    fn decode_entities_decodes_known_references() {
+        assert_eq!(
+            decode_entities("a &amp; b &lt;c&gt; &quot;d&quot; &apos;e&apos;&nbsp;f"),
+            "a & b <c> \"d\" 'e' f"
+        );
+        assert_eq!(decode_entities("&#233;t&#xE9; &#X1F600;"), "été 😀");
+    }
+
+    #[test]
+    fn decode_entities_keeps_unknown_references() {
+        assert_eq!(
+            decode_entities("&copy; &bogus; & &;"),
+            "&copy; &bogus; & &;"
+        );
+        assert_eq!(decode_entities("&#xzz; &#1114112;"), "&#xzz; &#1114112;");
+        assert_eq!(
+            decode_entities("AT&T &averyverylongname;"),
+            "AT&T &averyverylongname;"
+        );
+        assert_eq!(decode_entities("trailing &"), "trailing &");
+    }
+
+    #[test]
+    fn absolute_url_resolves_links_against_the_page() {
+        let base = "https://example.com/blog/post?page=2#top";
+        assert_eq!(
+            absolute_url(base, "http://other.org/a.png").as_deref(),
+            Some("http://other.org/a.png")
+        );
+        assert_eq!(
+            absolute_url(base, "//cdn.example.com/a.png").as_deref(),
+            Some("https://cdn.example.com/a.png")
+        );
+        assert_eq!(
+            absolute_url(base, "/a.png").as_deref(),
+            Some("https://example.com/a.png")
+        );
+        assert_eq!(
+            absolute_url(base, "a.png").as_deref(),
+            Some("https://example.com/blog/a.png")
+        );
+        assert_eq!(
+            absolute_url("https://example.com", "a.png").as_deref(),
+            Some("https://example.com/a.png")
+        );
+        assert_eq!(
+            absolute_url("https://example.com?q=1", "/a.png").as_deref(),
+            Some("https://example.com/a.png")
+        );
+    }
+
+    #[test]
+    fn absolute_url_drops_other_schemes() {
+        let base = "https://example.com/";
+        assert_eq!(absolute_url(base, "data:image/png;base64,AAAA"), None);
+        assert_eq!(absolute_url(base, "javascript:alert(1)"), None);
+        assert_eq!(absolute_url(base, "ftp://example.com/a.png"), None);
+    }
+}
@@ -0,0 +1,400 @@
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use uuid::Uuid;
+
+use super::db_migrations::migrate;
+
+#[derive(Debug)]
+pub enum MediaDbError {
+    GenericError,
+    AttachmentNotFound,
+    QuotaExceeded,
+}
+
+/// Uploaded media, backed by `media.db` inside the db collection folder. The content lives
+/// in the `MediaStorage`, several attachments share a file when their content is the same.
+#[derive(Debug)]
+pub struct MediaDbService {
+    conn: Connection,
+}
+
+/// Where an upload is in the image pipeline, see `media_processor`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessingState {
+    /// not an image, stored as uploaded
+    None,
+    /// an image waiting for the worker, its content isn't served yet
+    Pending,
+    Ready,
+    /// an image which couldn't be decoded, never served
+    Failed,
+}
+
+impl ProcessingState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProcessingState::None => "none",
+            ProcessingState::Pending => "pending",
+            ProcessingState::Ready => "ready",
+            ProcessingState::Failed => "failed",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "pending" => ProcessingState::Pending,
+            "ready" => ProcessingState::Ready,
+            "failed" => ProcessingState::Failed,
+            _ => ProcessingState::None,
+        }
+    }
+}
+
+/// A resized or converted copy of an image attachment.
+#[derive(Debug, Clone)]
+pub struct AttachmentVariant {
+    pub name: String,
+    pub hash: String,
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub size: i64,
+}
+
+/// What the worker made of an image.
+#[derive(Debug, Clone)]
+pub struct ProcessedImage {
+    /// the image without metadata, replaces the uploaded content
+    pub hash: String,
+    pub size: i64,
+    pub width: u32,
+    pub height: u32,
+    pub blurhash: Option<String>,
+    pub variants: Vec<AttachmentVariant>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub uuid: String,
+    pub owner_uuid: String,
+    /// sha-256 of the content, the key in the `MediaStorage`
+    pub hash: String,
+    /// sniffed from the content, never taken from the client
+    pub mime_type: String,
+    pub file_name: String,
+    pub size: i64,
+    pub created_at: i64,
+    pub processing: ProcessingState,
+    /// set for processed images
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// compact placeholder clients can render while the image loads
+    pub blurhash: Option<String>,
+    pub variants: Vec<AttachmentVariant>,
+}
+
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE attachment (
+        id          INTEGER PRIMARY KEY,
+        uuid        TEXT NOT NULL UNIQUE,
+        ownerUuid   TEXT NOT NULL,
+        hash        TEXT NOT NULL,
+        mimeType    TEXT NOT NULL,
+        fileName    TEXT NOT NULL,
+        size        INTEGER NOT NULL,
+        createdAt   INTEGER NOT NULL
+    );
+    CREATE INDEX attachment_owner_idx ON attachment (ownerUuid, id);
+    CREATE INDEX attachment_hash_idx ON attachment (hash);",
+    "ALTER TABLE attachment ADD COLUMN processing TEXT NOT NULL DEFAULT 'none';
+    ALTER TABLE attachment ADD COLUMN width INTEGER;
+    ALTER TABLE attachment ADD COLUMN height INTEGER;
+    ALTER TABLE attachment ADD COLUMN blurhash TEXT;
+    CREATE INDEX attachment_processing_idx ON attachment (processing, id);
+    CREATE TABLE attachment_variant (
+        attachmentId    INTEGER NOT NULL REFERENCES attachment(id) ON DELETE CASCADE,
+        name            TEXT NOT NULL,
+        hash            TEXT NOT NULL,
+        mimeType        TEXT NOT NULL,
+        width           INTEGER NOT NULL,
+        height          INTEGER NOT NULL,
+        size            INTEGER NOT NULL,
+        PRIMARY KEY (attachmentId, name)
+    ) WITHOUT ROWID;
+    CREATE INDEX attachment_variant_hash_idx ON attachment_variant (hash);",
+];
+
+const ATTACHMENT_COLUMNS: &str = "id, uuid, ownerUuid, hash, mimeType, fileName, size, createdAt,
+    processing, width, height, blurhash";
+
+fn variant_from_row(row: &Row) -> rusqlite::Result<AttachmentVariant> {
+    Ok(AttachmentVariant {
+        name: row.get(0)?,
+        hash: row.get(1)?,
+        mime_type: row.get(2)?,
+        width: row.get(3)?,
+        height: row.get(4)?,
+        size: row.get(5)?,
+    })
+}
+
+fn db_error(err: rusqlite::Error) -> MediaDbError {
+    log::error!("{:?}", err);
+    MediaDbError::GenericError
+}
+
+impl MediaDbService {
+    pub fn connect(db_collection_path: &str) -> Result<Self, MediaDbError> {
+        let mut conn =
+            Connection::open(format!("{}/media.db", db_collection_path)).map_err(db_error)?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")
+            .map_err(db_error)?;
+        migrate(&mut conn, MIGRATIONS).map_err(db_error)?;
+
+        return Ok(Self { conn });
+    }
+
+    fn attachment_from_row(&self, row: &Row) -> rusqlite::Result<Attachment> {
+        let attachment_id: i64 = row.get(0)?;
+
+        let mut statement = self.conn.prepare_cached(
+            "SELECT name, hash, mimeType, width, height, size FROM attachment_variant
+                WHERE attachmentId=?1 ORDER BY width, name",
+        )?;
+        let variants = statement
+            .query_map([attachment_id], variant_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(Attachment {
+            uuid: row.get(1)?,
+            owner_uuid: row.get(2)?,
+            hash: row.get(3)?,
+            mime_type: row.get(4)?,
+            file_name: row.get(5)?,
+            size: row.get(6)?,
+            created_at: row.get(7)?,
+            processing: ProcessingState::from_db(&row.get::<_, String>(8)?),
+            width: row.get(9)?,
+            height: row.get(10)?,
+            blurhash: row.get(11)?,
+            variants,
+        })
+    }
+
+    /// Adds an upload, images are left `Pending` for the worker.
+    /// Adds an attachment unless the owner would store more than `quota` bytes with it. The
+    /// check and the insert are one statement, concurrent uploads can't both pass the check.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_attachment(
+        &self,
+        owner_uuid: &str,
+        hash: &str,
+        mime_type: &str,
+        file_name: &str,
+        size: i64,
+        processing: ProcessingState,
+        quota: i64,
+    ) -> Result<Attachment, MediaDbError> {
+        let attachment = Attachment {
+            uuid: Uuid::new_v4().to_string(),
+            owner_uuid: owner_uuid.to_owned(),
+            hash: hash.to_owned(),
+            mime_type: mime_type.to_owned(),
+            file_name: file_name.to_owned(),
+            size,
+            created_at: Utc::now().timestamp(),
+            processing,
+            width: None,
+            height: None,
+            blurhash: None,
+            variants: Vec::new(),
+        };
+        let inserted = self
+            .conn
+            .execute(
+                "INSERT INTO attachment (uuid, ownerUuid, hash, mimeType, fileName, size, createdAt, processing)
+                    SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8
+                    WHERE (SELECT IFNULL(SUM(size), 0) FROM attachment WHERE ownerUuid=?2) + ?6 <= ?9",
+                params![
+                    attachment.uuid,
+                    attachment.owner_uuid,
+                    attachment.hash,
+                    attachment.mime_type,
+                    attachment.file_name,
+                    attachment.size,
+                    attachment.created_at,
+                    attachment.processing.as_str(),
+                    quota
+                ],
+            )
+            .map_err(db_error)?;
+        if inserted == 0 {
+            return Err(MediaDbError::QuotaExceeded);
+        }
+
+        return Ok(attachment);
+    }
+
+    pub fn get_attachment(&self, attachment_uuid: &str) -> Result<Attachment, MediaDbError> {
+        let attachment = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM attachment WHERE uuid=?1",
+                    ATTACHMENT_COLUMNS
+                ),
+                [attachment_uuid],
+                |row| self.attachment_from_row(row),
+            )
+            .optional()
+            .map_err(db_error)?;
+
+        return attachment.ok_or(MediaDbError::AttachmentNotFound);
+    }
+
+    /// Returns one page of the uploads of a user, newest first, and the total count.
+    pub fn get_attachments(
+        &self,
+        owner_uuid: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<Attachment>, i64), MediaDbError> {
+        let total: i64 = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM attachment WHERE ownerUuid=?1",
+                [owner_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM attachment WHERE ownerUuid=?1
+                    ORDER BY id DESC limit ?2 offset ?3",
+                ATTACHMENT_COLUMNS
+            ))
+            .map_err(db_error)?;
+        let attachments = statement
+            .query_map(params![owner_uuid, limit, offset], |row| {
+                self.attachment_from_row(row)
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok((attachments, total));
+    }
+
+    /// Bytes counted against the quota of a user. Every upload counts, even when the same
+    /// content is stored only once.
+    pub fn used_bytes(&self, owner_uuid: &str) -> Result<i64, MediaDbError> {
+        return self
+            .conn
+            .query_row(
+                "SELECT IFNULL(SUM(size), 0) FROM attachment WHERE ownerUuid=?1",
+                [owner_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error);
+    }
+
+    pub fn delete_attachment(&self, attachment_uuid: &str) -> Result<(), MediaDbError> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM attachment WHERE uuid=?1", [attachment_uuid])
+            .map_err(db_error)?;
+        if deleted == 0 {
+            return Err(MediaDbError::AttachmentNotFound);
+        }
+        return Ok(());
+    }
+
+    /// Whether any attachment or variant still has the content stored under `hash`.
+    pub fn is_hash_used(&self, hash: &str) -> Result<bool, MediaDbError> {
+        return self
+            .conn
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM attachment WHERE hash=?1)
+                    OR EXISTS (SELECT 1 FROM attachment_variant WHERE hash=?1)",
+                [hash],
+                |row| row.get(0),
+            )
+            .map_err(db_error);
+    }
+
+    /// The oldest images waiting for the worker.
+    pub fn get_pending_attachments(&self, limit: u32) -> Result<Vec<Attachment>, MediaDbError> {
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM attachment WHERE processing = 'pending' ORDER BY id limit ?1",
+                ATTACHMENT_COLUMNS
+            ))
+            .map_err(db_error)?;
+        let attachments = statement
+            .query_map([limit], |row| self.attachment_from_row(row))
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(attachments);
+    }
+
+    /// Stores what the worker made of a pending image. Returns `false` when the upload was
+    /// deleted in the meantime.
+    pub fn complete_processing(
+        &mut self,
+        attachment_uuid: &str,
+        image: &ProcessedImage,
+    ) -> Result<bool, MediaDbError> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        let updated = tx
+            .execute(
+                "UPDATE attachment SET processing = 'ready', hash=?1, size=?2, width=?3, height=?4,
+                    blurhash=?5 WHERE uuid=?6 AND processing = 'pending'",
+                params![
+                    image.hash,
+                    image.size,
+                    image.width,
+                    image.height,
+                    image.blurhash,
+                    attachment_uuid
+                ],
+            )
+            .map_err(db_error)?;
+        if updated == 0 {
+            return Ok(false);
+        }
+        for variant in &image.variants {
+            tx.execute(
+                "INSERT OR REPLACE INTO attachment_variant (attachmentId, name, hash, mimeType, width, height, size)
+                    SELECT id, ?2, ?3, ?4, ?5, ?6, ?7 FROM attachment WHERE uuid=?1",
+                params![
+                    attachment_uuid,
+                    variant.name,
+                    variant.hash,
+                    variant.mime_type,
+                    variant.width,
+                    variant.height,
+                    variant.size
+                ],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        return Ok(true);
+    }
+
+    pub fn fail_processing(&self, attachment_uuid: &str) -> Result<(), MediaDbError> {
+        self.conn
+            .execute(
+                "UPDATE attachment SET processing = 'failed' WHERE uuid=?1",
+                [attachment_uuid],
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    io::{self, Cursor},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageDecoder, ImageError,
+    ImageFormat, ImageReader, Limits,
+};
+
+use super::{
+    media_db_service::{Attachment, AttachmentVariant, MediaDbService, ProcessedImage},
+    media_storage::MediaStorage,
+};
+
+/// How often the worker looks for new uploads when it has nothing to do.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const BATCH_SIZE: u32 = 10;
+/// Larger images are refused, decoding them could exhaust the memory.
+const MAX_DIMENSION: u32 = 12000;
+const JPEG_QUALITY: u8 = 85;
+/// Resized variants, each only made when the image is wider.
+const VARIANT_WIDTHS: &[(&str, u32)] = &[("small", 320), ("medium", 800), ("large", 1600)];
+/// The WebP version is scaled down to this width, smaller images keep their size.
+const WEBP_MAX_WIDTH: u32 = 1600;
+const BLURHASH_COMPONENTS_X: u32 = 4;
+const BLURHASH_COMPONENTS_Y: u32 = 3;
+/// The placeholder only needs a tiny copy of the image.
+const BLURHASH_SOURCE_SIZE: u32 = 32;
+
+#[derive(Debug)]
+pub enum MediaProcessingError {
+    StorageError,
+    ImageError,
+}
+
+impl From<io::Error> for MediaProcessingError {
+    fn from(value: io::Error) -> Self {
+        log::error!("media worker, storage failed {:?}", value);
+        MediaProcessingError::StorageError
+    }
+}
+
+impl From<ImageError> for MediaProcessingError {
+    fn from(value: ImageError) -> Self {
+        log::error!("media worker, image failed {:?}", value);
+        MediaProcessingError::ImageError
+    }
+}
+
+/// Starts the background worker which processes uploaded images: it strips their metadata,
+/// rotates them upright, makes the resized variants and the WebP version, and computes the
+/// blurhash placeholder. Uploads left pending by a restart are picked up again.
+pub fn spawn_media_worker(
+    media_db_state: Arc<Mutex<MediaDbService>>,
+    media_storage: Arc<MediaStorage>,
+) {
+    thread::spawn(move || loop {
+        let pending = media_db_state
+            .lock()
+            .unwrap()
+            .get_pending_attachments(BATCH_SIZE);
+        match pending {
+            Ok(attachments) if !attachments.is_empty() => {
+                for attachment in attachments {
+                    process_attachment(&media_db_state, &media_storage, &attachment);
+                }
+            }
+            Ok(_) => thread::sleep(POLL_INTERVAL),
+            Err(err) => {
+                log::error!("media worker, reading pending uploads failed {:?}", err);
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    });
+}
+
+fn process_attachment(
+    media_db_state: &Mutex<MediaDbService>,
+    media_storage: &MediaStorage,
+    attachment: &Attachment,
+) {
+    let image = match process_image(media_storage, attachment) {
+        Ok(image) => image,
+        Err(err) => {
+            log::error!(
+                "media worker, processing {} failed {:?}",
+                attachment.uuid,
+                err
+            );
+            if let Err(err) = media_db_state
+                .lock()
+                .unwrap()
+                .fail_processing(&attachment.uuid)
+            {
+                log::error!("media worker, marking upload as failed failed {:?}", err);
+            }
+            return;
+        }
+    };
+
+    let completed = media_db_state
+        .lock()
+        .unwrap()
+        .complete_processing(&attachment.uuid, &image);
+    let mut unused_hashes = vec![attachment.hash.clone()];
+    match completed {
+        Ok(true) => {}
+        Ok(false) => {
+            // deleted while it was processed
+            unused_hashes.push(image.hash.clone());
+            unused_hashes.extend(image.variants.iter().map(|variant| variant.hash.clone()));
+        }
+        Err(err) => {
+            log::error!("media worker, storing {} failed {:?}", attachment.uuid, err);
+            return;
+        }
+    }
+    remove_unused(media_db_state, media_storage, &unused_hashes);
+}
+
+/// Removes stored content no attachment or variant refers to anymore.
+pub fn remove_unused(
+    media_db_state: &Mutex<MediaDbService>,
+    media_storage: &MediaStorage,
+    hashes: &[String],
+) {
+    for hash in hashes {
+        match media_db_state.lock().unwrap().is_hash_used(hash) {
+            Ok(true) => {}
+            Ok(false) => {
+                if let Err(err) = media_storage.remove(hash) {
+                    log::error!("media, removing stored content failed {:?}", err);
+                }
+            }
+            Err(err) => log::error!("media, content lookup failed {:?}", err),
+        }
+    }
+}
+
+fn process_image(
+    media_storage: &MediaStorage,
+    attachment: &Attachment,
+) -> Result<ProcessedImage, MediaProcessingError> {
+    let content = media_storage.read(&attachment.hash)?;
+    let image = decode(&content)?;
+
+    // GIFs carry no EXIF data and re-encoding them would drop the animation
+    let (hash, size) = match ImageFormat::from_mime_type(&attachment.mime_type) {
+        Some(ImageFormat::Gif) | None => (attachment.hash.clone(), content.len() as i64),
+        Some(format) => {
+            let stripped = encode(&image, format)?;
+            (media_storage.store(&stripped)?, stripped.len() as i64)
+        }
+    };
+
+    let variant_format = match ImageFormat::from_mime_type(&attachment.mime_type) {
+        Some(ImageFormat::Jpeg) => ImageFormat::Jpeg,
+        Some(ImageFormat::WebP) => ImageFormat::WebP,
+        _ => ImageFormat::Png,
+    };
+    let mut variants = Vec::new();
+    for (name, width) in VARIANT_WIDTHS {
+        if image.width() > *width {
+            let resized = image.resize(*width, u32::MAX, FilterType::Lanczos3);
+            variants.push(store_variant(
+                media_storage,
+                name,
+                &resized,
+                variant_format,
+            )?);
+        }
+    }
+    let webp = if image.width() > WEBP_MAX_WIDTH {
+        image.resize(WEBP_MAX_WIDTH, u32::MAX, FilterType::Lanczos3)
+    } else {
+        image.clone()
+    };
+    variants.push(store_variant(
+        media_storage,
+        "webp",
+        &webp,
+        ImageFormat::WebP,
+    )?);
+
+    let thumbnail = image
+        .thumbnail(BLURHASH_SOURCE_SIZE, BLURHASH_SOURCE_SIZE)
+        .to_rgba8();
+    let blurhash = blurhash::encode(
+        BLURHASH_COMPONENTS_X,
+        BLURHASH_COMPONENTS_Y,
+        thumbnail.width(),
+        thumbnail.height(),
+        thumbnail.as_raw(),
+    )
+    .map_err(|err| log::error!("media worker, blurhash failed {:?}", err))
+    .ok();
+
+    return Ok(ProcessedImage {
+        hash,
+        size,
+        width: image.width(),
+        height: image.height(),
+        blurhash,
+        variants,
+    });
+}
+
+/// Decodes an image and turns it upright as its EXIF orientation says, the pixels are all
+/// that is kept.
+fn decode(content: &[u8]) -> Result<DynamicImage, MediaProcessingError> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+
+    let mut reader = ImageReader::new(Cursor::new(content)).with_guessed_format()?;
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    return Ok(image);
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, MediaProcessingError> {
+    let mut content = Vec::new();
+    match format {
+        ImageFormat::Jpeg => image
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut content, JPEG_QUALITY))?,
+        // the WebP encoder only takes 8 bit images
+        ImageFormat::WebP => DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_to(Cursor::new(&mut content), ImageFormat::WebP)?,
+        _ => image.write_to(Cursor::new(&mut content), format)?,
+    }
+    return Ok(content);
+}
+
+fn store_variant(
+    media_storage: &MediaStorage,
+    name: &str,
+    image: &DynamicImage,
+    format: ImageFormat,
+) -> Result<AttachmentVariant, MediaProcessingError> {
+    let content = encode(image, format)?;
+    return Ok(AttachmentVariant {
+        name: name.to_owned(),
+        hash: media_storage.store(&content)?,
+        mime_type: format.to_mime_type().to_owned(),
+        width: image.width(),
+        height: image.height(),
+        size: content.len() as i64,
+    });
+}
@@ -0,0 +1,136 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::{
+    blob_storage::{BlobStorage, LocalBlobStorage},
+    env_settings::{EnvSettings, MediaStorageSettings},
+    s3_blob_storage::S3BlobStorage,
+};
+
+/// Bytes kept from the start of an upload to sniff its type from.
+const SNIFF_LENGTH: usize = 8192;
+
+/// Content-addressed file storage, a file is stored once under the sha-256 of its content
+/// however often it is uploaded. The content goes to the `BlobStorage` picked in the
+/// settings, uploads are written to the `media/tmp` folder inside the db collection folder
+/// first.
+#[derive(Debug)]
+pub struct MediaStorage {
+    temp_root: PathBuf,
+    blobs: Box<dyn BlobStorage>,
+}
+
+/// A file being uploaded, written to a temporary file until `finish` moves it into place.
+/// Dropping an unfinished upload removes the temporary file.
+#[derive(Debug)]
+pub struct MediaUpload {
+    file: File,
+    temp_path: Option<PathBuf>,
+    hasher: Sha256,
+    size: u64,
+    head: Vec<u8>,
+}
+
+impl MediaStorage {
+    pub fn new(env_settings: &EnvSettings) -> io::Result<Self> {
+        let root = PathBuf::from(&env_settings.db_collection_path).join("media");
+        let temp_root = root.join("tmp");
+        fs::create_dir_all(&temp_root)?;
+        let blobs: Box<dyn BlobStorage> = match &env_settings.media_storage {
+            MediaStorageSettings::Local => Box::new(LocalBlobStorage::new(root)?),
+            MediaStorageSettings::S3(s3_settings) => {
+                Box::new(S3BlobStorage::new(s3_settings.clone()))
+            }
+        };
+        return Ok(Self { temp_root, blobs });
+    }
+
+    pub fn begin_upload(&self) -> io::Result<MediaUpload> {
+        let temp_path = self.temp_root.join(Uuid::new_v4().to_string());
+        return Ok(MediaUpload {
+            file: File::create(&temp_path)?,
+            temp_path: Some(temp_path),
+            hasher: Sha256::new(),
+            size: 0,
+            head: Vec::new(),
+        });
+    }
+
+    /// Stores content which is already in memory, returns the hash it is stored under.
+    pub fn store(&self, content: &[u8]) -> io::Result<String> {
+        let mut upload = self.begin_upload()?;
+        upload.write(content)?;
+        return upload.finish(self);
+    }
+
+    pub fn read(&self, hash: &str) -> io::Result<Vec<u8>> {
+        self.blobs.get(hash)
+    }
+
+    pub fn remove(&self, hash: &str) -> io::Result<()> {
+        self.blobs.delete(hash)
+    }
+
+    /// See `BlobStorage::presigned_url`.
+    pub fn presigned_url(
+        &self,
+        hash: &str,
+        mime_type: &str,
+        content_disposition: &str,
+    ) -> Option<String> {
+        self.blobs
+            .presigned_url(hash, mime_type, content_disposition)
+    }
+}
+
+impl MediaUpload {
+    pub fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.file.write_all(chunk)?;
+        self.hasher.update(chunk);
+        self.size += chunk.len() as u64;
+        if self.head.len() < SNIFF_LENGTH {
+            let missing = SNIFF_LENGTH - self.head.len();
+            self.head
+                .extend_from_slice(&chunk[..missing.min(chunk.len())]);
+        }
+        return Ok(());
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The first bytes of the upload, enough to tell its type.
+    pub fn head(&self) -> &[u8] {
+        &self.head
+    }
+
+    /// Moves the upload into the storage and returns the hex encoded sha-256 it is stored
+    /// under. Content which is already stored is kept as it is.
+    pub fn finish(mut self, storage: &MediaStorage) -> io::Result<String> {
+        self.file.sync_all()?;
+        let hash = format!("{:x}", self.hasher.clone().finalize());
+        let temp_path = self.temp_path.take().unwrap();
+
+        let stored = storage.blobs.put_file(&hash, &temp_path);
+        fs::remove_file(&temp_path)?;
+        stored?;
+        return Ok(hash);
+    }
+}
+
+impl Drop for MediaUpload {
+    fn drop(&mut self) {
+        if let Some(temp_path) = self.temp_path.take() {
+            if let Err(err) = fs::remove_file(&temp_path) {
+                log::error!("media, removing temporary upload failed {:?}", err);
+            }
+        }
+    }
+}
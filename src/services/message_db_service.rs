@@ -0,0 +1,433 @@
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use uuid::Uuid;
+
+use super::db_migrations::migrate;
+
+#[derive(Debug)]
+pub enum MessageDbError {
+    GenericError,
+    ConversationNotFound,
+}
+
+/// Direct messages, backed by `messages.db` inside the db collection folder. Kept apart
+/// from the user databases holding the posts, so no post query can ever reach a message.
+///
+/// Reading methods take `hidden_uuids`, the users blocked by the reader or blocking them.
+/// Their messages are left out and one-to-one conversations with them are hidden.
+#[derive(Debug)]
+pub struct MessageDbService {
+    conn: Connection,
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub uuid: String,
+    pub sender_uuid: String,
+    pub body: String,
+    pub created_at: i64,
+}
+
+/// A conversation as seen by one of its members.
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    pub uuid: String,
+    /// group conversations can have more than two members, even when only two are left
+    pub is_group: bool,
+    pub member_uuids: Vec<String>,
+    pub created_at: i64,
+    /// time of the latest message, or of the creation when there is none yet
+    pub updated_at: i64,
+    /// messages of the other members the user hasn't read yet
+    pub unread_count: i64,
+    pub last_message: Option<Message>,
+}
+
+const MIGRATIONS: &[&str] = &["CREATE TABLE conversation (
+        id          INTEGER PRIMARY KEY,
+        uuid        TEXT NOT NULL UNIQUE,
+        isGroup     INTEGER NOT NULL,
+        directKey   TEXT UNIQUE,
+        createdAt   INTEGER NOT NULL,
+        updatedAt   INTEGER NOT NULL
+    );
+    CREATE TABLE conversation_member (
+        conversationId      INTEGER NOT NULL REFERENCES conversation(id) ON DELETE CASCADE,
+        userUuid            TEXT NOT NULL,
+        lastReadMessageId   INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (conversationId, userUuid)
+    ) WITHOUT ROWID;
+    CREATE INDEX conversation_member_user_idx ON conversation_member (userUuid);
+    CREATE TABLE message (
+        id              INTEGER PRIMARY KEY AUTOINCREMENT,
+        uuid            TEXT NOT NULL UNIQUE,
+        conversationId  INTEGER NOT NULL REFERENCES conversation(id) ON DELETE CASCADE,
+        senderUuid      TEXT NOT NULL,
+        body            TEXT NOT NULL,
+        createdAt       INTEGER NOT NULL
+    );
+    CREATE INDEX message_conversation_idx ON message (conversationId, id);"];
+
+/// Expects the JSON array of the hidden users bound to `?1`.
+const CONVERSATION_COLUMNS: &str = "c.id, c.uuid, c.isGroup, c.createdAt, c.updatedAt,
+    (SELECT COUNT(*) FROM message m WHERE m.conversationId = c.id
+        AND m.id > cm.lastReadMessageId AND m.senderUuid != cm.userUuid
+        AND m.senderUuid NOT IN (SELECT value FROM json_each(?1)))";
+
+/// One-to-one conversations with a hidden user bound to `?1` don't show up at all, group
+/// conversations only lose the messages of hidden users.
+const VISIBLE_CONVERSATION: &str = "NOT (c.isGroup = 0 AND EXISTS (
+    SELECT 1 FROM conversation_member o WHERE o.conversationId = c.id
+        AND o.userUuid IN (SELECT value FROM json_each(?1))))";
+
+const MESSAGE_COLUMNS: &str = "uuid, senderUuid, body, createdAt";
+
+/// Identifies the one-to-one conversation of two users, whoever started it.
+fn direct_key(user_uuid: &str, other_uuid: &str) -> String {
+    if user_uuid < other_uuid {
+        format!("{}:{}", user_uuid, other_uuid)
+    } else {
+        format!("{}:{}", other_uuid, user_uuid)
+    }
+}
+
+fn message_from_row(row: &Row) -> rusqlite::Result<Message> {
+    Ok(Message {
+        uuid: row.get(0)?,
+        sender_uuid: row.get(1)?,
+        body: row.get(2)?,
+        created_at: row.get(3)?,
+    })
+}
+
+/// The hidden users are passed to SQLite as one JSON array, read with `json_each`.
+fn hidden_json(hidden_uuids: &[String]) -> String {
+    serde_json::to_string(hidden_uuids).unwrap_or_else(|_| "[]".to_owned())
+}
+
+fn db_error(err: rusqlite::Error) -> MessageDbError {
+    log::error!("{:?}", err);
+    MessageDbError::GenericError
+}
+
+impl MessageDbService {
+    pub fn connect(db_collection_path: &str) -> Result<Self, MessageDbError> {
+        let mut conn =
+            Connection::open(format!("{}/messages.db", db_collection_path)).map_err(db_error)?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")
+            .map_err(db_error)?;
+        migrate(&mut conn, MIGRATIONS).map_err(db_error)?;
+
+        return Ok(Self { conn });
+    }
+
+    /// Returns the uuid of the one-to-one conversation of two users, started when needed.
+    pub fn direct_conversation(
+        &mut self,
+        user_uuid: &str,
+        other_uuid: &str,
+    ) -> Result<String, MessageDbError> {
+        let key = direct_key(user_uuid, other_uuid);
+        let existing: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT uuid FROM conversation WHERE directKey=?1",
+                [&key],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?;
+        if let Some(conversation_uuid) = existing {
+            return Ok(conversation_uuid);
+        }
+
+        return self.insert_conversation(&[user_uuid, other_uuid], Some(&key));
+    }
+
+    /// Starts a new group conversation, `member_uuids` includes the user starting it.
+    pub fn create_group(&mut self, member_uuids: &[&str]) -> Result<String, MessageDbError> {
+        return self.insert_conversation(member_uuids, None);
+    }
+
+    fn insert_conversation(
+        &mut self,
+        member_uuids: &[&str],
+        direct_key: Option<&str>,
+    ) -> Result<String, MessageDbError> {
+        let now = Utc::now().timestamp();
+        let conversation_uuid = Uuid::new_v4().to_string();
+
+        let tx = self.conn.transaction().map_err(db_error)?;
+        tx.execute(
+            "INSERT INTO conversation (uuid, isGroup, directKey, createdAt, updatedAt)
+                VALUES (?1, ?2, ?3, ?4, ?4)",
+            params![conversation_uuid, direct_key.is_none(), direct_key, now],
+        )
+        .map_err(db_error)?;
+        let conversation_id = tx.last_insert_rowid();
+        for member_uuid in member_uuids {
+            tx.execute(
+                "INSERT OR IGNORE INTO conversation_member (conversationId, userUuid)
+                    VALUES (?1, ?2)",
+                params![conversation_id, member_uuid],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        return Ok(conversation_uuid);
+    }
+
+    /// Looks up a conversation of `user_uuid`, conversations the user isn't part of are
+    /// reported as not found.
+    pub fn get_conversation(
+        &self,
+        conversation_uuid: &str,
+        user_uuid: &str,
+        hidden_uuids: &[String],
+    ) -> Result<Conversation, MessageDbError> {
+        let hidden = hidden_json(hidden_uuids);
+        let conversation = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM conversation c
+                        JOIN conversation_member cm ON cm.conversationId = c.id
+                        WHERE c.uuid=?2 AND cm.userUuid=?3 AND {}",
+                    CONVERSATION_COLUMNS, VISIBLE_CONVERSATION
+                ),
+                params![hidden, conversation_uuid, user_uuid],
+                |row| self.conversation_from_row(row, &hidden),
+            )
+            .optional()
+            .map_err(db_error)?;
+
+        return conversation.ok_or(MessageDbError::ConversationNotFound);
+    }
+
+    /// Returns one page of the conversations of a user, the most recently active first,
+    /// and the total count.
+    pub fn get_conversations(
+        &self,
+        user_uuid: &str,
+        hidden_uuids: &[String],
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<Conversation>, i64), MessageDbError> {
+        let hidden = hidden_json(hidden_uuids);
+        let total: i64 = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM conversation c
+                        JOIN conversation_member cm ON cm.conversationId = c.id
+                        WHERE cm.userUuid=?2 AND {}",
+                    VISIBLE_CONVERSATION
+                ),
+                params![hidden, user_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM conversation c
+                    JOIN conversation_member cm ON cm.conversationId = c.id
+                    WHERE cm.userUuid=?2 AND {}
+                    ORDER BY c.updatedAt DESC, c.id DESC limit ?3 offset ?4",
+                CONVERSATION_COLUMNS, VISIBLE_CONVERSATION
+            ))
+            .map_err(db_error)?;
+        let conversations = statement
+            .query_map(params![hidden, user_uuid, limit, offset], |row| {
+                self.conversation_from_row(row, &hidden)
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok((conversations, total));
+    }
+
+    fn conversation_from_row(&self, row: &Row, hidden: &str) -> rusqlite::Result<Conversation> {
+        let conversation_id: i64 = row.get(0)?;
+
+        let mut statement = self
+            .conn
+            .prepare_cached("SELECT userUuid FROM conversation_member WHERE conversationId=?1")?;
+        let member_uuids = statement
+            .query_map([conversation_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        let last_message = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM message WHERE conversationId=?1
+                        AND senderUuid NOT IN (SELECT value FROM json_each(?2))
+                        ORDER BY id DESC limit 1",
+                    MESSAGE_COLUMNS
+                ),
+                params![conversation_id, hidden],
+                message_from_row,
+            )
+            .optional()?;
+
+        Ok(Conversation {
+            uuid: row.get(1)?,
+            is_group: row.get(2)?,
+            member_uuids,
+            created_at: row.get(3)?,
+            updated_at: row.get(4)?,
+            unread_count: row.get(5)?,
+            last_message,
+        })
+    }
+
+    /// Adds a message to a conversation of the sender. Everything up to the new message
+    /// counts as read for the sender.
+    pub fn add_message(
+        &mut self,
+        conversation_uuid: &str,
+        sender_uuid: &str,
+        body: &str,
+    ) -> Result<Message, MessageDbError> {
+        let message = Message {
+            uuid: Uuid::new_v4().to_string(),
+            sender_uuid: sender_uuid.to_owned(),
+            body: body.to_owned(),
+            created_at: Utc::now().timestamp(),
+        };
+
+        let tx = self.conn.transaction().map_err(db_error)?;
+        let conversation_id: i64 = tx
+            .query_row(
+                "SELECT c.id FROM conversation c
+                    JOIN conversation_member cm ON cm.conversationId = c.id
+                    WHERE c.uuid=?1 AND cm.userUuid=?2",
+                params![conversation_uuid, sender_uuid],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?
+            .ok_or(MessageDbError::ConversationNotFound)?;
+        tx.execute(
+            "INSERT INTO message (uuid, conversationId, senderUuid, body, createdAt)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                message.uuid,
+                conversation_id,
+                message.sender_uuid,
+                message.body,
+                message.created_at
+            ],
+        )
+        .map_err(db_error)?;
+        let message_id = tx.last_insert_rowid();
+        tx.execute(
+            "UPDATE conversation SET updatedAt=?1 WHERE id=?2",
+            params![message.created_at, conversation_id],
+        )
+        .map_err(db_error)?;
+        tx.execute(
+            "UPDATE conversation_member SET lastReadMessageId=?1
+                WHERE conversationId=?2 AND userUuid=?3",
+            params![message_id, conversation_id, sender_uuid],
+        )
+        .map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+
+        return Ok(message);
+    }
+
+    /// Returns one page of messages of a conversation, newest first, and the total count.
+    /// The caller has to make sure the user is a member.
+    pub fn get_messages(
+        &self,
+        conversation_uuid: &str,
+        hidden_uuids: &[String],
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<Message>, i64), MessageDbError> {
+        let hidden = hidden_json(hidden_uuids);
+        let total: i64 = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM message
+                    WHERE conversationId = (SELECT id FROM conversation WHERE uuid=?1)
+                        AND senderUuid NOT IN (SELECT value FROM json_each(?2))",
+                params![conversation_uuid, hidden],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM message
+                    WHERE conversationId = (SELECT id FROM conversation WHERE uuid=?1)
+                        AND senderUuid NOT IN (SELECT value FROM json_each(?2))
+                    ORDER BY id DESC limit ?3 offset ?4",
+                MESSAGE_COLUMNS
+            ))
+            .map_err(db_error)?;
+        let messages = statement
+            .query_map(
+                params![conversation_uuid, hidden, limit, offset],
+                message_from_row,
+            )
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok((messages, total));
+    }
+
+    /// Marks every message of the conversation as read by the user.
+    pub fn mark_read(
+        &self,
+        conversation_uuid: &str,
+        user_uuid: &str,
+    ) -> Result<(), MessageDbError> {
+        let updated = self
+            .conn
+            .execute(
+                "UPDATE conversation_member SET lastReadMessageId = IFNULL(
+                    (SELECT MAX(id) FROM message WHERE message.conversationId = conversation_member.conversationId),
+                    0)
+                WHERE userUuid=?2
+                    AND conversationId = (SELECT id FROM conversation WHERE uuid=?1)",
+                params![conversation_uuid, user_uuid],
+            )
+            .map_err(db_error)?;
+        if updated == 0 {
+            return Err(MessageDbError::ConversationNotFound);
+        }
+        return Ok(());
+    }
+
+    /// Number of conversations with messages the user hasn't read yet.
+    pub fn count_unread_conversations(
+        &self,
+        user_uuid: &str,
+        hidden_uuids: &[String],
+    ) -> Result<i64, MessageDbError> {
+        return self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM conversation c
+                        JOIN conversation_member cm ON cm.conversationId = c.id
+                        WHERE cm.userUuid=?2 AND {} AND EXISTS (
+                            SELECT 1 FROM message m WHERE m.conversationId = c.id
+                                AND m.id > cm.lastReadMessageId AND m.senderUuid != cm.userUuid
+                                AND m.senderUuid NOT IN (SELECT value FROM json_each(?1))
+                        )",
+                    VISIBLE_CONVERSATION
+                ),
+                params![hidden_json(hidden_uuids), user_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error);
+    }
+}
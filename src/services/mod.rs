@@ -1,2 +1,19 @@
+pub mod analytics_db_service;
+pub mod blob_storage;
+pub mod db_migrations;
 pub mod env_settings;
+pub mod link_preview_db_service;
+pub mod link_preview_fetcher;
+pub mod media_db_service;
+pub mod media_processor;
+pub mod media_storage;
+pub mod message_db_service;
+pub mod moderation_db_service;
+pub mod post_db_service;
+pub mod post_entities;
+pub mod post_renderer;
+pub mod s3_blob_storage;
+pub mod search_db_service;
+pub mod search_query;
+pub mod social_db_service;
 pub mod user_db_service;
@@ -0,0 +1,400 @@
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use super::db_migrations::migrate;
+
+#[derive(Debug)]
+pub enum ModerationDbError {
+    GenericError,
+    ReportNotFound,
+    ReportAlreadyResolved,
+}
+
+/// Reports about posts and users, the actions moderators took on them and the suspended
+/// accounts, backed by `moderation.db` inside the db collection folder.
+#[derive(Debug)]
+pub struct ModerationDbService {
+    conn: Connection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportReason {
+    Spam,
+    Harassment,
+    Hate,
+    Violence,
+    Sexual,
+    Misinformation,
+    Other,
+}
+
+impl ReportReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportReason::Spam => "spam",
+            ReportReason::Harassment => "harassment",
+            ReportReason::Hate => "hate",
+            ReportReason::Violence => "violence",
+            ReportReason::Sexual => "sexual",
+            ReportReason::Misinformation => "misinformation",
+            ReportReason::Other => "other",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "spam" => ReportReason::Spam,
+            "harassment" => ReportReason::Harassment,
+            "hate" => ReportReason::Hate,
+            "violence" => ReportReason::Violence,
+            "sexual" => ReportReason::Sexual,
+            "misinformation" => ReportReason::Misinformation,
+            _ => ReportReason::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportState {
+    Open,
+    /// a moderator hid the post, suspended or warned the user
+    Actioned,
+    Dismissed,
+}
+
+impl ReportState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportState::Open => "open",
+            ReportState::Actioned => "actioned",
+            ReportState::Dismissed => "dismissed",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "actioned" => ReportState::Actioned,
+            "dismissed" => ReportState::Dismissed,
+            _ => ReportState::Open,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModerationActionKind {
+    HidePost,
+    SuspendUser,
+    Warn,
+    Dismiss,
+}
+
+impl ModerationActionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModerationActionKind::HidePost => "hide-post",
+            ModerationActionKind::SuspendUser => "suspend-user",
+            ModerationActionKind::Warn => "warn",
+            ModerationActionKind::Dismiss => "dismiss",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "hide-post" => ModerationActionKind::HidePost,
+            "suspend-user" => ModerationActionKind::SuspendUser,
+            "warn" => ModerationActionKind::Warn,
+            _ => ModerationActionKind::Dismiss,
+        }
+    }
+}
+
+/// A report about `user_uuid`, or about their post `post_uuid` when it is set.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub id: i64,
+    pub reporter_uuid: String,
+    pub user_uuid: String,
+    pub post_uuid: Option<String>,
+    pub reason: ReportReason,
+    pub details: Option<String>,
+    pub state: ReportState,
+    pub created_at: i64,
+    pub resolved_at: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModerationAction {
+    pub id: i64,
+    pub moderator_uuid: String,
+    pub action: ModerationActionKind,
+    pub reason: String,
+    pub created_at: i64,
+}
+
+const MIGRATIONS: &[&str] = &["CREATE TABLE report (
+        id              INTEGER PRIMARY KEY AUTOINCREMENT,
+        reporterUuid    TEXT NOT NULL,
+        userUuid        TEXT NOT NULL,
+        postUuid        TEXT,
+        reason          TEXT NOT NULL,
+        details         TEXT,
+        state           TEXT NOT NULL DEFAULT 'open',
+        createdAt       INTEGER NOT NULL,
+        resolvedAt      INTEGER
+    );
+    CREATE UNIQUE INDEX report_open_idx ON report (reporterUuid, userUuid, IFNULL(postUuid, ''))
+        WHERE state = 'open';
+    CREATE INDEX report_state_idx ON report (state, id);
+    CREATE TABLE moderation_action (
+        id              INTEGER PRIMARY KEY AUTOINCREMENT,
+        reportId        INTEGER NOT NULL REFERENCES report(id),
+        moderatorUuid   TEXT NOT NULL,
+        action          TEXT NOT NULL,
+        reason          TEXT NOT NULL,
+        createdAt       INTEGER NOT NULL
+    );
+    CREATE INDEX moderation_action_report_idx ON moderation_action (reportId);
+    CREATE TABLE suspension (
+        userUuid    TEXT PRIMARY KEY,
+        reason      TEXT NOT NULL,
+        createdAt   INTEGER NOT NULL,
+        endsAt      INTEGER
+    ) WITHOUT ROWID;"];
+
+const REPORT_COLUMNS: &str =
+    "id, reporterUuid, userUuid, postUuid, reason, details, state, createdAt, resolvedAt";
+
+const ACTION_COLUMNS: &str = "id, moderatorUuid, action, reason, createdAt";
+
+fn report_from_row(row: &Row) -> rusqlite::Result<Report> {
+    let reason: String = row.get(4)?;
+    let state: String = row.get(6)?;
+    Ok(Report {
+        id: row.get(0)?,
+        reporter_uuid: row.get(1)?,
+        user_uuid: row.get(2)?,
+        post_uuid: row.get(3)?,
+        reason: ReportReason::from_db(&reason),
+        details: row.get(5)?,
+        state: ReportState::from_db(&state),
+        created_at: row.get(7)?,
+        resolved_at: row.get(8)?,
+    })
+}
+
+fn action_from_row(row: &Row) -> rusqlite::Result<ModerationAction> {
+    let action: String = row.get(2)?;
+    Ok(ModerationAction {
+        id: row.get(0)?,
+        moderator_uuid: row.get(1)?,
+        action: ModerationActionKind::from_db(&action),
+        reason: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+fn db_error(err: rusqlite::Error) -> ModerationDbError {
+    log::error!("{:?}", err);
+    ModerationDbError::GenericError
+}
+
+impl ModerationDbService {
+    pub fn connect(db_collection_path: &str) -> Result<Self, ModerationDbError> {
+        let mut conn =
+            Connection::open(format!("{}/moderation.db", db_collection_path)).map_err(db_error)?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")
+            .map_err(db_error)?;
+        migrate(&mut conn, MIGRATIONS).map_err(db_error)?;
+
+        return Ok(Self { conn });
+    }
+
+    /// Files a report. Reporting the same post or user again while the earlier report is
+    /// still open returns the open report unchanged.
+    pub fn add_report(
+        &self,
+        reporter_uuid: &str,
+        user_uuid: &str,
+        post_uuid: Option<&str>,
+        reason: ReportReason,
+        details: Option<&str>,
+    ) -> Result<Report, ModerationDbError> {
+        let open_report = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM report WHERE reporterUuid=?1 AND userUuid=?2
+                        AND IFNULL(postUuid, '') = IFNULL(?3, '') AND state = 'open'",
+                    REPORT_COLUMNS
+                ),
+                params![reporter_uuid, user_uuid, post_uuid],
+                report_from_row,
+            )
+            .optional()
+            .map_err(db_error)?;
+        if let Some(report) = open_report {
+            return Ok(report);
+        }
+
+        let report = self
+            .conn
+            .query_row(
+                &format!(
+                    "INSERT INTO report (reporterUuid, userUuid, postUuid, reason, details, createdAt)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6) RETURNING {}",
+                    REPORT_COLUMNS
+                ),
+                params![
+                    reporter_uuid,
+                    user_uuid,
+                    post_uuid,
+                    reason.as_str(),
+                    details,
+                    Utc::now().timestamp()
+                ],
+                report_from_row,
+            )
+            .map_err(db_error)?;
+
+        return Ok(report);
+    }
+
+    pub fn get_report(&self, report_id: i64) -> Result<Report, ModerationDbError> {
+        let report = self
+            .conn
+            .query_row(
+                &format!("SELECT {} FROM report WHERE id=?1", REPORT_COLUMNS),
+                [report_id],
+                report_from_row,
+            )
+            .optional()
+            .map_err(db_error)?;
+
+        return report.ok_or(ModerationDbError::ReportNotFound);
+    }
+
+    /// Returns one page of the reports in `state`, or of all reports when `None`, and the
+    /// total count. The oldest come first, the queue is worked through in order.
+    pub fn get_reports(
+        &self,
+        state: Option<ReportState>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<Report>, i64), ModerationDbError> {
+        let state = state.map(|state| state.as_str());
+        let total: i64 = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM report WHERE ?1 IS NULL OR state=?1",
+                params![state],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM report WHERE ?1 IS NULL OR state=?1
+                    ORDER BY id ASC limit ?2 offset ?3",
+                REPORT_COLUMNS
+            ))
+            .map_err(db_error)?;
+        let reports = statement
+            .query_map(params![state, limit, offset], report_from_row)
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok((reports, total));
+    }
+
+    /// The actions taken on a report, oldest first.
+    pub fn get_actions(&self, report_id: i64) -> Result<Vec<ModerationAction>, ModerationDbError> {
+        let mut statement = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT {} FROM moderation_action WHERE reportId=?1 ORDER BY id ASC",
+                ACTION_COLUMNS
+            ))
+            .map_err(db_error)?;
+        let actions = statement
+            .query_map([report_id], action_from_row)
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(actions);
+    }
+
+    /// Records the action of a moderator and resolves the report, together with the other
+    /// open reports about the same post or user. `SuspendUser` suspends the reported user
+    /// until `suspended_until`, or indefinitely when `None`.
+    pub fn resolve_report(
+        &mut self,
+        report_id: i64,
+        moderator_uuid: &str,
+        action: ModerationActionKind,
+        reason: &str,
+        suspended_until: Option<i64>,
+    ) -> Result<Report, ModerationDbError> {
+        let now = Utc::now().timestamp();
+        let state = match action {
+            ModerationActionKind::Dismiss => ReportState::Dismissed,
+            _ => ReportState::Actioned,
+        };
+
+        let tx = self.conn.transaction().map_err(db_error)?;
+        let report = tx
+            .query_row(
+                &format!("SELECT {} FROM report WHERE id=?1", REPORT_COLUMNS),
+                [report_id],
+                report_from_row,
+            )
+            .optional()
+            .map_err(db_error)?
+            .ok_or(ModerationDbError::ReportNotFound)?;
+        if report.state != ReportState::Open {
+            return Err(ModerationDbError::ReportAlreadyResolved);
+        }
+
+        tx.execute(
+            "INSERT INTO moderation_action (reportId, moderatorUuid, action, reason, createdAt)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![report_id, moderator_uuid, action.as_str(), reason, now],
+        )
+        .map_err(db_error)?;
+        tx.execute(
+            "UPDATE report SET state=?1, resolvedAt=?2
+                WHERE state = 'open' AND userUuid=?3 AND IFNULL(postUuid, '') = IFNULL(?4, '')",
+            params![state.as_str(), now, report.user_uuid, report.post_uuid],
+        )
+        .map_err(db_error)?;
+        if action == ModerationActionKind::SuspendUser {
+            tx.execute(
+                "INSERT INTO suspension (userUuid, reason, createdAt, endsAt)
+                    VALUES (?1, ?2, ?3, ?4)
+                    ON CONFLICT (userUuid) DO UPDATE SET
+                        reason=excluded.reason, createdAt=excluded.createdAt, endsAt=excluded.endsAt",
+                params![report.user_uuid, reason, now, suspended_until],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        return self.get_report(report_id);
+    }
+
+    /// Whether the user is suspended right now, suspensions end on their own once `endsAt`
+    /// has passed.
+    pub fn is_suspended(&self, user_uuid: &str) -> Result<bool, ModerationDbError> {
+        return self
+            .conn
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM suspension
+                    WHERE userUuid=?1 AND (endsAt IS NULL OR endsAt > ?2))",
+                params![user_uuid, Utc::now().timestamp()],
+                |row| row.get(0),
+            )
+            .map_err(db_error);
+    }
+}
@@ -0,0 +1,1168 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::Path,
+};
+
+use chrono::Utc;
+use deunicode::deunicode;
+use rusqlite::{
+    params, params_from_iter, types::Type, Connection, OptionalExtension, Row, Transaction,
+};
+use uuid::Uuid;
+
+use super::{
+    db_migrations::migrate,
+    post_entities::extract_urls,
+    post_renderer::{render_html, ContentFormat},
+    search_query::{HIGHLIGHT_END, HIGHLIGHT_START},
+};
+
+#[derive(Debug)]
+pub enum PostDbError {
+    GenericError,
+    PostNotFound,
+    RevisionNotFound,
+}
+
+/// Per-user post storage, backed by `user_{uuid}.db` inside the db collection folder.
+#[derive(Debug)]
+pub struct PostDbService {
+    conn: Connection,
+}
+
+#[derive(Debug, Clone)]
+pub struct Post {
+    pub uuid: String,
+    /// url safe, per user unique name derived from the title
+    pub slug: String,
+    pub title: String,
+    pub post: String,
+    pub format: ContentFormat,
+    /// sanitized html rendered from `post`
+    pub post_html: String,
+    pub visibility: Visibility,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// uuid of the post this one answers, the parent can belong to another user
+    pub reply_to: Option<String>,
+    /// number of public replies to this post
+    pub reply_count: i64,
+    pub like_count: i64,
+    pub repost_count: i64,
+    /// hidden by a moderator, only the author can still read it
+    pub hidden: bool,
+    /// uuids of the attached media, in display order
+    pub attachments: Vec<String>,
+    pub poll: Option<Poll>,
+    /// `http` and `https` urls written in the post, in the order they appear
+    pub links: Vec<String>,
+}
+
+/// A poll attached to a post, it is fixed once the post is written. The votes are kept
+/// in the `SocialDbService`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Poll {
+    pub options: Vec<String>,
+    /// voters can pick more than one option
+    pub multiple: bool,
+    pub closes_at: i64,
+}
+
+/// Everything a client provides when writing a post.
+#[derive(Debug, Clone)]
+pub struct PostInput {
+    pub title: String,
+    pub post: String,
+    pub format: ContentFormat,
+    pub visibility: Visibility,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    /// uuids of uploaded media of the author, in display order
+    pub attachments: Vec<String>,
+}
+
+/// Who can read a post.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Visibility {
+    /// listed on the blog, in feeds and in search
+    Public,
+    /// readable by everyone who has the link, but not listed anywhere
+    Unlisted,
+    /// only the author can read it
+    Private,
+}
+
+impl Visibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Unlisted => "unlisted",
+            Visibility::Private => "private",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "public" => Visibility::Public,
+            "unlisted" => Visibility::Unlisted,
+            _ => Visibility::Private,
+        }
+    }
+
+    /// Whether readers other than the author can open the post.
+    pub fn is_readable_by_others(&self) -> bool {
+        *self != Visibility::Private
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TagMatch {
+    #[default]
+    Any,
+    All,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PostFilter {
+    pub tags: Vec<String>,
+    pub tag_match: TagMatch,
+    pub category: Option<String>,
+    /// only posts with one of these visibilities, empty means all of them
+    pub visibilities: Vec<Visibility>,
+    /// leave out posts hidden by a moderator
+    pub exclude_hidden: bool,
+    /// leave out pinned posts, for listings showing them separately
+    pub exclude_pinned: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct NameCount {
+    pub name: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PostSearchHit {
+    pub post: Post,
+    /// title with the matched terms wrapped in highlight markers
+    pub title_highlight: String,
+    /// fragment of the post body around the matched terms, wrapped in highlight markers
+    pub snippet: String,
+    /// bm25 score, lower is a better match
+    pub rank: f64,
+}
+
+impl Post {
+    /// Whether the post is listed on the blog, in feeds, timelines and search.
+    pub fn is_public(&self) -> bool {
+        self.visibility == Visibility::Public && !self.hidden
+    }
+
+    /// Whether readers other than the author can open the post.
+    pub fn is_readable_by_others(&self) -> bool {
+        self.visibility.is_readable_by_others() && !self.hidden
+    }
+}
+
+/// Result of looking up a post by slug.
+#[derive(Debug, Clone)]
+pub enum SlugLookup {
+    Post(Box<Post>),
+    /// the slug belonged to the post before its title changed, holds the current slug
+    Redirect(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PostRevision {
+    pub revision: i64,
+    pub title: String,
+    pub post: String,
+    pub format: ContentFormat,
+    pub created_at: i64,
+}
+
+/// Schema migrations of the user database, see [`migrate`].
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS post (
+        id      INTEGER PRIMARY KEY,
+        title   TEXT NOT NULL,
+        post    TEXT NOT NULL,
+        uuid    TEXT NOT NULL UNIQUE
+    );",
+    "ALTER TABLE post ADD COLUMN createdAt INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE post ADD COLUMN updatedAt INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE post_revision (
+        id          INTEGER PRIMARY KEY,
+        postId      INTEGER NOT NULL REFERENCES post(id) ON DELETE CASCADE,
+        revision    INTEGER NOT NULL,
+        title       TEXT NOT NULL,
+        post        TEXT NOT NULL,
+        createdAt   INTEGER NOT NULL,
+        UNIQUE (postId, revision)
+    );
+    INSERT INTO post_revision (postId, revision, title, post, createdAt)
+        SELECT id, 1, title, post, createdAt FROM post;",
+    "ALTER TABLE post ADD COLUMN category TEXT;
+    CREATE TABLE tag (
+        id      INTEGER PRIMARY KEY,
+        name    TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE post_tag (
+        postId  INTEGER NOT NULL REFERENCES post(id) ON DELETE CASCADE,
+        tagId   INTEGER NOT NULL REFERENCES tag(id) ON DELETE CASCADE,
+        PRIMARY KEY (postId, tagId)
+    );
+    CREATE INDEX post_tag_tag_idx ON post_tag (tagId);",
+    "CREATE VIRTUAL TABLE post_fts USING fts5(
+        title, post,
+        content='post', content_rowid='id',
+        tokenize='unicode61 remove_diacritics 2'
+    );
+    CREATE TRIGGER post_fts_after_insert AFTER INSERT ON post BEGIN
+        INSERT INTO post_fts (rowid, title, post) VALUES (new.id, new.title, new.post);
+    END;
+    CREATE TRIGGER post_fts_after_delete AFTER DELETE ON post BEGIN
+        INSERT INTO post_fts (post_fts, rowid, title, post) VALUES ('delete', old.id, old.title, old.post);
+    END;
+    CREATE TRIGGER post_fts_after_update AFTER UPDATE OF title, post ON post BEGIN
+        INSERT INTO post_fts (post_fts, rowid, title, post) VALUES ('delete', old.id, old.title, old.post);
+        INSERT INTO post_fts (rowid, title, post) VALUES (new.id, new.title, new.post);
+    END;
+    INSERT INTO post_fts (post_fts) VALUES ('rebuild');",
+    "ALTER TABLE post ADD COLUMN published INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE post ADD COLUMN format TEXT NOT NULL DEFAULT 'plain';
+    ALTER TABLE post ADD COLUMN postHtml TEXT;
+    ALTER TABLE post_revision ADD COLUMN format TEXT NOT NULL DEFAULT 'plain';",
+    "ALTER TABLE post ADD COLUMN slug TEXT;
+    CREATE UNIQUE INDEX post_slug_idx ON post (slug);
+    CREATE TABLE post_slug_redirect (
+        slug    TEXT PRIMARY KEY,
+        postId  INTEGER NOT NULL REFERENCES post(id) ON DELETE CASCADE
+    );",
+    "ALTER TABLE post ADD COLUMN visibility TEXT NOT NULL DEFAULT 'private';
+    UPDATE post SET visibility='public' WHERE published=1;
+    ALTER TABLE post DROP COLUMN published;
+    CREATE INDEX post_visibility_created_idx ON post (visibility, createdAt);",
+    "ALTER TABLE post ADD COLUMN replyTo TEXT;
+    ALTER TABLE post ADD COLUMN replyCount INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE post ADD COLUMN likeCount INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE post ADD COLUMN repostCount INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE post ADD COLUMN hidden INTEGER NOT NULL DEFAULT 0;",
+    "CREATE TABLE post_attachment (
+        postId          INTEGER NOT NULL REFERENCES post(id) ON DELETE CASCADE,
+        position        INTEGER NOT NULL,
+        attachmentUuid  TEXT NOT NULL,
+        PRIMARY KEY (postId, position)
+    ) WITHOUT ROWID;
+    CREATE INDEX post_attachment_uuid_idx ON post_attachment (attachmentUuid);",
+    "CREATE TABLE poll (
+        postId      INTEGER PRIMARY KEY REFERENCES post(id) ON DELETE CASCADE,
+        multiple    INTEGER NOT NULL,
+        closesAt    INTEGER NOT NULL
+    );
+    CREATE TABLE poll_option (
+        postId      INTEGER NOT NULL REFERENCES poll(postId) ON DELETE CASCADE,
+        position    INTEGER NOT NULL,
+        text        TEXT NOT NULL,
+        PRIMARY KEY (postId, position)
+    ) WITHOUT ROWID;",
+    "CREATE TABLE pinned_post (
+        postId      INTEGER PRIMARY KEY REFERENCES post(id) ON DELETE CASCADE,
+        position    INTEGER NOT NULL
+    );",
+    "CREATE TABLE post_link (
+        postId      INTEGER NOT NULL REFERENCES post(id) ON DELETE CASCADE,
+        position    INTEGER NOT NULL,
+        url         TEXT NOT NULL,
+        PRIMARY KEY (postId, position)
+    ) WITHOUT ROWID;",
+    "UPDATE post SET title = replace(replace(title, char(57344), ''), char(57345), ''),
+            post = replace(replace(post, char(57344), ''), char(57345), '')
+        WHERE instr(title || post, char(57344)) OR instr(title || post, char(57345));
+    UPDATE post_revision SET title = replace(replace(title, char(57344), ''), char(57345), ''),
+            post = replace(replace(post, char(57344), ''), char(57345), '')
+        WHERE instr(title || post, char(57344)) OR instr(title || post, char(57345));",
+];
+
+/// Version which added the `postHtml` column, older databases need their html rendered.
+const POST_HTML_VERSION: usize = 6;
+/// Version which added the `slug` column, older databases need slugs for their posts.
+const POST_SLUG_VERSION: usize = 7;
+/// Version which added the `post_link` table, links of older posts need to be extracted.
+const POST_LINK_VERSION: usize = 15;
+
+const MAX_SLUG_LENGTH: usize = 80;
+
+/// Column weights for bm25 ranking, a match in the title counts more than one in the body.
+const SEARCH_RANK: &str = "bm25(post_fts, 10.0, 1.0)";
+
+const POST_COLUMNS: &str = "post.uuid, post.title, post.post, post.createdAt, post.updatedAt, post.category,
+    (SELECT GROUP_CONCAT(name, ',') FROM
+        (SELECT tag.name FROM post_tag JOIN tag ON tag.id = post_tag.tagId
+            WHERE post_tag.postId = post.id ORDER BY post_tag.rowid)),
+    post.format, IFNULL(post.postHtml, ''), IFNULL(post.slug, ''), post.visibility,
+    post.replyTo, post.replyCount, post.likeCount, post.repostCount, post.hidden,
+    (SELECT GROUP_CONCAT(attachmentUuid, ',') FROM
+        (SELECT attachmentUuid FROM post_attachment WHERE postId = post.id ORDER BY position)),
+    (SELECT multiple FROM poll WHERE postId = post.id), (SELECT closesAt FROM poll WHERE postId = post.id),
+    (SELECT json_group_array(text) FROM
+        (SELECT text FROM poll_option WHERE postId = post.id ORDER BY position)),
+    (SELECT json_group_array(url) FROM
+        (SELECT url FROM post_link WHERE postId = post.id ORDER BY position))";
+
+/// Number of columns in `POST_COLUMNS`, queries selecting more columns read them from here on.
+const POST_COLUMN_COUNT: usize = 21;
+
+const REVISION_COLUMNS: &str = "r.revision, r.title, r.post, r.createdAt, r.format";
+
+/// Lowercases a tag, strips a leading `#` and replaces whitespace with `-`.
+/// Returns `None` when nothing usable is left.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let normalized = tag
+        .trim()
+        .trim_start_matches('#')
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+        .take(50)
+        .collect::<String>();
+
+    if normalized.is_empty() {
+        return None;
+    }
+    return Some(normalized);
+}
+
+/// Normalizes and de-duplicates tags, keeping the order they were given in.
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags.iter().filter_map(|tag| normalize_tag(tag)) {
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    return normalized;
+}
+
+pub fn normalize_category(category: Option<&str>) -> Option<String> {
+    category
+        .map(|category| category.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|category| !category.is_empty())
+}
+
+fn post_from_row(row: &Row) -> rusqlite::Result<Post> {
+    let poll = match row.get::<_, Option<bool>>(17)? {
+        Some(multiple) => Some(Poll {
+            options: serde_json::from_str(&row.get::<_, String>(19)?).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(19, Type::Text, Box::new(err))
+            })?,
+            multiple,
+            closes_at: row.get(18)?,
+        }),
+        None => None,
+    };
+    let links = serde_json::from_str(&row.get::<_, String>(20)?)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(20, Type::Text, Box::new(err)))?;
+    Ok(Post {
+        uuid: row.get(0)?,
+        title: row.get(1)?,
+        post: row.get(2)?,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
+        category: row.get(5)?,
+        tags: row
+            .get::<_, Option<String>>(6)?
+            .map(|tags| tags.split(',').map(str::to_owned).collect())
+            .unwrap_or_default(),
+        format: ContentFormat::from_db(&row.get::<_, String>(7)?),
+        post_html: row.get(8)?,
+        slug: row.get(9)?,
+        visibility: Visibility::from_db(&row.get::<_, String>(10)?),
+        reply_to: row.get(11)?,
+        reply_count: row.get(12)?,
+        like_count: row.get(13)?,
+        repost_count: row.get(14)?,
+        hidden: row.get(15)?,
+        attachments: row
+            .get::<_, Option<String>>(16)?
+            .map(|attachments| attachments.split(',').map(str::to_owned).collect())
+            .unwrap_or_default(),
+        poll,
+        links,
+    })
+}
+
+fn revision_from_row(row: &Row) -> rusqlite::Result<PostRevision> {
+    Ok(PostRevision {
+        revision: row.get(0)?,
+        title: row.get(1)?,
+        post: row.get(2)?,
+        created_at: row.get(3)?,
+        format: ContentFormat::from_db(&row.get::<_, String>(4)?),
+    })
+}
+
+fn db_error(err: rusqlite::Error) -> PostDbError {
+    log::error!("{:?}", err);
+    PostDbError::GenericError
+}
+
+/// Builds a url safe slug from a title, non latin characters are transliterated,
+/// e.g. `Crème brûlée à Paris` becomes `creme-brulee-a-paris`.
+pub fn slugify(title: &str) -> String {
+    let ascii = deunicode(title).to_lowercase();
+    let mut slug = ascii
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.len() > MAX_SLUG_LENGTH {
+        slug.truncate(MAX_SLUG_LENGTH);
+        slug = slug.trim_end_matches('-').to_string();
+    }
+    if slug.is_empty() {
+        return "post".to_string();
+    }
+    return slug;
+}
+
+/// Gives the post a free slug based on its title, adding a `-2`, `-3`... suffix on collisions.
+/// The slug the post had before is kept as a redirect, so existing links keep working.
+fn assign_slug(tx: &Transaction, post_id: i64, title: &str) -> rusqlite::Result<String> {
+    let base = slugify(title);
+    let current: Option<String> =
+        tx.query_row("SELECT slug FROM post WHERE id=?1", [post_id], |row| {
+            row.get(0)
+        })?;
+
+    let mut slug = base.clone();
+    let mut suffix = 1;
+    loop {
+        if current.as_deref() == Some(slug.as_str()) {
+            return Ok(slug);
+        }
+        let taken: bool = tx.query_row(
+            "SELECT EXISTS (SELECT 1 FROM post WHERE slug=?1)
+                OR EXISTS (SELECT 1 FROM post_slug_redirect WHERE slug=?1 AND postId<>?2)",
+            params![slug, post_id],
+            |row| row.get(0),
+        )?;
+        if !taken {
+            break;
+        }
+        suffix += 1;
+        slug = format!("{}-{}", base, suffix);
+    }
+
+    if let Some(current) = current {
+        tx.execute(
+            "INSERT OR REPLACE INTO post_slug_redirect (slug, postId) VALUES (?1, ?2)",
+            params![current, post_id],
+        )?;
+    }
+    // the post may get back a slug it used before
+    tx.execute("DELETE FROM post_slug_redirect WHERE slug=?1", [&slug])?;
+    tx.execute(
+        "UPDATE post SET slug=?1 WHERE id=?2",
+        params![slug, post_id],
+    )?;
+
+    return Ok(slug);
+}
+
+/// `WHERE` clause and its parameters for listing posts.
+fn filter_clause(filter: &PostFilter) -> (String, Vec<String>) {
+    let mut conditions: Vec<String> = Vec::new();
+    let mut values: Vec<String> = Vec::new();
+
+    if !filter.tags.is_empty() {
+        let placeholders = vec!["?"; filter.tags.len()].join(", ");
+        let having = match filter.tag_match {
+            TagMatch::Any => String::new(),
+            TagMatch::All => format!("HAVING COUNT(DISTINCT tag.id) = {}", filter.tags.len()),
+        };
+        conditions.push(format!(
+            "post.id IN (SELECT post_tag.postId FROM post_tag JOIN tag ON tag.id = post_tag.tagId
+                WHERE tag.name IN ({}) GROUP BY post_tag.postId {})",
+            placeholders, having
+        ));
+        values.extend(filter.tags.iter().cloned());
+    }
+    if let Some(category) = &filter.category {
+        conditions.push("post.category = ? COLLATE NOCASE".to_string());
+        values.push(category.clone());
+    }
+    if !filter.visibilities.is_empty() {
+        let placeholders = vec!["?"; filter.visibilities.len()].join(", ");
+        conditions.push(format!("post.visibility IN ({})", placeholders));
+        values.extend(
+            filter
+                .visibilities
+                .iter()
+                .map(|visibility| visibility.as_str().to_string()),
+        );
+    }
+
+    if filter.exclude_hidden {
+        conditions.push("post.hidden = 0".to_string());
+    }
+    if filter.exclude_pinned {
+        conditions.push("post.id NOT IN (SELECT postId FROM pinned_post)".to_string());
+    }
+
+    if conditions.is_empty() {
+        return (String::new(), values);
+    }
+    return (format!("WHERE {}", conditions.join(" AND ")), values);
+}
+
+/// Replaces the tags of a post, they are read back in the order given here.
+fn set_post_tags(tx: &Transaction, post_uuid: &str, tags: &[String]) -> rusqlite::Result<()> {
+    tx.execute(
+        "DELETE FROM post_tag WHERE postId=(SELECT id FROM post WHERE uuid=?1)",
+        [post_uuid],
+    )?;
+    for tag in tags {
+        tx.execute("INSERT OR IGNORE INTO tag (name) VALUES (?1)", [tag])?;
+        tx.execute(
+            "INSERT OR IGNORE INTO post_tag (postId, tagId)
+                SELECT post.id, tag.id FROM post, tag WHERE post.uuid=?1 AND tag.name=?2",
+            params![post_uuid, tag],
+        )?;
+    }
+    // tags which are no longer used by any post
+    tx.execute(
+        "DELETE FROM tag WHERE id NOT IN (SELECT tagId FROM post_tag)",
+        (),
+    )?;
+    return Ok(());
+}
+
+fn add_poll(tx: &Transaction, post_id: i64, poll: &Poll) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT INTO poll (postId, multiple, closesAt) VALUES (?1, ?2, ?3)",
+        params![post_id, poll.multiple, poll.closes_at],
+    )?;
+    for (position, text) in poll.options.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO poll_option (postId, position, text) VALUES (?1, ?2, ?3)",
+            params![post_id, position, text],
+        )?;
+    }
+    return Ok(());
+}
+
+fn set_post_attachments(
+    tx: &Transaction,
+    post_uuid: &str,
+    attachment_uuids: &[String],
+) -> rusqlite::Result<()> {
+    tx.execute(
+        "DELETE FROM post_attachment WHERE postId=(SELECT id FROM post WHERE uuid=?1)",
+        [post_uuid],
+    )?;
+    for (position, attachment_uuid) in attachment_uuids.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO post_attachment (postId, position, attachmentUuid)
+                SELECT id, ?2, ?3 FROM post WHERE uuid=?1",
+            params![post_uuid, position, attachment_uuid],
+        )?;
+    }
+    return Ok(());
+}
+
+/// Stores the urls written in the post, see `extract_urls`.
+fn set_post_links(tx: &Transaction, post_id: i64, post: &str) -> rusqlite::Result<()> {
+    tx.execute("DELETE FROM post_link WHERE postId=?1", [post_id])?;
+    for (position, url) in extract_urls(post).iter().enumerate() {
+        tx.execute(
+            "INSERT INTO post_link (postId, position, url) VALUES (?1, ?2, ?3)",
+            params![post_id, position, url],
+        )?;
+    }
+    return Ok(());
+}
+
+impl PostDbService {
+    pub fn db_file(db_collection_path: &str, user_uuid: &str) -> String {
+        format!("{}/user_{}.db", db_collection_path, user_uuid)
+    }
+
+    pub fn exists(db_collection_path: &str, user_uuid: &str) -> bool {
+        Path::new(&Self::db_file(db_collection_path, user_uuid)).exists()
+    }
+
+    /// Uuids of all users who have a post database.
+    pub fn user_uuids(db_collection_path: &str) -> io::Result<Vec<String>> {
+        let mut uuids: Vec<String> = Vec::new();
+        for entry in fs::read_dir(db_collection_path)? {
+            let file_name = entry?.file_name();
+            let file_name = file_name.to_string_lossy();
+            if let Some(uuid) = file_name
+                .strip_prefix("user_")
+                .and_then(|name| name.strip_suffix(".db"))
+            {
+                uuids.push(uuid.to_owned());
+            }
+        }
+        return Ok(uuids);
+    }
+
+    /// Opens the user's post database, creating and migrating it when needed.
+    pub fn connect(db_collection_path: &str, user_uuid: &str) -> Result<Self, PostDbError> {
+        let user_db_file = Self::db_file(db_collection_path, user_uuid);
+
+        if !Path::new(&user_db_file).exists() {
+            // Only create the file if it doesn't exist
+            if let Err(err) = File::create(&user_db_file) {
+                log::error!("{:?}", err);
+                return Err(PostDbError::GenericError);
+            }
+        }
+
+        let mut conn = Connection::open(&user_db_file).map_err(db_error)?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")
+            .map_err(db_error)?;
+        let previous_version = migrate(&mut conn, MIGRATIONS).map_err(db_error)?;
+
+        let mut post_db_service = Self { conn };
+        if previous_version < POST_HTML_VERSION {
+            post_db_service.render_missing_html().map_err(db_error)?;
+        }
+        if previous_version < POST_SLUG_VERSION {
+            post_db_service.assign_missing_slugs().map_err(db_error)?;
+        }
+        if previous_version < POST_LINK_VERSION {
+            post_db_service.extract_missing_links().map_err(db_error)?;
+        }
+        return Ok(post_db_service);
+    }
+
+    fn assign_missing_slugs(&mut self) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        let posts = tx
+            .prepare("SELECT id, title FROM post WHERE slug IS NULL ORDER BY id")?
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for (id, title) in posts {
+            assign_slug(&tx, id, &title)?;
+        }
+        return tx.commit();
+    }
+
+    fn extract_missing_links(&mut self) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        let posts = tx
+            .prepare("SELECT id, post FROM post")?
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for (id, post) in posts {
+            set_post_links(&tx, id, &post)?;
+        }
+        return tx.commit();
+    }
+
+    fn render_missing_html(&self) -> rusqlite::Result<()> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT id, format, post FROM post WHERE postHtml IS NULL")?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    ContentFormat::from_db(&row.get::<_, String>(1)?),
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for (id, format, post) in rows {
+            self.conn.execute(
+                "UPDATE post SET postHtml=?1 WHERE id=?2",
+                params![render_html(format, &post), id],
+            )?;
+        }
+        return Ok(());
+    }
+
+    /// `reply_to` is the uuid of the post the new post answers, it can't be changed later,
+    /// and neither can the poll.
+    pub fn add_post(
+        &mut self,
+        input: &PostInput,
+        reply_to: Option<&str>,
+        poll: Option<&Poll>,
+    ) -> Result<Post, PostDbError> {
+        let now = Utc::now().timestamp();
+        let post_uuid = Uuid::new_v4().to_string();
+
+        let tx = self.conn.transaction().map_err(db_error)?;
+        tx.execute(
+            "INSERT INTO post (title, post, uuid, createdAt, updatedAt, category, format, postHtml, visibility, replyTo)
+                VALUES (?1, ?2, ?3, ?4, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                input.title,
+                input.post,
+                post_uuid,
+                now,
+                input.category,
+                input.format.as_str(),
+                render_html(input.format, &input.post),
+                input.visibility.as_str(),
+                reply_to
+            ],
+        )
+        .map_err(db_error)?;
+        let post_id = tx.last_insert_rowid();
+        tx.execute(
+            "INSERT INTO post_revision (postId, revision, title, post, createdAt, format) VALUES (?1, 1, ?2, ?3, ?4, ?5)",
+            params![post_id, input.title, input.post, now, input.format.as_str()],
+        )
+        .map_err(db_error)?;
+        assign_slug(&tx, post_id, &input.title).map_err(db_error)?;
+        set_post_tags(&tx, &post_uuid, &input.tags).map_err(db_error)?;
+        set_post_attachments(&tx, &post_uuid, &input.attachments).map_err(db_error)?;
+        set_post_links(&tx, post_id, &input.post).map_err(db_error)?;
+        if let Some(poll) = poll {
+            add_poll(&tx, post_id, poll).map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        return self.get_post(&post_uuid);
+    }
+
+    pub fn get_post(&self, post_uuid: &str) -> Result<Post, PostDbError> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM post WHERE post.uuid=?1 limit 1;",
+                    POST_COLUMNS
+                ),
+                [post_uuid],
+                post_from_row,
+            )
+            .optional()
+            .map_err(db_error)?
+            .ok_or(PostDbError::PostNotFound)
+    }
+
+    pub fn find_post_by_slug(&self, slug: &str) -> Result<SlugLookup, PostDbError> {
+        let post = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM post WHERE post.slug=?1 limit 1;",
+                    POST_COLUMNS
+                ),
+                [slug],
+                post_from_row,
+            )
+            .optional()
+            .map_err(db_error)?;
+        if let Some(post) = post {
+            return Ok(SlugLookup::Post(Box::new(post)));
+        }
+
+        self.conn
+            .query_row(
+                "SELECT post.slug FROM post_slug_redirect
+                    JOIN post ON post.id = post_slug_redirect.postId
+                    WHERE post_slug_redirect.slug=?1 limit 1;",
+                [slug],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?
+            .map(SlugLookup::Redirect)
+            .ok_or(PostDbError::PostNotFound)
+    }
+
+    /// Newest posts first.
+    pub fn get_posts(
+        &self,
+        filter: &PostFilter,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Post>, PostDbError> {
+        let (where_clause, values) = filter_clause(filter);
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM post {} ORDER BY post.createdAt DESC, post.id DESC limit {} offset {};",
+                POST_COLUMNS, where_clause, limit, offset
+            ))
+            .map_err(db_error)?;
+        let posts = statement
+            .query_map(params_from_iter(values.iter()), post_from_row)
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(posts);
+    }
+
+    /// The pinned posts matching the filter, in the order the user pinned them.
+    pub fn get_pinned_posts(&self, filter: &PostFilter) -> Result<Vec<Post>, PostDbError> {
+        let (where_clause, values) = filter_clause(filter);
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM post JOIN pinned_post ON pinned_post.postId = post.id {}
+                    ORDER BY pinned_post.position;",
+                POST_COLUMNS, where_clause
+            ))
+            .map_err(db_error)?;
+        let posts = statement
+            .query_map(params_from_iter(values.iter()), post_from_row)
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(posts);
+    }
+
+    /// Replaces the pinned posts, in the given order. Deleted posts drop out on their own.
+    pub fn set_pinned_posts(&mut self, post_uuids: &[String]) -> Result<(), PostDbError> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        tx.execute("DELETE FROM pinned_post", ())
+            .map_err(db_error)?;
+        for (position, post_uuid) in post_uuids.iter().enumerate() {
+            let inserted = tx
+                .execute(
+                    "INSERT INTO pinned_post (postId, position)
+                        SELECT id, ?2 FROM post WHERE uuid=?1",
+                    params![post_uuid, position],
+                )
+                .map_err(db_error)?;
+            if inserted == 0 {
+                return Err(PostDbError::PostNotFound);
+            }
+        }
+        tx.commit().map_err(db_error)?;
+
+        return Ok(());
+    }
+
+    pub fn count_posts(&self, filter: &PostFilter) -> Result<i64, PostDbError> {
+        let (where_clause, values) = filter_clause(filter);
+        self.conn
+            .query_row(
+                &format!("SELECT COUNT(*) FROM post {}", where_clause),
+                params_from_iter(values.iter()),
+                |row| row.get(0),
+            )
+            .map_err(db_error)
+    }
+
+    /// Replaces the current content of a post. When the title or body changes the new
+    /// content is recorded as a revision, so every prior version stays available.
+    pub fn update_post(&mut self, post_uuid: &str, input: &PostInput) -> Result<Post, PostDbError> {
+        let current = self.get_post(post_uuid)?;
+        let content_changed = current.title != input.title
+            || current.post != input.post
+            || current.format != input.format;
+        if !content_changed
+            && current.visibility == input.visibility
+            && current.category == input.category
+            && current.tags == input.tags
+            && current.attachments == input.attachments
+        {
+            return Ok(current);
+        }
+
+        let now = Utc::now().timestamp();
+        let tx = self.conn.transaction().map_err(db_error)?;
+        tx.execute(
+            "UPDATE post SET title=?1, post=?2, category=?3, updatedAt=?4, format=?5, postHtml=?6, visibility=?7
+                WHERE uuid=?8",
+            params![
+                input.title,
+                input.post,
+                input.category,
+                now,
+                input.format.as_str(),
+                render_html(input.format, &input.post),
+                input.visibility.as_str(),
+                post_uuid
+            ],
+        )
+        .map_err(db_error)?;
+        if content_changed {
+            tx.execute(
+                "INSERT INTO post_revision (postId, revision, title, post, createdAt, format)
+                    SELECT id, (SELECT IFNULL(MAX(revision), 0) + 1 FROM post_revision WHERE postId=post.id), ?1, ?2, ?3, ?4
+                    FROM post WHERE uuid=?5",
+                params![input.title, input.post, now, input.format.as_str(), post_uuid],
+            )
+            .map_err(db_error)?;
+        }
+        let post_id: i64 = tx
+            .query_row("SELECT id FROM post WHERE uuid=?1", [post_uuid], |row| {
+                row.get(0)
+            })
+            .map_err(db_error)?;
+        if current.title != input.title {
+            assign_slug(&tx, post_id, &input.title).map_err(db_error)?;
+        }
+        if current.post != input.post {
+            set_post_links(&tx, post_id, &input.post).map_err(db_error)?;
+        }
+        set_post_tags(&tx, post_uuid, &input.tags).map_err(db_error)?;
+        set_post_attachments(&tx, post_uuid, &input.attachments).map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+
+        return self.get_post(post_uuid);
+    }
+
+    /// Deletes a post together with its revisions and tags.
+    pub fn delete_post(&mut self, post_uuid: &str) -> Result<Post, PostDbError> {
+        let post = self.get_post(post_uuid)?;
+
+        let tx = self.conn.transaction().map_err(db_error)?;
+        tx.execute("DELETE FROM post WHERE uuid=?1", [post_uuid])
+            .map_err(db_error)?;
+        tx.execute(
+            "DELETE FROM tag WHERE id NOT IN (SELECT tagId FROM post_tag)",
+            (),
+        )
+        .map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+
+        return Ok(post);
+    }
+
+    /// Whether any post of the user has the attachment.
+    pub fn is_attachment_used(&self, attachment_uuid: &str) -> Result<bool, PostDbError> {
+        return self
+            .conn
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM post_attachment WHERE attachmentUuid=?1)",
+                [attachment_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error);
+    }
+
+    /// Stores the number of public replies, counted in the social database. Doesn't touch
+    /// `updatedAt`, replies from others don't change the post itself.
+    pub fn set_reply_count(&self, post_uuid: &str, reply_count: i64) -> Result<(), PostDbError> {
+        let updated = self
+            .conn
+            .execute(
+                "UPDATE post SET replyCount=?1 WHERE uuid=?2",
+                params![reply_count, post_uuid],
+            )
+            .map_err(db_error)?;
+        if updated == 0 {
+            return Err(PostDbError::PostNotFound);
+        }
+        return Ok(());
+    }
+
+    /// Hides a post from everyone but its author, or makes it readable again. Editing the post
+    /// doesn't change it, only moderators do.
+    pub fn set_hidden(&self, post_uuid: &str, hidden: bool) -> Result<Post, PostDbError> {
+        let updated = self
+            .conn
+            .execute(
+                "UPDATE post SET hidden=?1 WHERE uuid=?2",
+                params![hidden, post_uuid],
+            )
+            .map_err(db_error)?;
+        if updated == 0 {
+            return Err(PostDbError::PostNotFound);
+        }
+        return self.get_post(post_uuid);
+    }
+
+    /// Stores the number of likes and reposts, counted in the social database. Like the reply
+    /// count it doesn't touch `updatedAt`.
+    pub fn set_engagement_counts(
+        &self,
+        post_uuid: &str,
+        like_count: i64,
+        repost_count: i64,
+    ) -> Result<(), PostDbError> {
+        let updated = self
+            .conn
+            .execute(
+                "UPDATE post SET likeCount=?1, repostCount=?2 WHERE uuid=?3",
+                params![like_count, repost_count, post_uuid],
+            )
+            .map_err(db_error)?;
+        if updated == 0 {
+            return Err(PostDbError::PostNotFound);
+        }
+        return Ok(());
+    }
+
+    pub fn get_tags(&self) -> Result<Vec<NameCount>, PostDbError> {
+        self.name_counts(
+            "SELECT tag.name, COUNT(post_tag.postId) FROM tag
+                JOIN post_tag ON post_tag.tagId = tag.id
+                GROUP BY tag.id ORDER BY COUNT(post_tag.postId) DESC, tag.name ASC;",
+        )
+    }
+
+    pub fn get_categories(&self) -> Result<Vec<NameCount>, PostDbError> {
+        self.name_counts(
+            "SELECT category, COUNT(*) FROM post WHERE category IS NOT NULL
+                GROUP BY category COLLATE NOCASE ORDER BY COUNT(*) DESC, category ASC;",
+        )
+    }
+
+    /// Full-text search, `fts_query` must already be a valid FTS5 match expression.
+    /// Returns one page of hits ordered by rank, and the total number of hits.
+    pub fn search_posts(
+        &self,
+        fts_query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<PostSearchHit>, i64), PostDbError> {
+        let total: i64 = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM post_fts WHERE post_fts MATCH ?1",
+                [fts_query],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT {columns},
+                    highlight(post_fts, 0, ?2, ?3),
+                    snippet(post_fts, 1, ?2, ?3, '…', 32),
+                    {rank}
+                FROM post_fts JOIN post ON post.id = post_fts.rowid
+                WHERE post_fts MATCH ?1
+                ORDER BY {rank} limit ?4 offset ?5;",
+                columns = POST_COLUMNS,
+                rank = SEARCH_RANK
+            ))
+            .map_err(db_error)?;
+        let hits = statement
+            .query_map(
+                params![fts_query, HIGHLIGHT_START, HIGHLIGHT_END, limit, offset],
+                |row| {
+                    Ok(PostSearchHit {
+                        post: post_from_row(row)?,
+                        title_highlight: row.get(POST_COLUMN_COUNT)?,
+                        snippet: row.get(POST_COLUMN_COUNT + 1)?,
+                        rank: row.get(POST_COLUMN_COUNT + 2)?,
+                    })
+                },
+            )
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok((hits, total));
+    }
+
+    /// Rebuilds the full-text index from the `post` table.
+    pub fn reindex(&self) -> Result<(), PostDbError> {
+        self.conn
+            .execute("INSERT INTO post_fts (post_fts) VALUES ('rebuild')", ())
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    fn name_counts(&self, sql: &str) -> Result<Vec<NameCount>, PostDbError> {
+        let mut statement = self.conn.prepare(sql).map_err(db_error)?;
+        let counts = statement
+            .query_map([], |row| {
+                Ok(NameCount {
+                    name: row.get(0)?,
+                    count: row.get(1)?,
+                })
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(counts);
+    }
+
+    /// Lists all revisions of a post, oldest first. The last one matches the current content.
+    pub fn get_revisions(&self, post_uuid: &str) -> Result<Vec<PostRevision>, PostDbError> {
+        self.get_post(post_uuid)?;
+
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM post_revision r
+                        JOIN post p ON p.id = r.postId
+                        WHERE p.uuid=?1 ORDER BY r.revision ASC;",
+                REVISION_COLUMNS
+            ))
+            .map_err(db_error)?;
+        let revisions = statement
+            .query_map([post_uuid], revision_from_row)
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(revisions);
+    }
+
+    pub fn get_revision(
+        &self,
+        post_uuid: &str,
+        revision: i64,
+    ) -> Result<PostRevision, PostDbError> {
+        self.get_post(post_uuid)?;
+
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM post_revision r
+                        JOIN post p ON p.id = r.postId
+                        WHERE p.uuid=?1 AND r.revision=?2 limit 1;",
+                    REVISION_COLUMNS
+                ),
+                params![post_uuid, revision],
+                revision_from_row,
+            )
+            .optional()
+            .map_err(db_error)?
+            .ok_or(PostDbError::RevisionNotFound)
+    }
+
+    /// Makes an old revision the current content again. History is never rewritten,
+    /// the restored content is recorded as a new revision.
+    pub fn restore_revision(
+        &mut self,
+        post_uuid: &str,
+        revision: i64,
+    ) -> Result<Post, PostDbError> {
+        let old = self.get_revision(post_uuid, revision)?;
+        let current = self.get_post(post_uuid)?;
+        return self.update_post(
+            post_uuid,
+            &PostInput {
+                title: old.title,
+                post: old.post,
+                format: old.format,
+                visibility: current.visibility,
+                category: current.category,
+                tags: current.tags,
+                attachments: current.attachments,
+            },
+        );
+    }
+}
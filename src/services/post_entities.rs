@@ -0,0 +1,126 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Characters allowed in a mention or hashtag after the `@` or `#`.
+fn is_entity_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// Collects the words following `marker`, e.g. `@` or `#`. A marker only starts an entity
+/// at the start of the text or after a character which can't be part of a word, so email
+/// addresses and url fragments are skipped. Trailing dots and dashes are punctuation, not
+/// part of the entity. Each entity is returned once, in the order it first appears.
+fn extract_entities(text: &str, marker: char) -> Vec<String> {
+    let mut entities: Vec<String> = Vec::new();
+    let mut previous: Option<char> = None;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let starts_entity = c == marker
+            && previous.is_none_or(|previous| {
+                !is_entity_char(previous) && previous != '/' && previous != marker
+            });
+        previous = Some(c);
+        if !starts_entity {
+            continue;
+        }
+
+        let mut entity = String::new();
+        while let Some(c) = chars.next_if(|c| is_entity_char(*c)) {
+            entity.push(c);
+            previous = Some(c);
+        }
+        let entity = entity.trim_end_matches(['.', '-']);
+        if !entity.is_empty() && !entities.iter().any(|known| known == entity) {
+            entities.push(entity.to_owned());
+        }
+    }
+
+    return entities;
+}
+
+/// Display names mentioned with `@displayName`, not checked against the user database.
+pub fn extract_mentions(text: &str) -> Vec<String> {
+    extract_entities(text, '@')
+}
+
+/// Tags written inline as `#tag`, still to be normalized like any other tag.
+pub fn extract_hashtags(text: &str) -> Vec<String> {
+    extract_entities(text, '#')
+        .into_iter()
+        // `#1` or `#2024` are numbers, not tags
+        .filter(|tag| !tag.chars().all(|c| c.is_ascii_digit()))
+        .collect()
+}
+
+/// Every `http` and `https` url written in the text, in order. A url runs until whitespace
+/// or a character which can't be part of one; closing punctuation at its end and a `)`
+/// without its `(`, as in markdown links, are left out.
+fn find_urls(text: &str) -> Vec<&str> {
+    let mut urls: Vec<&str> = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("http") {
+        let preceded_by_word = rest[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '/');
+        let candidate = &rest[start..];
+        let scheme_length = if candidate.starts_with("https://") {
+            "https://".len()
+        } else if candidate.starts_with("http://") {
+            "http://".len()
+        } else {
+            0
+        };
+        if preceded_by_word || scheme_length == 0 {
+            rest = &candidate["http".len()..];
+            continue;
+        }
+
+        let end = candidate
+            .find(|c: char| c.is_whitespace() || "<>\"'`{}|\\^".contains(c))
+            .unwrap_or(candidate.len());
+        let mut url = &candidate[..end];
+        loop {
+            let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '*', '_', '~']);
+            let trimmed = match trimmed.strip_suffix(')') {
+                Some(without) if trimmed.matches(')').count() > trimmed.matches('(').count() => {
+                    without
+                }
+                _ => trimmed,
+            };
+            if trimmed == url {
+                break;
+            }
+            url = trimmed;
+        }
+        if url.len() > scheme_length {
+            urls.push(url);
+        }
+        rest = &candidate[end.max(scheme_length)..];
+    }
+
+    return urls;
+}
+
+/// `http` and `https` urls written in the text, each returned once in the order it first
+/// appears.
+pub fn extract_urls(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for url in find_urls(text) {
+        if !urls.iter().any(|known| known == url) {
+            urls.push(url.to_owned());
+        }
+    }
+
+    return urls;
+}
+
+/// Length of the text as readers see it, in grapheme clusters. Every url counts as
+/// `url_length` however long it is, clients show them shortened.
+pub fn text_length(text: &str, url_length: usize) -> usize {
+    let urls = find_urls(text);
+    let url_graphemes: usize = urls.iter().map(|url| url.graphemes(true).count()).sum();
+
+    return text.graphemes(true).count().saturating_sub(url_graphemes) + urls.len() * url_length;
+}
@@ -0,0 +1,136 @@
+use std::collections::HashSet;
+
+use ammonia::Builder;
+use pulldown_cmark::{html, Options, Parser};
+
+/// How the source of a post is written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentFormat {
+    Plain,
+    /// CommonMark with the GitHub flavored extensions, tables, strikethrough and task lists
+    Markdown,
+}
+
+impl ContentFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentFormat::Plain => "plain",
+            ContentFormat::Markdown => "markdown",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "markdown" => ContentFormat::Markdown,
+            _ => ContentFormat::Plain,
+        }
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+/// Renders the post source to html which is safe to embed in a page.
+pub fn render_html(format: ContentFormat, source: &str) -> String {
+    match format {
+        ContentFormat::Plain => render_plain(source),
+        ContentFormat::Markdown => render_markdown(source),
+    }
+}
+
+/// Blank lines separate paragraphs, single line breaks are kept.
+fn render_plain(source: &str) -> String {
+    source
+        .replace("\r\n", "\n")
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>", escape_html(paragraph).replace('\n', "<br>\n")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_markdown(source: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_GFM;
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(source, options));
+
+    return sanitizer().clean(&unsafe_html).to_string();
+}
+
+/// Allow-list of the markup a post may contain. Anything else, scripts, styles, event
+/// handlers, iframes or `javascript:` urls, is dropped.
+fn sanitizer() -> Builder<'static> {
+    let tags: HashSet<&str> = [
+        "a",
+        "blockquote",
+        "br",
+        "code",
+        "del",
+        "em",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "hr",
+        "img",
+        "input",
+        "li",
+        "ol",
+        "p",
+        "pre",
+        "strong",
+        "table",
+        "tbody",
+        "td",
+        "th",
+        "thead",
+        "tr",
+        "ul",
+    ]
+    .into_iter()
+    .collect();
+    let url_schemes: HashSet<&str> = ["http", "https", "mailto"].into_iter().collect();
+
+    let mut builder = Builder::default();
+    builder
+        .tags(tags)
+        .url_schemes(url_schemes)
+        .generic_attributes(HashSet::new())
+        .tag_attributes(
+            [
+                ("a", ["href", "title"].into_iter().collect()),
+                ("img", ["src", "alt", "title"].into_iter().collect()),
+                ("ol", ["start"].into_iter().collect()),
+                ("code", ["class"].into_iter().collect()),
+                (
+                    "input",
+                    ["type", "checked", "disabled"].into_iter().collect(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        )
+        .set_tag_attribute_value("input", "type", "checkbox")
+        .set_tag_attribute_value("input", "disabled", "")
+        .link_rel(Some("nofollow noopener noreferrer ugc"));
+    return builder;
+}
@@ -0,0 +1,387 @@
+use rusqlite::{params, params_from_iter, types::Value, Connection, Row};
+
+use super::{
+    db_migrations::migrate,
+    post_db_service::Post,
+    search_query::{HIGHLIGHT_END, HIGHLIGHT_START},
+};
+
+#[derive(Debug)]
+pub enum SearchDbError {
+    GenericError,
+}
+
+/// Site-wide index of public posts, backed by `search.db` inside the db collection folder.
+/// Posts live in a separate database per user, this index is the one place to query all of them.
+#[derive(Debug)]
+pub struct SearchDbService {
+    conn: Connection,
+}
+
+#[derive(Debug, Clone)]
+pub struct PublicPost {
+    pub post_uuid: String,
+    pub author_uuid: String,
+    pub author_display_name: String,
+    pub slug: String,
+    pub title: String,
+    pub post: String,
+    /// sanitized html rendered from `post`, empty for posts indexed before it was stored
+    pub post_html: String,
+    pub tags: Vec<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub reply_to: Option<String>,
+    pub reply_count: i64,
+    pub like_count: i64,
+    pub repost_count: i64,
+    /// uuids of the attached media, in display order
+    pub attachments: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PublicPostHit {
+    pub post: PublicPost,
+    /// title with highlight markers, `None` when searching without a query
+    pub title_highlight: Option<String>,
+    /// fragment of the post body with highlight markers, `None` when searching without a query
+    pub snippet: Option<String>,
+    pub rank: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PublicPostFilter {
+    /// FTS5 match expression, without one the newest posts come first
+    pub fts_query: Option<String>,
+    pub author_uuid: Option<String>,
+    pub author_display_name: Option<String>,
+    pub tag: Option<String>,
+    /// unix timestamp, inclusive
+    pub created_from: Option<i64>,
+    /// unix timestamp, inclusive
+    pub created_to: Option<i64>,
+}
+
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE public_post (
+        id                  INTEGER PRIMARY KEY,
+        postUuid            TEXT NOT NULL UNIQUE,
+        authorUuid          TEXT NOT NULL,
+        authorDisplayName   TEXT NOT NULL,
+        title               TEXT NOT NULL,
+        post                TEXT NOT NULL,
+        createdAt           INTEGER NOT NULL,
+        updatedAt           INTEGER NOT NULL
+    );
+    CREATE INDEX public_post_author_idx ON public_post (authorDisplayName COLLATE NOCASE, createdAt);
+    CREATE INDEX public_post_created_idx ON public_post (createdAt);
+    CREATE TABLE public_post_tag (
+        publicPostId    INTEGER NOT NULL REFERENCES public_post(id) ON DELETE CASCADE,
+        tag             TEXT NOT NULL,
+        PRIMARY KEY (publicPostId, tag)
+    );
+    CREATE INDEX public_post_tag_tag_idx ON public_post_tag (tag);
+    CREATE VIRTUAL TABLE public_post_fts USING fts5(
+        title, post,
+        content='public_post', content_rowid='id',
+        tokenize='unicode61 remove_diacritics 2'
+    );
+    CREATE TRIGGER public_post_fts_after_insert AFTER INSERT ON public_post BEGIN
+        INSERT INTO public_post_fts (rowid, title, post) VALUES (new.id, new.title, new.post);
+    END;
+    CREATE TRIGGER public_post_fts_after_delete AFTER DELETE ON public_post BEGIN
+        INSERT INTO public_post_fts (public_post_fts, rowid, title, post) VALUES ('delete', old.id, old.title, old.post);
+    END;
+    CREATE TRIGGER public_post_fts_after_update AFTER UPDATE OF title, post ON public_post BEGIN
+        INSERT INTO public_post_fts (public_post_fts, rowid, title, post) VALUES ('delete', old.id, old.title, old.post);
+        INSERT INTO public_post_fts (rowid, title, post) VALUES (new.id, new.title, new.post);
+    END;",
+    "ALTER TABLE public_post ADD COLUMN slug TEXT NOT NULL DEFAULT '';
+    ALTER TABLE public_post ADD COLUMN postHtml TEXT NOT NULL DEFAULT '';",
+    "ALTER TABLE public_post ADD COLUMN replyTo TEXT;
+    ALTER TABLE public_post ADD COLUMN replyCount INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE public_post ADD COLUMN likeCount INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE public_post ADD COLUMN repostCount INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE public_post ADD COLUMN attachments TEXT NOT NULL DEFAULT '';",
+    "UPDATE public_post SET title = replace(replace(title, char(57344), ''), char(57345), ''),
+            post = replace(replace(post, char(57344), ''), char(57345), '')
+        WHERE instr(title || post, char(57344)) OR instr(title || post, char(57345));",
+];
+
+const PUBLIC_POST_COLUMNS: &str = "public_post.postUuid, public_post.authorUuid,
+    public_post.authorDisplayName, public_post.title, public_post.post,
+    public_post.createdAt, public_post.updatedAt,
+    (SELECT GROUP_CONCAT(tag, ',') FROM
+        (SELECT tag FROM public_post_tag WHERE publicPostId = public_post.id ORDER BY rowid)),
+    public_post.slug, public_post.postHtml, public_post.replyTo, public_post.replyCount,
+    public_post.likeCount, public_post.repostCount, public_post.attachments";
+/// Number of columns in `PUBLIC_POST_COLUMNS`, queries selecting more columns read them from
+/// here on.
+const PUBLIC_POST_COLUMN_COUNT: usize = 15;
+
+/// Column weights for bm25 ranking, a match in the title counts more than one in the body.
+const SEARCH_RANK: &str = "bm25(public_post_fts, 10.0, 1.0)";
+
+fn public_post_from_row(row: &Row) -> rusqlite::Result<PublicPost> {
+    Ok(PublicPost {
+        post_uuid: row.get(0)?,
+        author_uuid: row.get(1)?,
+        author_display_name: row.get(2)?,
+        title: row.get(3)?,
+        post: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        tags: row
+            .get::<_, Option<String>>(7)?
+            .map(|tags| tags.split(',').map(str::to_owned).collect())
+            .unwrap_or_default(),
+        slug: row.get(8)?,
+        post_html: row.get(9)?,
+        reply_to: row.get(10)?,
+        reply_count: row.get(11)?,
+        like_count: row.get(12)?,
+        repost_count: row.get(13)?,
+        attachments: row
+            .get::<_, String>(14)?
+            .split(',')
+            .filter(|uuid| !uuid.is_empty())
+            .map(str::to_owned)
+            .collect(),
+    })
+}
+
+fn db_error(err: rusqlite::Error) -> SearchDbError {
+    log::error!("{:?}", err);
+    SearchDbError::GenericError
+}
+
+impl SearchDbService {
+    pub fn connect(db_collection_path: &str) -> Result<Self, SearchDbError> {
+        let mut conn =
+            Connection::open(format!("{}/search.db", db_collection_path)).map_err(db_error)?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")
+            .map_err(db_error)?;
+        migrate(&mut conn, MIGRATIONS).map_err(db_error)?;
+
+        return Ok(Self { conn });
+    }
+
+    /// Adds a post to the index, or refreshes it when it's already there.
+    pub fn index_post(
+        &mut self,
+        author_uuid: &str,
+        author_display_name: &str,
+        post: &Post,
+    ) -> Result<(), SearchDbError> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        tx.execute(
+            "INSERT INTO public_post (postUuid, authorUuid, authorDisplayName, title, post, createdAt, updatedAt, slug, postHtml, replyTo, replyCount, likeCount, repostCount, attachments)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+                ON CONFLICT (postUuid) DO UPDATE SET
+                    authorDisplayName=excluded.authorDisplayName, title=excluded.title,
+                    post=excluded.post, updatedAt=excluded.updatedAt,
+                    slug=excluded.slug, postHtml=excluded.postHtml, replyCount=excluded.replyCount,
+                    likeCount=excluded.likeCount, repostCount=excluded.repostCount,
+                    attachments=excluded.attachments",
+            params![
+                post.uuid,
+                author_uuid,
+                author_display_name,
+                post.title,
+                post.post,
+                post.created_at,
+                post.updated_at,
+                post.slug,
+                post.post_html,
+                post.reply_to,
+                post.reply_count,
+                post.like_count,
+                post.repost_count,
+                post.attachments.join(",")
+            ],
+        )
+        .map_err(db_error)?;
+        tx.execute(
+            "DELETE FROM public_post_tag WHERE publicPostId=(SELECT id FROM public_post WHERE postUuid=?1)",
+            [&post.uuid],
+        )
+        .map_err(db_error)?;
+        for tag in &post.tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO public_post_tag (publicPostId, tag)
+                    SELECT id, ?2 FROM public_post WHERE postUuid=?1",
+                params![post.uuid, tag],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        return Ok(());
+    }
+
+    pub fn set_reply_count(&self, post_uuid: &str, reply_count: i64) -> Result<(), SearchDbError> {
+        self.conn
+            .execute(
+                "UPDATE public_post SET replyCount=?1 WHERE postUuid=?2",
+                params![reply_count, post_uuid],
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    pub fn set_engagement_counts(
+        &self,
+        post_uuid: &str,
+        like_count: i64,
+        repost_count: i64,
+    ) -> Result<(), SearchDbError> {
+        self.conn
+            .execute(
+                "UPDATE public_post SET likeCount=?1, repostCount=?2 WHERE postUuid=?3",
+                params![like_count, repost_count, post_uuid],
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    pub fn remove_post(&self, post_uuid: &str) -> Result<(), SearchDbError> {
+        self.conn
+            .execute("DELETE FROM public_post WHERE postUuid=?1", [post_uuid])
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    /// Drops every indexed post, used before rebuilding the index from the user databases.
+    pub fn clear(&self) -> Result<(), SearchDbError> {
+        self.conn
+            .execute_batch(
+                "DELETE FROM public_post;
+                INSERT INTO public_post_fts (public_post_fts) VALUES ('rebuild');",
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    /// Returns one page of matching posts, and the total number of matches.
+    /// With a query the best matches come first, otherwise the newest posts.
+    pub fn search(
+        &self,
+        filter: &PublicPostFilter,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<PublicPostHit>, i64), SearchDbError> {
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(fts_query) = &filter.fts_query {
+            conditions.push("public_post_fts MATCH ?");
+            values.push(Value::from(fts_query.clone()));
+        }
+        if let Some(author_uuid) = &filter.author_uuid {
+            conditions.push("public_post.authorUuid = ?");
+            values.push(Value::from(author_uuid.clone()));
+        }
+        if let Some(author_display_name) = &filter.author_display_name {
+            conditions.push("public_post.authorDisplayName = ? COLLATE NOCASE");
+            values.push(Value::from(author_display_name.clone()));
+        }
+        if let Some(tag) = &filter.tag {
+            conditions
+                .push("public_post.id IN (SELECT publicPostId FROM public_post_tag WHERE tag = ?)");
+            values.push(Value::from(tag.clone()));
+        }
+        if let Some(created_from) = filter.created_from {
+            conditions.push("public_post.createdAt >= ?");
+            values.push(Value::from(created_from));
+        }
+        if let Some(created_to) = filter.created_to {
+            conditions.push("public_post.createdAt <= ?");
+            values.push(Value::from(created_to));
+        }
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let (from_clause, extra_columns, order_by) = if filter.fts_query.is_some() {
+            (
+                "public_post_fts JOIN public_post ON public_post.id = public_post_fts.rowid",
+                format!(
+                    "highlight(public_post_fts, 0, '{start}', '{end}'),
+                    snippet(public_post_fts, 1, '{start}', '{end}', '…', 32), {rank}",
+                    start = HIGHLIGHT_START,
+                    end = HIGHLIGHT_END,
+                    rank = SEARCH_RANK
+                ),
+                SEARCH_RANK,
+            )
+        } else {
+            (
+                "public_post",
+                "NULL, NULL, NULL".to_string(),
+                "public_post.createdAt DESC",
+            )
+        };
+
+        let total: i64 = self
+            .conn
+            .query_row(
+                &format!("SELECT COUNT(*) FROM {} {}", from_clause, where_clause),
+                params_from_iter(values.iter()),
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT {}, {} FROM {} {} ORDER BY {} limit {} offset {};",
+                PUBLIC_POST_COLUMNS,
+                extra_columns,
+                from_clause,
+                where_clause,
+                order_by,
+                limit,
+                offset
+            ))
+            .map_err(db_error)?;
+        let hits = statement
+            .query_map(params_from_iter(values.iter()), |row| {
+                Ok(PublicPostHit {
+                    post: public_post_from_row(row)?,
+                    title_highlight: row.get(PUBLIC_POST_COLUMN_COUNT)?,
+                    snippet: row.get(PUBLIC_POST_COLUMN_COUNT + 1)?,
+                    rank: row.get(PUBLIC_POST_COLUMN_COUNT + 2)?,
+                })
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok((hits, total));
+    }
+
+    /// Looks up indexed posts by their uuids, posts which aren't indexed are left out.
+    pub fn get_posts(&self, post_uuids: &[String]) -> Result<Vec<PublicPost>, SearchDbError> {
+        if post_uuids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = vec!["?"; post_uuids.len()].join(", ");
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM public_post WHERE postUuid IN ({})",
+                PUBLIC_POST_COLUMNS, placeholders
+            ))
+            .map_err(db_error)?;
+        let posts = statement
+            .query_map(params_from_iter(post_uuids.iter()), public_post_from_row)
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(posts);
+    }
+}
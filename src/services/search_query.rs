@@ -0,0 +1,87 @@
+use super::post_renderer::escape_html;
+
+/// Markers placed around matched terms by the FTS5 `snippet()` and `highlight()` functions.
+/// They are removed from titles and posts when those are written, so the text can be escaped
+/// safely before the markers are turned into `<mark>` tags.
+pub const HIGHLIGHT_START: &str = "\u{E000}";
+pub const HIGHLIGHT_END: &str = "\u{E001}";
+
+/// Removes the highlight markers from text written by a user, only FTS5 may place them.
+pub fn strip_highlight_markers(text: &str) -> String {
+    return text.replace(HIGHLIGHT_START, "").replace(HIGHLIGHT_END, "");
+}
+
+/// Turns a user search query into an FTS5 match expression.
+///
+/// Supported syntax:
+/// - `word` matches the word, all terms must match
+/// - `"some phrase"` matches the words next to each other
+/// - `pre*` matches every word starting with `pre`, also works on phrases
+/// - `OR` between two terms matches either of them
+///
+/// Everything else is quoted, so FTS5 operators and column filters can't be injected.
+/// Returns `None` when the query has no searchable terms.
+pub fn to_fts_query(query: &str) -> Option<String> {
+    let mut terms: Vec<String> = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        let mut text = String::new();
+        let quoted = c == '"';
+        if quoted {
+            for c in chars.by_ref() {
+                if c == '"' {
+                    break;
+                }
+                text.push(c);
+            }
+        } else {
+            text.push(c);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+                text.push(c);
+            }
+        }
+
+        let mut prefix = false;
+        if quoted {
+            prefix = chars.next_if_eq(&'*').is_some();
+        } else if text.ends_with('*') {
+            prefix = true;
+            text = text.trim_end_matches('*').to_string();
+        }
+
+        if !quoted && text == "OR" {
+            if terms.last().is_some_and(|term| term != "OR") {
+                terms.push(text);
+            }
+            continue;
+        }
+
+        // FTS5 only looks at letters and digits, terms without any can't match
+        if !text.chars().any(char::is_alphanumeric) {
+            continue;
+        }
+
+        let term = format!("\"{}\"", text.replace('"', "\"\""));
+        terms.push(if prefix { format!("{}*", term) } else { term });
+    }
+
+    if terms.last().is_some_and(|term| term == "OR") {
+        terms.pop();
+    }
+    if terms.is_empty() {
+        return None;
+    }
+    return Some(terms.join(" "));
+}
+
+/// Escapes a snippet returned by FTS5 and wraps the matched terms in `<mark>` tags.
+pub fn highlight_to_html(text: &str) -> String {
+    escape_html(text)
+        .replace(HIGHLIGHT_START, "<mark>")
+        .replace(HIGHLIGHT_END, "</mark>")
+}
@@ -0,0 +1,1427 @@
+use chrono::Utc;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+
+use super::db_migrations::migrate;
+
+#[derive(Debug)]
+pub enum SocialDbError {
+    GenericError,
+}
+
+/// Relations between users, backed by `social.db` inside the db collection folder.
+/// Holds the follow graph, the home timelines built from it, the reply links between
+/// posts, which can belong to different user databases, likes, reposts and bookmarks,
+/// mentions, the notifications sent for all of them, the event log streamed to clients, the
+/// blocks and mutes between users, and the votes on polls.
+#[derive(Debug)]
+pub struct SocialDbService {
+    conn: Connection,
+}
+
+/// One side of a follow relation, `user_uuid` is the follower or the followed user
+/// depending on the list it comes from.
+#[derive(Debug, Clone)]
+pub struct FollowEntry {
+    pub user_uuid: String,
+    pub created_at: i64,
+}
+
+/// A public post as it is referenced from home timelines.
+#[derive(Debug, Clone)]
+pub struct TimelinePost {
+    pub post_uuid: String,
+    pub author_uuid: String,
+    pub created_at: i64,
+}
+
+/// Links a reply to the post it answers.
+#[derive(Debug, Clone)]
+pub struct ReplyLink {
+    pub post_uuid: String,
+    pub author_uuid: String,
+    pub parent_uuid: String,
+    pub parent_author_uuid: String,
+    pub created_at: i64,
+}
+
+/// A reply found below a post, `depth` is 1 for direct replies.
+#[derive(Debug, Clone)]
+pub struct ThreadReply {
+    pub post_uuid: String,
+    pub parent_uuid: String,
+    pub depth: u32,
+}
+
+/// A user who liked a post.
+#[derive(Debug, Clone)]
+pub struct LikeEntry {
+    pub user_uuid: String,
+    pub created_at: i64,
+}
+
+/// A user who reposted a post, optionally with a quote of their own.
+#[derive(Debug, Clone)]
+pub struct RepostEntry {
+    pub user_uuid: String,
+    pub quote: Option<String>,
+    pub created_at: i64,
+}
+
+/// How one user engaged with a post.
+#[derive(Debug, Clone, Default)]
+pub struct EngagementState {
+    pub liked: bool,
+    pub reposted: bool,
+    pub bookmarked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationKind {
+    Mention,
+    Reply,
+    Follow,
+    Like,
+    /// a moderator acted on a report about the recipient or one of their posts
+    Moderation,
+}
+
+impl NotificationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::Mention => "mention",
+            NotificationKind::Reply => "reply",
+            NotificationKind::Follow => "follow",
+            NotificationKind::Like => "like",
+            NotificationKind::Moderation => "moderation",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "mention" => NotificationKind::Mention,
+            "reply" => NotificationKind::Reply,
+            "follow" => NotificationKind::Follow,
+            "moderation" => NotificationKind::Moderation,
+            _ => NotificationKind::Like,
+        }
+    }
+}
+
+/// Something `actor_uuid` did which concerns the recipient, `post_uuid` is the post with
+/// the mention, the reply or the liked post, and `None` for new followers.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub id: i64,
+    pub kind: NotificationKind,
+    pub actor_uuid: String,
+    pub post_uuid: Option<String>,
+    pub created_at: i64,
+    pub read: bool,
+    /// text written by a moderator, only set for moderation notices
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamEventKind {
+    /// a followed user published a post
+    Post,
+    Notification,
+    /// one of the own posts was edited or restored, e.g. from another device
+    PostUpdated,
+    PostDeleted,
+    /// a direct message in one of the conversations of the user
+    Message,
+}
+
+impl StreamEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StreamEventKind::Post => "post",
+            StreamEventKind::Notification => "notification",
+            StreamEventKind::PostUpdated => "post-updated",
+            StreamEventKind::PostDeleted => "post-deleted",
+            StreamEventKind::Message => "message",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "post" => StreamEventKind::Post,
+            "notification" => StreamEventKind::Notification,
+            "post-updated" => StreamEventKind::PostUpdated,
+            "message" => StreamEventKind::Message,
+            _ => StreamEventKind::PostDeleted,
+        }
+    }
+}
+
+/// An entry of the event log of one user, `data` is the JSON payload sent to the client.
+#[derive(Debug, Clone)]
+pub struct StreamEvent {
+    pub id: i64,
+    pub kind: StreamEventKind,
+    pub data: String,
+}
+
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE follow (
+        followerUuid    TEXT NOT NULL,
+        followeeUuid    TEXT NOT NULL,
+        createdAt       INTEGER NOT NULL,
+        PRIMARY KEY (followerUuid, followeeUuid)
+    ) WITHOUT ROWID;
+    CREATE INDEX follow_followee_idx ON follow (followeeUuid, createdAt);
+    CREATE TABLE timeline (
+        ownerUuid   TEXT NOT NULL,
+        postUuid    TEXT NOT NULL,
+        authorUuid  TEXT NOT NULL,
+        createdAt   INTEGER NOT NULL,
+        PRIMARY KEY (ownerUuid, postUuid)
+    ) WITHOUT ROWID;
+    CREATE INDEX timeline_owner_created_idx ON timeline (ownerUuid, createdAt);
+    CREATE INDEX timeline_post_idx ON timeline (postUuid);",
+    "CREATE TABLE reply (
+        postUuid        TEXT PRIMARY KEY,
+        authorUuid      TEXT NOT NULL,
+        parentUuid      TEXT NOT NULL,
+        parentAuthorUuid TEXT NOT NULL,
+        createdAt       INTEGER NOT NULL,
+        public          INTEGER NOT NULL
+    ) WITHOUT ROWID;
+    CREATE INDEX reply_parent_idx ON reply (parentUuid, createdAt);",
+    "CREATE TABLE post_like (
+        postUuid        TEXT NOT NULL,
+        userUuid        TEXT NOT NULL,
+        postAuthorUuid  TEXT NOT NULL,
+        createdAt       INTEGER NOT NULL,
+        PRIMARY KEY (postUuid, userUuid)
+    ) WITHOUT ROWID;
+    CREATE INDEX post_like_post_created_idx ON post_like (postUuid, createdAt);
+    CREATE TABLE repost (
+        postUuid        TEXT NOT NULL,
+        userUuid        TEXT NOT NULL,
+        postAuthorUuid  TEXT NOT NULL,
+        quote           TEXT,
+        createdAt       INTEGER NOT NULL,
+        PRIMARY KEY (postUuid, userUuid)
+    ) WITHOUT ROWID;
+    CREATE INDEX repost_post_created_idx ON repost (postUuid, createdAt);
+    CREATE TABLE bookmark (
+        userUuid    TEXT NOT NULL,
+        postUuid    TEXT NOT NULL,
+        createdAt   INTEGER NOT NULL,
+        PRIMARY KEY (userUuid, postUuid)
+    ) WITHOUT ROWID;
+    CREATE INDEX bookmark_user_created_idx ON bookmark (userUuid, createdAt);
+    CREATE INDEX bookmark_post_idx ON bookmark (postUuid);",
+    "CREATE TABLE mention (
+        postUuid    TEXT NOT NULL,
+        userUuid    TEXT NOT NULL,
+        PRIMARY KEY (postUuid, userUuid)
+    ) WITHOUT ROWID;
+    CREATE TABLE notification (
+        id              INTEGER PRIMARY KEY AUTOINCREMENT,
+        recipientUuid   TEXT NOT NULL,
+        kind            TEXT NOT NULL,
+        actorUuid       TEXT NOT NULL,
+        postUuid        TEXT,
+        createdAt       INTEGER NOT NULL,
+        read            INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE UNIQUE INDEX notification_event_idx
+        ON notification (recipientUuid, kind, actorUuid, IFNULL(postUuid, ''));
+    CREATE INDEX notification_recipient_idx ON notification (recipientUuid, read, id);
+    CREATE INDEX notification_post_idx ON notification (postUuid);",
+    "CREATE TABLE stream_event (
+        id              INTEGER PRIMARY KEY AUTOINCREMENT,
+        recipientUuid   TEXT NOT NULL,
+        kind            TEXT NOT NULL,
+        data            TEXT NOT NULL,
+        createdAt       INTEGER NOT NULL
+    );
+    CREATE INDEX stream_event_recipient_idx ON stream_event (recipientUuid, id);
+    CREATE INDEX stream_event_created_idx ON stream_event (createdAt);",
+    "CREATE TABLE block (
+        blockerUuid TEXT NOT NULL,
+        blockedUuid TEXT NOT NULL,
+        createdAt   INTEGER NOT NULL,
+        PRIMARY KEY (blockerUuid, blockedUuid)
+    ) WITHOUT ROWID;
+    CREATE INDEX block_blocked_idx ON block (blockedUuid);
+    CREATE TABLE mute (
+        muterUuid   TEXT NOT NULL,
+        mutedUuid   TEXT NOT NULL,
+        createdAt   INTEGER NOT NULL,
+        PRIMARY KEY (muterUuid, mutedUuid)
+    ) WITHOUT ROWID;",
+    "ALTER TABLE notification ADD COLUMN message TEXT;
+    DROP INDEX notification_event_idx;
+    CREATE UNIQUE INDEX notification_event_idx
+        ON notification (recipientUuid, kind, actorUuid, IFNULL(postUuid, ''))
+        WHERE kind != 'moderation';",
+    "CREATE TABLE poll (
+        postUuid    TEXT PRIMARY KEY,
+        authorUuid  TEXT NOT NULL
+    ) WITHOUT ROWID;
+    CREATE TABLE poll_vote (
+        postUuid    TEXT NOT NULL,
+        userUuid    TEXT NOT NULL,
+        choice      INTEGER NOT NULL,
+        createdAt   INTEGER NOT NULL,
+        PRIMARY KEY (postUuid, userUuid, choice)
+    ) WITHOUT ROWID;",
+];
+
+/// Users hidden from the user bound to `?1`: users blocked by them, users blocking them
+/// and users they muted.
+const HIDDEN_USERS: &str = "SELECT blockedUuid FROM block WHERE blockerUuid=?1
+    UNION ALL SELECT blockerUuid FROM block WHERE blockedUuid=?1
+    UNION ALL SELECT mutedUuid FROM mute WHERE muterUuid=?1";
+
+/// Events older than this are dropped from the log, clients away for longer miss them.
+const STREAM_EVENT_RETENTION_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+fn db_error(err: rusqlite::Error) -> SocialDbError {
+    log::error!("{:?}", err);
+    SocialDbError::GenericError
+}
+
+impl SocialDbService {
+    pub fn connect(db_collection_path: &str) -> Result<Self, SocialDbError> {
+        let mut conn =
+            Connection::open(format!("{}/social.db", db_collection_path)).map_err(db_error)?;
+        migrate(&mut conn, MIGRATIONS).map_err(db_error)?;
+
+        return Ok(Self { conn });
+    }
+
+    /// Returns `false` when `follower_uuid` already followed `followee_uuid`.
+    pub fn follow(&self, follower_uuid: &str, followee_uuid: &str) -> Result<bool, SocialDbError> {
+        let inserted = self
+            .conn
+            .execute(
+                "INSERT OR IGNORE INTO follow (followerUuid, followeeUuid, createdAt)
+                    VALUES (?1, ?2, ?3)",
+                params![follower_uuid, followee_uuid, Utc::now().timestamp()],
+            )
+            .map_err(db_error)?;
+        return Ok(inserted > 0);
+    }
+
+    /// Removes the follow relation and the posts of the unfollowed user from the home
+    /// timeline of the follower.
+    pub fn unfollow(
+        &mut self,
+        follower_uuid: &str,
+        followee_uuid: &str,
+    ) -> Result<(), SocialDbError> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        tx.execute(
+            "DELETE FROM follow WHERE followerUuid=?1 AND followeeUuid=?2",
+            params![follower_uuid, followee_uuid],
+        )
+        .map_err(db_error)?;
+        tx.execute(
+            "DELETE FROM timeline WHERE ownerUuid=?1 AND authorUuid=?2",
+            params![follower_uuid, followee_uuid],
+        )
+        .map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+
+        return Ok(());
+    }
+
+    /// Returns the number of followers and the number of followed users.
+    pub fn follow_counts(&self, user_uuid: &str) -> Result<(i64, i64), SocialDbError> {
+        return self
+            .conn
+            .query_row(
+                "SELECT
+                    (SELECT COUNT(*) FROM follow WHERE followeeUuid=?1),
+                    (SELECT COUNT(*) FROM follow WHERE followerUuid=?1)",
+                [user_uuid],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(db_error);
+    }
+
+    /// Users following `user_uuid`, the most recent followers first.
+    pub fn get_followers(
+        &self,
+        user_uuid: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<FollowEntry>, SocialDbError> {
+        return self.follow_entries(
+            "SELECT followerUuid, createdAt FROM follow WHERE followeeUuid=?1
+                ORDER BY createdAt DESC, followerUuid limit ?2 offset ?3",
+            user_uuid,
+            limit,
+            offset,
+        );
+    }
+
+    /// Users `user_uuid` follows, the most recently followed first.
+    pub fn get_following(
+        &self,
+        user_uuid: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<FollowEntry>, SocialDbError> {
+        return self.follow_entries(
+            "SELECT followeeUuid, createdAt FROM follow WHERE followerUuid=?1
+                ORDER BY createdAt DESC, followeeUuid limit ?2 offset ?3",
+            user_uuid,
+            limit,
+            offset,
+        );
+    }
+
+    fn follow_entries(
+        &self,
+        sql: &str,
+        user_uuid: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<FollowEntry>, SocialDbError> {
+        let mut statement = self.conn.prepare(sql).map_err(db_error)?;
+        let entries = statement
+            .query_map(params![user_uuid, limit, offset], |row| {
+                Ok(FollowEntry {
+                    user_uuid: row.get(0)?,
+                    created_at: row.get(1)?,
+                })
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+        return Ok(entries);
+    }
+
+    /// Every follower of a user, used to address events about the posts of the user.
+    pub fn get_follower_uuids(&self, user_uuid: &str) -> Result<Vec<String>, SocialDbError> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT followerUuid FROM follow WHERE followeeUuid=?1")
+            .map_err(db_error)?;
+        let follower_uuids = statement
+            .query_map([user_uuid], |row| row.get(0))
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .map_err(db_error)?;
+        return Ok(follower_uuids);
+    }
+
+    /// Blocks a user, which also ends the follow relations between the two users in both
+    /// directions. Returns `false` when `blocked_uuid` was already blocked.
+    pub fn block(&mut self, blocker_uuid: &str, blocked_uuid: &str) -> Result<bool, SocialDbError> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        let inserted = tx
+            .execute(
+                "INSERT OR IGNORE INTO block (blockerUuid, blockedUuid, createdAt)
+                    VALUES (?1, ?2, ?3)",
+                params![blocker_uuid, blocked_uuid, Utc::now().timestamp()],
+            )
+            .map_err(db_error)?;
+        tx.execute(
+            "DELETE FROM follow WHERE (followerUuid=?1 AND followeeUuid=?2)
+                OR (followerUuid=?2 AND followeeUuid=?1)",
+            params![blocker_uuid, blocked_uuid],
+        )
+        .map_err(db_error)?;
+        tx.execute(
+            "DELETE FROM timeline WHERE (ownerUuid=?1 AND authorUuid=?2)
+                OR (ownerUuid=?2 AND authorUuid=?1)",
+            params![blocker_uuid, blocked_uuid],
+        )
+        .map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+
+        return Ok(inserted > 0);
+    }
+
+    /// Lifting a block doesn't restore the follow relations it ended.
+    pub fn unblock(&self, blocker_uuid: &str, blocked_uuid: &str) -> Result<(), SocialDbError> {
+        self.conn
+            .execute(
+                "DELETE FROM block WHERE blockerUuid=?1 AND blockedUuid=?2",
+                params![blocker_uuid, blocked_uuid],
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    /// Returns `false` when `muted_uuid` was already muted.
+    pub fn mute(&self, muter_uuid: &str, muted_uuid: &str) -> Result<bool, SocialDbError> {
+        let inserted = self
+            .conn
+            .execute(
+                "INSERT OR IGNORE INTO mute (muterUuid, mutedUuid, createdAt) VALUES (?1, ?2, ?3)",
+                params![muter_uuid, muted_uuid, Utc::now().timestamp()],
+            )
+            .map_err(db_error)?;
+        return Ok(inserted > 0);
+    }
+
+    pub fn unmute(&self, muter_uuid: &str, muted_uuid: &str) -> Result<(), SocialDbError> {
+        self.conn
+            .execute(
+                "DELETE FROM mute WHERE muterUuid=?1 AND mutedUuid=?2",
+                params![muter_uuid, muted_uuid],
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    /// Users blocked by `user_uuid`, the most recently blocked first, and the total count.
+    pub fn get_blocks(
+        &self,
+        user_uuid: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<FollowEntry>, i64), SocialDbError> {
+        let total: i64 = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM block WHERE blockerUuid=?1",
+                [user_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+        let entries = self.follow_entries(
+            "SELECT blockedUuid, createdAt FROM block WHERE blockerUuid=?1
+                ORDER BY createdAt DESC, blockedUuid limit ?2 offset ?3",
+            user_uuid,
+            limit,
+            offset,
+        )?;
+        return Ok((entries, total));
+    }
+
+    /// Users muted by `user_uuid`, the most recently muted first, and the total count.
+    pub fn get_mutes(
+        &self,
+        user_uuid: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<FollowEntry>, i64), SocialDbError> {
+        let total: i64 = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM mute WHERE muterUuid=?1",
+                [user_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+        let entries = self.follow_entries(
+            "SELECT mutedUuid, createdAt FROM mute WHERE muterUuid=?1
+                ORDER BY createdAt DESC, mutedUuid limit ?2 offset ?3",
+            user_uuid,
+            limit,
+            offset,
+        )?;
+        return Ok((entries, total));
+    }
+
+    /// Whether either user blocked the other one, blocks hide the users from each other.
+    pub fn is_blocked(&self, user_uuid: &str, other_uuid: &str) -> Result<bool, SocialDbError> {
+        return self
+            .conn
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM block WHERE (blockerUuid=?1 AND blockedUuid=?2)
+                    OR (blockerUuid=?2 AND blockedUuid=?1))",
+                params![user_uuid, other_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error);
+    }
+
+    /// Whether `user_uuid` doesn't want to hear from `other_uuid`, because of a block in
+    /// either direction or because `user_uuid` muted them.
+    pub fn is_hidden(&self, user_uuid: &str, other_uuid: &str) -> Result<bool, SocialDbError> {
+        return self
+            .conn
+            .query_row(
+                &format!("SELECT ?2 IN ({})", HIDDEN_USERS),
+                params![user_uuid, other_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error);
+    }
+
+    /// Every user blocked by `user_uuid` or blocking them, used to filter lists built from
+    /// other databases.
+    pub fn get_blocked_uuids(&self, user_uuid: &str) -> Result<Vec<String>, SocialDbError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT blockedUuid FROM block WHERE blockerUuid=?1
+                    UNION SELECT blockerUuid FROM block WHERE blockedUuid=?1",
+            )
+            .map_err(db_error)?;
+        let blocked_uuids = statement
+            .query_map([user_uuid], |row| row.get(0))
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .map_err(db_error)?;
+        return Ok(blocked_uuids);
+    }
+
+    /// Fan-out on write, puts a public post on the home timeline of its author and of
+    /// everyone following the author. Reading a timeline then never has to look into the
+    /// databases of the followed users.
+    pub fn fan_out_post(&self, post: &TimelinePost) -> Result<(), SocialDbError> {
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO timeline (ownerUuid, postUuid, authorUuid, createdAt)
+                    SELECT ?2, ?1, ?2, ?3
+                    UNION ALL
+                    SELECT followerUuid, ?1, ?2, ?3 FROM follow WHERE followeeUuid=?2",
+                params![post.post_uuid, post.author_uuid, post.created_at],
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    /// Adds existing posts to one home timeline, used when a user starts following someone.
+    pub fn add_to_timeline(
+        &mut self,
+        owner_uuid: &str,
+        posts: &[TimelinePost],
+    ) -> Result<(), SocialDbError> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        for post in posts {
+            tx.execute(
+                "INSERT OR IGNORE INTO timeline (ownerUuid, postUuid, authorUuid, createdAt)
+                    VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    owner_uuid,
+                    post.post_uuid,
+                    post.author_uuid,
+                    post.created_at
+                ],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        return Ok(());
+    }
+
+    /// Takes a post off every home timeline, after it was deleted or stopped being public.
+    pub fn remove_from_timelines(&self, post_uuid: &str) -> Result<(), SocialDbError> {
+        self.conn
+            .execute("DELETE FROM timeline WHERE postUuid=?1", [post_uuid])
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    /// Empties every home timeline, used before rebuilding them from the user databases.
+    pub fn clear_timelines(&self) -> Result<(), SocialDbError> {
+        self.conn
+            .execute("DELETE FROM timeline", [])
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    /// Returns one page of post uuids of a home timeline, newest first, and the total count.
+    /// Posts of muted users stay on the timeline but are left out until they are unmuted.
+    pub fn get_timeline(
+        &self,
+        owner_uuid: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<String>, i64), SocialDbError> {
+        let total: i64 = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM timeline
+                        WHERE ownerUuid=?1 AND authorUuid NOT IN ({})",
+                    HIDDEN_USERS
+                ),
+                [owner_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT postUuid FROM timeline WHERE ownerUuid=?1 AND authorUuid NOT IN ({})
+                    ORDER BY createdAt DESC, postUuid DESC limit ?2 offset ?3",
+                HIDDEN_USERS
+            ))
+            .map_err(db_error)?;
+        let post_uuids = statement
+            .query_map(params![owner_uuid, limit, offset], |row| row.get(0))
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .map_err(db_error)?;
+
+        return Ok((post_uuids, total));
+    }
+
+    /// Records that a post answers another one. The link is kept when either post is deleted,
+    /// so the rest of the conversation stays connected.
+    pub fn add_reply(&self, reply: &ReplyLink) -> Result<(), SocialDbError> {
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO reply (postUuid, authorUuid, parentUuid, parentAuthorUuid, createdAt, public)
+                    VALUES (?1, ?2, ?3, ?4, ?5, 0)",
+                params![
+                    reply.post_uuid,
+                    reply.author_uuid,
+                    reply.parent_uuid,
+                    reply.parent_author_uuid,
+                    reply.created_at
+                ],
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    pub fn get_reply(&self, post_uuid: &str) -> Result<Option<ReplyLink>, SocialDbError> {
+        return self
+            .conn
+            .query_row(
+                "SELECT postUuid, authorUuid, parentUuid, parentAuthorUuid, createdAt
+                    FROM reply WHERE postUuid=?1",
+                [post_uuid],
+                |row| {
+                    Ok(ReplyLink {
+                        post_uuid: row.get(0)?,
+                        author_uuid: row.get(1)?,
+                        parent_uuid: row.get(2)?,
+                        parent_author_uuid: row.get(3)?,
+                        created_at: row.get(4)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(db_error);
+    }
+
+    /// Only public replies are counted, deleted replies are marked as not public.
+    pub fn set_reply_public(&self, post_uuid: &str, public: bool) -> Result<(), SocialDbError> {
+        self.conn
+            .execute(
+                "UPDATE reply SET public=?1 WHERE postUuid=?2",
+                params![public, post_uuid],
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    pub fn count_replies(&self, parent_uuid: &str) -> Result<i64, SocialDbError> {
+        return self
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM reply WHERE parentUuid=?1 AND public=1",
+                [parent_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error);
+    }
+
+    /// Uuids of the posts a reply answers, starting with its parent and ending with the post
+    /// which started the conversation. At most `limit` posts are returned.
+    pub fn get_ancestors(&self, post_uuid: &str, limit: u32) -> Result<Vec<String>, SocialDbError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "WITH RECURSIVE ancestor(postUuid, parentUuid, distance) AS (
+                    SELECT postUuid, parentUuid, 0 FROM reply WHERE postUuid=?1
+                    UNION ALL
+                    SELECT reply.postUuid, reply.parentUuid, ancestor.distance + 1
+                        FROM reply JOIN ancestor ON reply.postUuid = ancestor.parentUuid
+                        WHERE ancestor.distance < ?2
+                )
+                SELECT parentUuid FROM ancestor ORDER BY distance limit ?2",
+            )
+            .map_err(db_error)?;
+        let ancestors = statement
+            .query_map(params![post_uuid, limit], |row| row.get(0))
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .map_err(db_error)?;
+        return Ok(ancestors);
+    }
+
+    /// Every reply below a post down to `max_depth` levels, oldest first within a level.
+    /// Deleted and non public replies are included, they can still have public replies.
+    pub fn get_thread_replies(
+        &self,
+        post_uuid: &str,
+        max_depth: u32,
+        limit: u32,
+    ) -> Result<Vec<ThreadReply>, SocialDbError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "WITH RECURSIVE thread(postUuid, parentUuid, depth, createdAt) AS (
+                    SELECT postUuid, parentUuid, 1, createdAt FROM reply WHERE parentUuid=?1
+                    UNION ALL
+                    SELECT reply.postUuid, reply.parentUuid, thread.depth + 1, reply.createdAt
+                        FROM reply JOIN thread ON reply.parentUuid = thread.postUuid
+                        WHERE thread.depth < ?2
+                )
+                SELECT postUuid, parentUuid, depth FROM thread
+                    ORDER BY depth, createdAt, postUuid limit ?3",
+            )
+            .map_err(db_error)?;
+        let replies = statement
+            .query_map(params![post_uuid, max_depth, limit], |row| {
+                Ok(ThreadReply {
+                    post_uuid: row.get(0)?,
+                    parent_uuid: row.get(1)?,
+                    depth: row.get(2)?,
+                })
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+        return Ok(replies);
+    }
+
+    /// Liking a post twice keeps the first like, and returns `false` the second time.
+    pub fn like(
+        &self,
+        user_uuid: &str,
+        post_uuid: &str,
+        post_author_uuid: &str,
+    ) -> Result<bool, SocialDbError> {
+        let inserted = self
+            .conn
+            .execute(
+                "INSERT OR IGNORE INTO post_like (postUuid, userUuid, postAuthorUuid, createdAt)
+                    VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    post_uuid,
+                    user_uuid,
+                    post_author_uuid,
+                    Utc::now().timestamp()
+                ],
+            )
+            .map_err(db_error)?;
+        return Ok(inserted > 0);
+    }
+
+    /// Returns the author of the post when a like was removed, `None` when there was none.
+    pub fn unlike(
+        &self,
+        user_uuid: &str,
+        post_uuid: &str,
+    ) -> Result<Option<String>, SocialDbError> {
+        return self
+            .conn
+            .query_row(
+                "DELETE FROM post_like WHERE postUuid=?1 AND userUuid=?2 RETURNING postAuthorUuid",
+                params![post_uuid, user_uuid],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error);
+    }
+
+    /// Reposting a post again only replaces the quote, the repost keeps its place in time.
+    pub fn repost(
+        &self,
+        user_uuid: &str,
+        post_uuid: &str,
+        post_author_uuid: &str,
+        quote: Option<&str>,
+    ) -> Result<(), SocialDbError> {
+        self.conn
+            .execute(
+                "INSERT INTO repost (postUuid, userUuid, postAuthorUuid, quote, createdAt)
+                    VALUES (?1, ?2, ?3, ?4, ?5)
+                    ON CONFLICT (postUuid, userUuid) DO UPDATE SET quote=excluded.quote",
+                params![
+                    post_uuid,
+                    user_uuid,
+                    post_author_uuid,
+                    quote,
+                    Utc::now().timestamp()
+                ],
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    /// Returns the author of the post when a repost was removed, `None` when there was none.
+    pub fn unrepost(
+        &self,
+        user_uuid: &str,
+        post_uuid: &str,
+    ) -> Result<Option<String>, SocialDbError> {
+        return self
+            .conn
+            .query_row(
+                "DELETE FROM repost WHERE postUuid=?1 AND userUuid=?2 RETURNING postAuthorUuid",
+                params![post_uuid, user_uuid],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error);
+    }
+
+    pub fn bookmark(&self, user_uuid: &str, post_uuid: &str) -> Result<(), SocialDbError> {
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO bookmark (userUuid, postUuid, createdAt)
+                    VALUES (?1, ?2, ?3)",
+                params![user_uuid, post_uuid, Utc::now().timestamp()],
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    pub fn unbookmark(&self, user_uuid: &str, post_uuid: &str) -> Result<(), SocialDbError> {
+        self.conn
+            .execute(
+                "DELETE FROM bookmark WHERE userUuid=?1 AND postUuid=?2",
+                params![user_uuid, post_uuid],
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    /// Returns the number of likes and the number of reposts of a post.
+    pub fn engagement_counts(&self, post_uuid: &str) -> Result<(i64, i64), SocialDbError> {
+        return self
+            .conn
+            .query_row(
+                "SELECT
+                    (SELECT COUNT(*) FROM post_like WHERE postUuid=?1),
+                    (SELECT COUNT(*) FROM repost WHERE postUuid=?1)",
+                [post_uuid],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(db_error);
+    }
+
+    pub fn engagement_state(
+        &self,
+        user_uuid: &str,
+        post_uuid: &str,
+    ) -> Result<EngagementState, SocialDbError> {
+        return self
+            .conn
+            .query_row(
+                "SELECT
+                    EXISTS (SELECT 1 FROM post_like WHERE postUuid=?1 AND userUuid=?2),
+                    EXISTS (SELECT 1 FROM repost WHERE postUuid=?1 AND userUuid=?2),
+                    EXISTS (SELECT 1 FROM bookmark WHERE postUuid=?1 AND userUuid=?2)",
+                params![post_uuid, user_uuid],
+                |row| {
+                    Ok(EngagementState {
+                        liked: row.get(0)?,
+                        reposted: row.get(1)?,
+                        bookmarked: row.get(2)?,
+                    })
+                },
+            )
+            .map_err(db_error);
+    }
+
+    /// Users who liked a post, the most recent likes first.
+    pub fn get_likes(
+        &self,
+        post_uuid: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<LikeEntry>, SocialDbError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT userUuid, createdAt FROM post_like WHERE postUuid=?1
+                    ORDER BY createdAt DESC, userUuid limit ?2 offset ?3",
+            )
+            .map_err(db_error)?;
+        let entries = statement
+            .query_map(params![post_uuid, limit, offset], |row| {
+                Ok(LikeEntry {
+                    user_uuid: row.get(0)?,
+                    created_at: row.get(1)?,
+                })
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+        return Ok(entries);
+    }
+
+    /// Users who reposted a post, the most recent reposts first.
+    pub fn get_reposts(
+        &self,
+        post_uuid: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<RepostEntry>, SocialDbError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT userUuid, quote, createdAt FROM repost WHERE postUuid=?1
+                    ORDER BY createdAt DESC, userUuid limit ?2 offset ?3",
+            )
+            .map_err(db_error)?;
+        let entries = statement
+            .query_map(params![post_uuid, limit, offset], |row| {
+                Ok(RepostEntry {
+                    user_uuid: row.get(0)?,
+                    quote: row.get(1)?,
+                    created_at: row.get(2)?,
+                })
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+        return Ok(entries);
+    }
+
+    /// Returns one page of post uuids bookmarked by a user, the latest bookmark first, and
+    /// the total count.
+    pub fn get_bookmarks(
+        &self,
+        user_uuid: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<String>, i64), SocialDbError> {
+        let total: i64 = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM bookmark WHERE userUuid=?1",
+                [user_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT postUuid FROM bookmark WHERE userUuid=?1
+                    ORDER BY createdAt DESC, postUuid DESC limit ?2 offset ?3",
+            )
+            .map_err(db_error)?;
+        let post_uuids = statement
+            .query_map(params![user_uuid, limit, offset], |row| row.get(0))
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .map_err(db_error)?;
+
+        return Ok((post_uuids, total));
+    }
+
+    /// Drops the likes, reposts, bookmarks, mentions, notifications and poll votes of a
+    /// deleted post.
+    pub fn remove_engagement(&mut self, post_uuid: &str) -> Result<(), SocialDbError> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        tx.execute("DELETE FROM poll_vote WHERE postUuid=?1", [post_uuid])
+            .map_err(db_error)?;
+        tx.execute("DELETE FROM poll WHERE postUuid=?1", [post_uuid])
+            .map_err(db_error)?;
+        tx.execute("DELETE FROM post_like WHERE postUuid=?1", [post_uuid])
+            .map_err(db_error)?;
+        tx.execute("DELETE FROM repost WHERE postUuid=?1", [post_uuid])
+            .map_err(db_error)?;
+        tx.execute("DELETE FROM bookmark WHERE postUuid=?1", [post_uuid])
+            .map_err(db_error)?;
+        tx.execute("DELETE FROM mention WHERE postUuid=?1", [post_uuid])
+            .map_err(db_error)?;
+        tx.execute("DELETE FROM notification WHERE postUuid=?1", [post_uuid])
+            .map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+
+        return Ok(());
+    }
+
+    /// Records which user database holds a post with a poll, votes only name the post.
+    pub fn add_poll(&self, post_uuid: &str, author_uuid: &str) -> Result<(), SocialDbError> {
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO poll (postUuid, authorUuid) VALUES (?1, ?2)",
+                params![post_uuid, author_uuid],
+            )
+            .map_err(db_error)?;
+        return Ok(());
+    }
+
+    pub fn get_poll_author(&self, post_uuid: &str) -> Result<Option<String>, SocialDbError> {
+        return self
+            .conn
+            .query_row(
+                "SELECT authorUuid FROM poll WHERE postUuid=?1",
+                [post_uuid],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error);
+    }
+
+    /// Each user votes once, returns `false` when the user already voted.
+    pub fn vote(
+        &mut self,
+        user_uuid: &str,
+        post_uuid: &str,
+        choices: &[usize],
+    ) -> Result<bool, SocialDbError> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        let voted: bool = tx
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM poll_vote WHERE postUuid=?1 AND userUuid=?2)",
+                params![post_uuid, user_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+        if voted {
+            return Ok(false);
+        }
+
+        let now = Utc::now().timestamp();
+        for choice in choices {
+            tx.execute(
+                "INSERT OR IGNORE INTO poll_vote (postUuid, userUuid, choice, createdAt)
+                    VALUES (?1, ?2, ?3, ?4)",
+                params![post_uuid, user_uuid, choice, now],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        return Ok(true);
+    }
+
+    /// The votes per option of a poll with `option_count` options, and the number of users
+    /// who voted.
+    pub fn poll_results(
+        &self,
+        post_uuid: &str,
+        option_count: usize,
+    ) -> Result<(Vec<i64>, i64), SocialDbError> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT choice, COUNT(*) FROM poll_vote WHERE postUuid=?1 GROUP BY choice")
+            .map_err(db_error)?;
+        let mut votes = vec![0; option_count];
+        let rows = statement
+            .query_map([post_uuid], |row| {
+                Ok((row.get::<_, usize>(0)?, row.get::<_, i64>(1)?))
+            })
+            .map_err(db_error)?;
+        for row in rows {
+            let (choice, count) = row.map_err(db_error)?;
+            if let Some(option_votes) = votes.get_mut(choice) {
+                *option_votes = count;
+            }
+        }
+
+        let voter_count = self
+            .conn
+            .query_row(
+                "SELECT COUNT(DISTINCT userUuid) FROM poll_vote WHERE postUuid=?1",
+                [post_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+        return Ok((votes, voter_count));
+    }
+
+    /// The options the user picked, empty when the user didn't vote.
+    pub fn poll_choices(
+        &self,
+        user_uuid: &str,
+        post_uuid: &str,
+    ) -> Result<Vec<usize>, SocialDbError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT choice FROM poll_vote WHERE postUuid=?1 AND userUuid=?2 ORDER BY choice",
+            )
+            .map_err(db_error)?;
+        let choices = statement
+            .query_map(params![post_uuid, user_uuid], |row| row.get(0))
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<usize>>>()
+            .map_err(db_error)?;
+        return Ok(choices);
+    }
+
+    /// Replaces the users mentioned in a post and returns the ones which weren't mentioned
+    /// before, so editing a post only notifies newly mentioned users.
+    pub fn set_mentions(
+        &mut self,
+        post_uuid: &str,
+        user_uuids: &[String],
+    ) -> Result<Vec<String>, SocialDbError> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        let mut added: Vec<String> = Vec::new();
+        for user_uuid in user_uuids {
+            let inserted = tx
+                .execute(
+                    "INSERT OR IGNORE INTO mention (postUuid, userUuid) VALUES (?1, ?2)",
+                    params![post_uuid, user_uuid],
+                )
+                .map_err(db_error)?;
+            if inserted > 0 {
+                added.push(user_uuid.clone());
+            }
+        }
+        let placeholders = vec!["?"; user_uuids.len()].join(", ");
+        let mut values: Vec<&str> = vec![post_uuid];
+        values.extend(user_uuids.iter().map(String::as_str));
+        tx.execute(
+            &format!(
+                "DELETE FROM mention WHERE postUuid=?1 AND userUuid NOT IN ({})",
+                placeholders
+            ),
+            params_from_iter(values.iter()),
+        )
+        .map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+
+        return Ok(added);
+    }
+
+    /// The same event notifies a recipient only once, e.g. liking a post again after
+    /// unliking it doesn't send a second notification. Returns the new notification,
+    /// `None` when the recipient was notified before.
+    pub fn add_notification(
+        &self,
+        recipient_uuid: &str,
+        kind: NotificationKind,
+        actor_uuid: &str,
+        post_uuid: Option<&str>,
+    ) -> Result<Option<Notification>, SocialDbError> {
+        let created_at = Utc::now().timestamp();
+        let inserted = self
+            .conn
+            .execute(
+                "INSERT OR IGNORE INTO notification (recipientUuid, kind, actorUuid, postUuid, createdAt)
+                    VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    recipient_uuid,
+                    kind.as_str(),
+                    actor_uuid,
+                    post_uuid,
+                    created_at
+                ],
+            )
+            .map_err(db_error)?;
+        if inserted == 0 {
+            return Ok(None);
+        }
+
+        return Ok(Some(Notification {
+            id: self.conn.last_insert_rowid(),
+            kind,
+            actor_uuid: actor_uuid.to_owned(),
+            post_uuid: post_uuid.map(str::to_owned),
+            created_at,
+            read: false,
+            message: None,
+        }));
+    }
+
+    /// Moderation notices aren't deduplicated, every action taken is worth a notice.
+    pub fn add_moderation_notice(
+        &self,
+        recipient_uuid: &str,
+        moderator_uuid: &str,
+        post_uuid: Option<&str>,
+        message: &str,
+    ) -> Result<Notification, SocialDbError> {
+        let created_at = Utc::now().timestamp();
+        self.conn
+            .execute(
+                "INSERT INTO notification (recipientUuid, kind, actorUuid, postUuid, createdAt, message)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    recipient_uuid,
+                    NotificationKind::Moderation.as_str(),
+                    moderator_uuid,
+                    post_uuid,
+                    created_at,
+                    message
+                ],
+            )
+            .map_err(db_error)?;
+
+        return Ok(Notification {
+            id: self.conn.last_insert_rowid(),
+            kind: NotificationKind::Moderation,
+            actor_uuid: moderator_uuid.to_owned(),
+            post_uuid: post_uuid.map(str::to_owned),
+            created_at,
+            read: false,
+            message: Some(message.to_owned()),
+        });
+    }
+
+    /// Returns one page of notifications, newest first, and the total count. Notifications
+    /// caused by blocked or muted users are left out, moderation notices always show.
+    pub fn get_notifications(
+        &self,
+        recipient_uuid: &str,
+        unread_only: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<Notification>, i64), SocialDbError> {
+        let read_filter = if unread_only { "AND read=0" } else { "" };
+        let total: i64 = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM notification
+                        WHERE recipientUuid=?1 {}
+                            AND (kind = 'moderation' OR actorUuid NOT IN ({}))",
+                    read_filter, HIDDEN_USERS
+                ),
+                [recipient_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT id, kind, actorUuid, postUuid, createdAt, read, message FROM notification
+                    WHERE recipientUuid=?1 {}
+                        AND (kind = 'moderation' OR actorUuid NOT IN ({}))
+                    ORDER BY id DESC limit ?2 offset ?3",
+                read_filter, HIDDEN_USERS
+            ))
+            .map_err(db_error)?;
+        let notifications = statement
+            .query_map(params![recipient_uuid, limit, offset], |row| {
+                Ok(Notification {
+                    id: row.get(0)?,
+                    kind: NotificationKind::from_db(&row.get::<_, String>(1)?),
+                    actor_uuid: row.get(2)?,
+                    post_uuid: row.get(3)?,
+                    created_at: row.get(4)?,
+                    read: row.get(5)?,
+                    message: row.get(6)?,
+                })
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok((notifications, total));
+    }
+
+    pub fn count_unread_notifications(&self, recipient_uuid: &str) -> Result<i64, SocialDbError> {
+        return self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM notification
+                        WHERE recipientUuid=?1 AND read=0
+                            AND (kind = 'moderation' OR actorUuid NOT IN ({}))",
+                    HIDDEN_USERS
+                ),
+                [recipient_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error);
+    }
+
+    /// Marks the given notifications of the recipient as read, or all of them when
+    /// `notification_ids` is `None`.
+    pub fn mark_notifications_read(
+        &mut self,
+        recipient_uuid: &str,
+        notification_ids: Option<&[i64]>,
+    ) -> Result<(), SocialDbError> {
+        let Some(notification_ids) = notification_ids else {
+            self.conn
+                .execute(
+                    "UPDATE notification SET read=1 WHERE recipientUuid=?1 AND read=0",
+                    [recipient_uuid],
+                )
+                .map_err(db_error)?;
+            return Ok(());
+        };
+
+        let tx = self.conn.transaction().map_err(db_error)?;
+        for notification_id in notification_ids {
+            tx.execute(
+                "UPDATE notification SET read=1 WHERE recipientUuid=?1 AND id=?2",
+                params![recipient_uuid, notification_id],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        return Ok(());
+    }
+
+    /// Appends an event to the log of every recipient and drops events past the retention.
+    pub fn add_stream_events(
+        &mut self,
+        recipient_uuids: &[String],
+        kind: StreamEventKind,
+        data: &str,
+    ) -> Result<(), SocialDbError> {
+        let now = Utc::now().timestamp();
+        let tx = self.conn.transaction().map_err(db_error)?;
+        for recipient_uuid in recipient_uuids {
+            tx.execute(
+                "INSERT INTO stream_event (recipientUuid, kind, data, createdAt)
+                    VALUES (?1, ?2, ?3, ?4)",
+                params![recipient_uuid, kind.as_str(), data, now],
+            )
+            .map_err(db_error)?;
+        }
+        tx.execute(
+            "DELETE FROM stream_event WHERE createdAt < ?1",
+            [now - STREAM_EVENT_RETENTION_SECONDS],
+        )
+        .map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+
+        return Ok(());
+    }
+
+    /// Events of a recipient which came after `after_id`, oldest first.
+    pub fn get_stream_events(
+        &self,
+        recipient_uuid: &str,
+        after_id: i64,
+        limit: u32,
+    ) -> Result<Vec<StreamEvent>, SocialDbError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT id, kind, data FROM stream_event
+                    WHERE recipientUuid=?1 AND id > ?2
+                    ORDER BY id limit ?3",
+            )
+            .map_err(db_error)?;
+        let events = statement
+            .query_map(params![recipient_uuid, after_id, limit], |row| {
+                Ok(StreamEvent {
+                    id: row.get(0)?,
+                    kind: StreamEventKind::from_db(&row.get::<_, String>(1)?),
+                    data: row.get(2)?,
+                })
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+        return Ok(events);
+    }
+
+    /// Id of the latest event of a recipient, 0 when there is none.
+    pub fn last_stream_event_id(&self, recipient_uuid: &str) -> Result<i64, SocialDbError> {
+        return self
+            .conn
+            .query_row(
+                "SELECT IFNULL(MAX(id), 0) FROM stream_event WHERE recipientUuid=?1",
+                [recipient_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error);
+    }
+}
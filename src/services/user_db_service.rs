@@ -1,6 +1,9 @@
 use std::{fs, io};
 
-use rusqlite::Connection;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::db_migrations::migrate;
 
 #[derive(Debug)]
 pub enum UserDbError {
@@ -15,6 +18,7 @@ pub struct UserDbService {
     conn: Connection,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct User {
     pub uuid: String,
@@ -22,6 +26,59 @@ pub struct User {
     pub email: String,
 }
 
+/// What a user tells about themselves on their public profile.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub bio: String,
+    /// uuid of an image the user uploaded
+    pub avatar_uuid: Option<String>,
+    pub location: Option<String>,
+    pub pronouns: Option<String>,
+    /// in display order
+    pub links: Vec<ProfileLink>,
+    /// unknown for accounts registered before it was recorded
+    pub joined_at: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfileLink {
+    pub label: String,
+    pub url: String,
+}
+
+const MIGRATIONS: &[&str] = &[
+    // databases created before migrations were tracked already have this table
+    "CREATE TABLE IF NOT EXISTS user (
+        id               INTEGER PRIMARY KEY,
+        email            TEXT NOT NULL UNIQUE,
+        password         TEXT NOT NULL,
+        displayName      TEXT NOT NULL UNIQUE,
+        uuid             TEXT NOT NULL UNIQUE,
+        emailVerified    INTEGER NOT NULL
+    );",
+    "ALTER TABLE user ADD COLUMN joinedAt INTEGER;
+    CREATE TABLE profile (
+        userUuid    TEXT PRIMARY KEY,
+        bio         TEXT NOT NULL,
+        avatarUuid  TEXT,
+        location    TEXT,
+        pronouns    TEXT
+    ) WITHOUT ROWID;
+    CREATE INDEX profile_avatar ON profile(avatarUuid);
+    CREATE TABLE profile_link (
+        userUuid    TEXT NOT NULL,
+        position    INTEGER NOT NULL,
+        label       TEXT NOT NULL,
+        url         TEXT NOT NULL,
+        PRIMARY KEY (userUuid, position)
+    ) WITHOUT ROWID;",
+];
+
+fn db_error(err: rusqlite::Error) -> UserDbError {
+    log::error!("{:?}", err);
+    UserDbError::GenericError
+}
+
 fn create_directory_if_not_exists(path: &str) -> io::Result<()> {
     if !std::path::Path::new(path).exists() {
         fs::create_dir_all(path)?;
@@ -31,34 +88,14 @@ fn create_directory_if_not_exists(path: &str) -> io::Result<()> {
 
 impl UserDbService {
     pub fn connect(db_collected_root_dir: &str) -> Result<Self, UserDbError> {
-        create_directory_if_not_exists(&db_collected_root_dir)
+        create_directory_if_not_exists(db_collected_root_dir)
             .map_err(|_| UserDbError::GenericError)?;
 
-        match Connection::open(format!("{}/users.db", db_collected_root_dir)) {
-            Ok(conn) => {
-                match conn.execute(
-                    "CREATE TABLE IF NOT EXISTS user (
-                        id               INTEGER PRIMARY KEY,
-                        email            TEXT NOT NULL UNIQUE,
-                        password         TEXT NOT NULL,
-                        displayName      TEXT NOT NULL UNIQUE,
-                        uuid             TEXT NOT NULL UNIQUE,
-                        emailVerified    INTEGER NOT NULL
-                    )",
-                    (), // empty list of parameters.
-                ) {
-                    Ok(_) => {
-                        return Ok(Self { conn });
-                    }
-                    Err(_) => {
-                        return Err(UserDbError::GenericError);
-                    }
-                };
-            }
-            Err(_) => {
-                return Err(UserDbError::GenericError);
-            }
-        };
+        let mut conn =
+            Connection::open(format!("{}/users.db", db_collected_root_dir)).map_err(db_error)?;
+        migrate(&mut conn, MIGRATIONS).map_err(db_error)?;
+
+        return Ok(Self { conn });
     }
 
     pub fn add_user(
@@ -69,8 +106,9 @@ impl UserDbService {
         uuid: &str,
     ) -> Result<(), UserDbError> {
         match self.conn.execute(
-            "INSERT INTO user (email, password, displayName, uuid, emailVerified) VALUES (?1, ?2, ?3, ?4, 0)",
-            (email, password, display_name, uuid),
+            "INSERT INTO user (email, password, displayName, uuid, emailVerified, joinedAt)
+                VALUES (?1, ?2, ?3, ?4, 0, ?5)",
+            (email, password, display_name, uuid, Utc::now().timestamp()),
         ) {
             Ok(_) => {
                 return Ok(());
@@ -113,10 +151,8 @@ impl UserDbService {
             }) {
                 let password_vec: Vec<_> = user_iter.collect();
 
-                if let Some(selected_password) = password_vec.get(0) {
-                    if let Ok(password) = selected_password {
-                        return Ok(password.clone());
-                    }
+                if let Some(Ok(password)) = password_vec.first() {
+                    return Ok(password.clone());
                 }
             }
         }
@@ -138,14 +174,143 @@ impl UserDbService {
             }) {
                 let user_vec: Vec<_> = user_iter.collect();
 
-                if let Some(selected_user) = user_vec.get(0) {
-                    if let Ok(user) = selected_user {
-                        return Ok(user.clone());
-                    }
+                if let Some(Ok(user)) = user_vec.first() {
+                    return Ok(user.clone());
+                }
+            }
+        }
+
+        return Err(UserDbError::UserNotFound);
+    }
+
+    pub fn get_user_from_uuid(&self, uuid: &str) -> Result<User, UserDbError> {
+        if let Ok(mut statement) = self
+            .conn
+            .prepare("SELECT displayName, email FROM user WHERE uuid=:uuid limit 1;")
+        {
+            if let Ok(user_iter) = statement.query_map(&[(":uuid", uuid)], |row| {
+                Ok(User {
+                    uuid: uuid.to_owned(),
+                    display_name: row.get(0)?,
+                    email: row.get(1)?,
+                })
+            }) {
+                let user_vec: Vec<_> = user_iter.collect();
+
+                if let Some(Ok(user)) = user_vec.first() {
+                    return Ok(user.clone());
+                }
+            }
+        }
+
+        return Err(UserDbError::UserNotFound);
+    }
+
+    pub fn get_user_from_display_name(&self, display_name: &str) -> Result<User, UserDbError> {
+        if let Ok(mut statement) = self.conn.prepare(
+            "SELECT uuid, displayName, email FROM user WHERE displayName=:displayName limit 1;",
+        ) {
+            if let Ok(user_iter) = statement.query_map(&[(":displayName", display_name)], |row| {
+                Ok(User {
+                    uuid: row.get(0)?,
+                    display_name: row.get(1)?,
+                    email: row.get(2)?,
+                })
+            }) {
+                let user_vec: Vec<_> = user_iter.collect();
+
+                if let Some(Ok(user)) = user_vec.first() {
+                    return Ok(user.clone());
                 }
             }
         }
 
         return Err(UserDbError::UserNotFound);
     }
+
+    /// The profile of a user, empty when they never filled it in.
+    pub fn get_profile(&self, uuid: &str) -> Result<Profile, UserDbError> {
+        let mut profile = self
+            .conn
+            .query_row(
+                "SELECT profile.bio, profile.avatarUuid, profile.location, profile.pronouns,
+                    user.joinedAt
+                    FROM user LEFT JOIN profile ON profile.userUuid = user.uuid
+                    WHERE user.uuid=?1",
+                params![uuid],
+                |row| {
+                    Ok(Profile {
+                        bio: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                        avatar_uuid: row.get(1)?,
+                        location: row.get(2)?,
+                        pronouns: row.get(3)?,
+                        links: Vec::new(),
+                        joined_at: row.get(4)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(db_error)?
+            .ok_or(UserDbError::UserNotFound)?;
+
+        let mut statement = self
+            .conn
+            .prepare("SELECT label, url FROM profile_link WHERE userUuid=?1 ORDER BY position")
+            .map_err(db_error)?;
+        profile.links = statement
+            .query_map(params![uuid], |row| {
+                Ok(ProfileLink {
+                    label: row.get(0)?,
+                    url: row.get(1)?,
+                })
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        return Ok(profile);
+    }
+
+    /// Replaces the profile of a user, `joined_at` can't be changed.
+    pub fn update_profile(&mut self, uuid: &str, profile: &Profile) -> Result<(), UserDbError> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        tx.execute(
+            "INSERT INTO profile (userUuid, bio, avatarUuid, location, pronouns)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                ON CONFLICT(userUuid) DO UPDATE SET bio=excluded.bio,
+                    avatarUuid=excluded.avatarUuid, location=excluded.location,
+                    pronouns=excluded.pronouns",
+            params![
+                uuid,
+                profile.bio,
+                profile.avatar_uuid,
+                profile.location,
+                profile.pronouns
+            ],
+        )
+        .map_err(db_error)?;
+        tx.execute("DELETE FROM profile_link WHERE userUuid=?1", params![uuid])
+            .map_err(db_error)?;
+        for (position, link) in profile.links.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO profile_link (userUuid, position, label, url) VALUES (?1, ?2, ?3, ?4)",
+                params![uuid, position, link.label, link.url],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        return Ok(());
+    }
+
+    /// Whether some user shows the attachment as their avatar.
+    pub fn is_avatar(&self, attachment_uuid: &str) -> Result<bool, UserDbError> {
+        self.conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM profile WHERE avatarUuid=?1)",
+                params![attachment_uuid],
+                |row| row.get(0),
+            )
+            .map_err(db_error)
+    }
 }